@@ -20,6 +20,8 @@ use super::trace::{HandlerRegister, Trace};
 pub struct ConcreteTrace<'a> {
     /// Thread-safe collection of registered trace handlers
     handlers: Arc<Mutex<Vec<Box<dyn TraceHandler + 'a>>>>,
+    /// Messages below this level are discarded
+    min_level: TraceLevel,
 }
 
 impl<'a> ConcreteTrace<'a> {
@@ -40,8 +42,30 @@ impl<'a> ConcreteTrace<'a> {
     pub fn new() -> Self {
         Self {
             handlers: Arc::new(Mutex::new(Vec::new())),
+            min_level: TraceLevel::Verbose,
         }
     }
+
+    /// Discards the messages below `level` (Builder pattern).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use traces::trace::{ConcreteTrace, Trace, TraceLevel};
+    ///
+    /// let trace = ConcreteTrace::new().with_min_level(TraceLevel::Warning);
+    /// assert!(!trace.enabled(TraceLevel::Info));
+    /// ```
+    pub fn with_min_level(mut self, level: TraceLevel) -> Self {
+        self.min_level = level;
+        self
+    }
+}
+
+impl<'a> Default for ConcreteTrace<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> HandlerRegister<'a> for ConcreteTrace<'a> {
@@ -54,10 +78,17 @@ impl<'a> HandlerRegister<'a> for ConcreteTrace<'a> {
 
 impl<'a> Trace for ConcreteTrace<'a> {
     fn log(&self, level: TraceLevel, message: &str) {
+        if !self.enabled(level) {
+            return;
+        }
         // Call all registered handlers
         let handlers = self.handlers.lock().unwrap();
         for handler in handlers.iter() {
             handler.log(level, message);
         }
     }
+
+    fn enabled(&self, level: TraceLevel) -> bool {
+        level >= self.min_level
+    }
 }
@@ -9,7 +9,10 @@ use std::fmt::Display;
 /// - Copy is more performant: no dereferencing, direct access to value
 /// - Copy is more idiomatic in Rust for primitive/simple types
 /// - Simplifies code: no & everywhere, no lifetime management
-#[derive(Debug, Copy, Clone)]
+///
+/// Levels are ordered by severity (`Verbose < ... < Critical < None`), so a
+/// minimum level can be expressed with a simple comparison.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TraceLevel {
     /// Verbose logging - most detailed
     Verbose,
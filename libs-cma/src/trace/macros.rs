@@ -0,0 +1,193 @@
+use std::fmt::{self, Display, Write};
+
+/// Builds the message logged by the logging macros.
+///
+/// The message is prefixed with the call site and followed by the fields,
+/// if any:
+///
+/// ```text
+/// app::db (src/db.rs:42) - connection lost {host=db1 retry=3}
+/// ```
+///
+/// Field values containing spaces, quotes or `=` are quoted.
+pub fn format_message(
+    module: &str,
+    file: &str,
+    line: u32,
+    message: fmt::Arguments<'_>,
+    fields: &[(&str, &dyn Display)],
+) -> String {
+    let mut text = format!("{} ({}:{}) - {}", module, file, line, message);
+    if !fields.is_empty() {
+        text.push_str(" {");
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                text.push(' ');
+            }
+            let value = value.to_string();
+            if value.is_empty() || value.contains([' ', '"', '=']) {
+                let _ = write!(text, "{}={:?}", key, value);
+            } else {
+                let _ = write!(text, "{}={}", key, value);
+            }
+        }
+        text.push('}');
+    }
+    text
+}
+
+/// Logs a message at the given level, with the call site and optional
+/// fields.
+///
+/// ```text
+/// trace_log!(trace, level, "format {}", args...; key = value, ...)
+/// ```
+///
+/// The message is built by [`format_message`](crate::trace::format_message)
+/// from `module_path!`, `file!`, `line!`, the formatted message and the
+/// fields after `;`, whose values are formatted with `Display`. When
+/// [`Trace::enabled`](crate::trace::Trace::enabled) rejects the level,
+/// neither the message nor the field values are formatted or even
+/// evaluated.
+///
+/// The per-level macros ([`trace_info!`](crate::trace_info),
+/// [`trace_error!`](crate::trace_error), ...) are shorthands for this one.
+///
+/// # Examples
+///
+/// ```
+/// use traces::trace::{ConcreteTrace, HandlerRegister, PrintTraceHandler, TraceLevel};
+/// use traces::{trace_debug, trace_log};
+///
+/// let trace = ConcreteTrace::new().with_min_level(TraceLevel::Info);
+/// trace.register(PrintTraceHandler::new());
+///
+/// let host = "db1";
+/// trace_log!(trace, TraceLevel::Error, "connection to {host} lost"; retry = 3);
+/// // Output: [ERROR] - my_app (src/main.rs:9) - connection to db1 lost {retry=3}
+///
+/// trace_debug!(trace, "not formatted: {}", host);
+/// ```
+#[macro_export]
+macro_rules! trace_log {
+    ($trace:expr, $level:expr, $fmt:literal $(, $arg:expr)* $(,)? $(; $($key:ident = $value:expr),+ $(,)?)?) => {{
+        #[allow(unused_imports)]
+        use $crate::trace::Trace as _;
+        let trace = &$trace;
+        let level: $crate::trace::TraceLevel = $level;
+        if trace.enabled(level) {
+            let message = $crate::trace::format_message(
+                ::std::module_path!(),
+                ::std::file!(),
+                ::std::line!(),
+                ::std::format_args!($fmt $(, $arg)*),
+                &[$($((::std::stringify!($key), &$value as &dyn ::std::fmt::Display)),+)?],
+            );
+            trace.log(level, &message);
+        }
+    }};
+}
+
+/// Logs a Verbose message, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_verbose {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Verbose, $($rest)+)
+    };
+}
+
+/// Logs a Debug message, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_debug {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Debug, $($rest)+)
+    };
+}
+
+/// Logs an Info message, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_info {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Info, $($rest)+)
+    };
+}
+
+/// Logs a Warning message, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_warning {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Warning, $($rest)+)
+    };
+}
+
+/// Logs an Error message, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_error {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Error, $($rest)+)
+    };
+}
+
+/// Logs a Critical message, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_critical {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Critical, $($rest)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trace::{ConcreteTrace, HandlerRegister, Trace, TraceHandler, TraceLevel};
+    use std::cell::RefCell;
+    use std::fmt;
+    use std::rc::Rc;
+
+    /// Keeps the messages it receives.
+    struct Collect(Rc<RefCell<Vec<String>>>);
+    impl Trace for Collect {
+        fn log(&self, level: TraceLevel, message: &str) {
+            self.0.borrow_mut().push(format!("{} {}", level, message));
+        }
+    }
+    impl TraceHandler for Collect {}
+
+    /// Panics when formatted.
+    struct Expensive;
+    impl fmt::Display for Expensive {
+        fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            panic!("formatted a filtered message");
+        }
+    }
+
+    #[test]
+    fn test_macros() {
+        let trace = ConcreteTrace::new().with_min_level(TraceLevel::Info);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        trace.register(Collect(seen.clone()));
+
+        let line = line!() + 1;
+        crate::trace_warning!(trace, "disk {}% full", 93; mount = "/var", owner = "log daemon");
+        crate::trace_info!(&trace, "plain",);
+
+        // Filtered out: nothing is formatted nor evaluated
+        let mut evaluated = false;
+        crate::trace_debug!(trace, "{}", Expensive; value = {
+            evaluated = true;
+            Expensive
+        });
+        assert!(!evaluated);
+
+        let at = |line| format!("{} ({}:{})", module_path!(), file!(), line);
+        assert_eq!(
+            *seen.borrow(),
+            [
+                format!(
+                    "[WARNING] {} - disk 93% full {{mount=/var owner=\"log daemon\"}}",
+                    at(line)
+                ),
+                format!("[INFO] {} - plain", at(line + 1)),
+            ]
+        );
+    }
+}
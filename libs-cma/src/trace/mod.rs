@@ -2,19 +2,21 @@ mod concrete_trace;
 mod file_trace_handlers;
 mod handlers;
 mod level;
+mod macros;
 mod print_trace_handlers;
 #[allow(clippy::module_inception)]
 mod trace;
 
 use std::io::Error;
 
-use concrete_trace::ConcreteTrace;
 use file_trace_handlers::FileTraceHanlder;
-use print_trace_handlers::PrintTraceHandler;
-use trace::HandlerRegister;
 
+pub use concrete_trace::ConcreteTrace;
+pub use handlers::TraceHandler;
 pub use level::TraceLevel;
-pub use trace::Trace;
+pub use macros::format_message;
+pub use print_trace_handlers::PrintTraceHandler;
+pub use trace::{HandlerRegister, Trace};
 
 /// Creates a preconfigured trace instance with common handlers.
 ///
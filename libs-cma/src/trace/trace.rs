@@ -23,6 +23,15 @@ pub trait Trace {
     /// trace.log(TraceLevel::Error, "Failed to connect to database");
     /// ```
     fn log(&self, level: TraceLevel, message: &str);
+
+    /// Returns `false` if messages of `level` would be discarded.
+    ///
+    /// The logging macros such as [`trace_info!`](crate::trace_info) check it
+    /// first and skip formatting the message when it returns `false`. The
+    /// default implementation keeps every level.
+    fn enabled(&self, _level: TraceLevel) -> bool {
+        true
+    }
 }
 
 /// Trait for types that can register trace handlers.
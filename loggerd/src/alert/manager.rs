@@ -0,0 +1,496 @@
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::notifier::{AlertEvent, AlertNotification, Notifier};
+use super::{AlertConfig, AlertRuleConfig};
+use crate::metrics::MetricsRegistry;
+use crate::trace::stage::TraceStage;
+use crate::trace::{TraceLevel, TraceRecord, lock};
+
+/// Source of the records announcing alert state changes.
+pub const ALERT_SOURCE: &str = "alerts";
+
+/// State of an alert rule, as reported by `/alerts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    /// The threshold is not reached
+    Ok,
+    /// The threshold was reached and the rule has not resolved yet
+    Firing,
+}
+
+/// Current state of an alert rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertStatus {
+    /// Name of the rule
+    pub name: String,
+    /// Ok or firing
+    pub state: AlertState,
+    /// When the alert started firing
+    pub since: Option<DateTime<Local>>,
+    /// Matching records since the alert started firing
+    pub count: u64,
+    /// Matching records needed to fire
+    pub threshold: u32,
+    /// Window of the rule
+    pub window_secs: u64,
+    /// When the last notification was sent
+    pub last_notification: Option<DateTime<Local>>,
+    /// Last record that matched the rule
+    pub last_record: Option<TraceRecord>,
+}
+
+/// A state change of a rule.
+struct Transition {
+    notification: AlertNotification,
+    /// False when the notification is suppressed by the cooldown
+    send: bool,
+}
+
+impl Transition {
+    /// Builds the record logging the transition.
+    fn record(&self) -> TraceRecord {
+        let (level, suffix) = match (self.notification.status, self.send) {
+            (AlertEvent::Firing, true) => (TraceLevel::Error, ""),
+            (AlertEvent::Firing, false) => (TraceLevel::Error, " (cooldown, not notified)"),
+            (AlertEvent::Resolved, _) => (TraceLevel::Info, ""),
+        };
+        let message = format!("{}{}", self.notification.summary, suffix);
+        TraceRecord::new(level, ALERT_SOURCE, &message)
+    }
+}
+
+/// Current firing episode of a rule.
+struct Firing {
+    since: DateTime<Local>,
+    count: u64,
+    /// Whether the firing notification was sent
+    notified: bool,
+}
+
+/// A compiled rule and its state.
+struct Rule {
+    config: AlertRuleConfig,
+    pattern: Option<Regex>,
+    window: Duration,
+    cooldown: Duration,
+    /// Times of the latest matches, at most `threshold` of them
+    hits: VecDeque<Instant>,
+    last_match: Option<Instant>,
+    last_record: Option<TraceRecord>,
+    firing: Option<Firing>,
+    /// Last firing notification, for the cooldown
+    last_notified: Option<Instant>,
+    last_notification: Option<DateTime<Local>>,
+    /// `alert_firing{alert=...}`
+    firing_gauge: Arc<AtomicU64>,
+    metrics: Arc<MetricsRegistry>,
+}
+
+impl Rule {
+    fn compile(config: &AlertRuleConfig, metrics: &Arc<MetricsRegistry>) -> Result<Self, Error> {
+        let invalid = |message: String| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid alert rule '{}': {}", config.name, message),
+            )
+        };
+
+        if config.threshold == 0 {
+            return Err(invalid("threshold must be at least 1".to_string()));
+        }
+        if config.window_secs == 0 {
+            return Err(invalid("window_secs must be at least 1".to_string()));
+        }
+        let pattern = config
+            .pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| invalid(e.to_string()))?;
+
+        Ok(Self {
+            config: config.clone(),
+            pattern,
+            window: Duration::from_secs(config.window_secs),
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            hits: VecDeque::new(),
+            last_match: None,
+            last_record: None,
+            firing: None,
+            last_notified: None,
+            last_notification: None,
+            firing_gauge: metrics.gauge("alert_firing", &[("alert", &config.name)]),
+            metrics: metrics.clone(),
+        })
+    }
+
+    /// Returns `true` if the record satisfies every criterion of the rule.
+    fn matches(&self, record: &TraceRecord) -> bool {
+        (self.config.sources.is_empty() || self.config.sources.contains(&record.source))
+            && self
+                .config
+                .min_level
+                .is_none_or(|level| record.level >= level)
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&record.message))
+    }
+
+    /// Accounts for a record; returns the transition if the rule starts firing.
+    fn observe(&mut self, record: &TraceRecord, now: Instant) -> Option<Transition> {
+        if !self.matches(record) {
+            return None;
+        }
+        self.last_match = Some(now);
+        self.last_record = Some(record.clone());
+
+        // Deduplication: an incident notifies once
+        if let Some(firing) = &mut self.firing {
+            firing.count += 1;
+            return None;
+        }
+
+        self.hits.push_back(now);
+        while self
+            .hits
+            .front()
+            .is_some_and(|&hit| now.saturating_duration_since(hit) > self.window)
+            || self.hits.len() > self.config.threshold as usize
+        {
+            self.hits.pop_front();
+        }
+        if self.hits.len() < self.config.threshold as usize {
+            return None;
+        }
+
+        self.hits.clear();
+        let send = self
+            .last_notified
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.cooldown);
+        if send {
+            self.last_notified = Some(now);
+            self.last_notification = Some(Local::now());
+        }
+        self.firing = Some(Firing {
+            since: Local::now(),
+            count: self.config.threshold as u64,
+            notified: send,
+        });
+        self.firing_gauge.store(1, Ordering::Relaxed);
+
+        let summary = format!(
+            "alert '{}' firing: {} matching records within {}s",
+            self.config.name, self.config.threshold, self.config.window_secs
+        );
+        Some(self.transition(AlertEvent::Firing, summary, send))
+    }
+
+    /// Resolves the alert once nothing matched for a whole window.
+    fn tick(&mut self, now: Instant) -> Option<Transition> {
+        let firing = self.firing.as_ref()?;
+        let quiet = self
+            .last_match
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.window);
+        if !quiet {
+            return None;
+        }
+
+        let summary = format!(
+            "alert '{}' resolved after {} matching records",
+            self.config.name, firing.count
+        );
+        let send = firing.notified;
+        let transition = self.transition(AlertEvent::Resolved, summary, send);
+        self.firing = None;
+        self.firing_gauge.store(0, Ordering::Relaxed);
+        Some(transition)
+    }
+
+    fn transition(&self, status: AlertEvent, summary: String, send: bool) -> Transition {
+        let firing = self.firing.as_ref().expect("transition of a firing alert");
+        let status_label = match status {
+            AlertEvent::Firing => "firing",
+            AlertEvent::Resolved => "resolved",
+        };
+        if send {
+            self.metrics
+                .counter(
+                    "alert_notifications_total",
+                    &[("alert", &self.config.name), ("status", status_label)],
+                )
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        Transition {
+            notification: AlertNotification {
+                alert: self.config.name.clone(),
+                status,
+                summary,
+                threshold: self.config.threshold,
+                window_secs: self.config.window_secs,
+                count: firing.count,
+                started_at: firing.since,
+                resolved_at: (status == AlertEvent::Resolved).then(Local::now),
+                last_record: self.last_record.clone(),
+            },
+            send,
+        }
+    }
+
+    fn status(&self) -> AlertStatus {
+        AlertStatus {
+            name: self.config.name.clone(),
+            state: match self.firing {
+                Some(_) => AlertState::Firing,
+                None => AlertState::Ok,
+            },
+            since: self.firing.as_ref().map(|firing| firing.since),
+            count: self.firing.as_ref().map_or(0, |firing| firing.count),
+            threshold: self.config.threshold,
+            window_secs: self.config.window_secs,
+            last_notification: self.last_notification,
+            last_record: self.last_record.clone(),
+        }
+    }
+}
+
+/// Shared part of an [`AlertManager`].
+struct Alerts {
+    rules: Mutex<Vec<Rule>>,
+    notifier: Notifier,
+}
+
+/// Alert rules evaluated on every record, with webhook notifications.
+///
+/// The manager is registered as a trace stage: it lets every record
+/// through, evaluates the rules on it and, on each state change, emits a
+/// record with the `alerts` source (ERROR when firing, INFO when resolved)
+/// and queues a notification for the webhooks. Resolution is checked on the
+/// stage tick, about once per second.
+///
+/// Clones share the same rules: one clone is registered in the trace
+/// system, another one serves `/alerts`.
+///
+/// The `alert_firing{alert}` gauge, `alert_notifications_total{alert, status}`
+/// and `alert_webhook_failures_total{webhook}` counters are registered in the
+/// metrics registry.
+#[derive(Clone)]
+pub struct AlertManager {
+    alerts: Arc<Alerts>,
+}
+
+impl AlertManager {
+    /// Compiles the rules and starts the notifier thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[alerts]` section
+    /// * `metrics` - Registry receiving the alerting metrics
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` for a duplicate rule name, an invalid
+    /// pattern, threshold or window, or an invalid webhook URL.
+    pub fn new(config: &AlertConfig, metrics: &Arc<MetricsRegistry>) -> Result<Self, Error> {
+        let mut names = HashSet::new();
+        if let Some(rule) = config.rules.iter().find(|rule| !names.insert(&rule.name)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("duplicate alert rule '{}'", rule.name),
+            ));
+        }
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| Rule::compile(rule, metrics))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            alerts: Arc::new(Alerts {
+                rules: Mutex::new(rules),
+                notifier: Notifier::start(config, metrics)?,
+            }),
+        })
+    }
+
+    /// Returns the state of every rule, in configuration order.
+    pub fn statuses(&self) -> Vec<AlertStatus> {
+        let rules = lock(&self.alerts.rules);
+        rules.iter().map(Rule::status).collect()
+    }
+
+    /// Logs and notifies the transitions (outside the rules lock).
+    fn dispatch(&self, transitions: Vec<Transition>, emit: &mut dyn FnMut(TraceRecord)) {
+        for transition in transitions {
+            emit(transition.record());
+            if transition.send {
+                self.alerts.notifier.notify(transition.notification);
+            }
+        }
+    }
+}
+
+impl TraceStage for AlertManager {
+    fn process(&self, record: TraceRecord, emit: &mut dyn FnMut(TraceRecord)) {
+        let now = Instant::now();
+        let transitions: Vec<Transition> = {
+            let mut rules = lock(&self.alerts.rules);
+            rules
+                .iter_mut()
+                .filter_map(|rule| rule.observe(&record, now))
+                .collect()
+        };
+        emit(record);
+        self.dispatch(transitions, emit);
+    }
+
+    fn tick(&self, now: Instant, emit: &mut dyn FnMut(TraceRecord)) {
+        let transitions: Vec<Transition> = {
+            let mut rules = lock(&self.alerts.rules);
+            rules.iter_mut().filter_map(|rule| rule.tick(now)).collect()
+        };
+        self.dispatch(transitions, emit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(threshold: u32) -> AlertRuleConfig {
+        AlertRuleConfig {
+            name: "billing-errors".to_string(),
+            sources: vec!["billing".to_string()],
+            min_level: Some(TraceLevel::Error),
+            pattern: None,
+            threshold,
+            window_secs: 60,
+            cooldown_secs: 300,
+        }
+    }
+
+    fn compile(config: AlertRuleConfig) -> Rule {
+        Rule::compile(&config, &Arc::new(MetricsRegistry::new())).unwrap()
+    }
+
+    fn error(source: &str) -> TraceRecord {
+        TraceRecord::new(TraceLevel::Error, source, "payment failed")
+    }
+
+    #[test]
+    fn test_threshold_within_window() {
+        let mut rule = compile(rule(3));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(rule.observe(&error("billing"), at(0)).is_none());
+        assert!(rule.observe(&error("billing"), at(10)).is_none());
+        // Other sources and lower levels don't count
+        assert!(rule.observe(&error("web"), at(20)).is_none());
+        let info = TraceRecord::new(TraceLevel::Info, "billing", "ok");
+        assert!(rule.observe(&info, at(20)).is_none());
+        // The first hit left the window
+        assert!(rule.observe(&error("billing"), at(61)).is_none());
+
+        let transition = rule.observe(&error("billing"), at(62)).unwrap();
+        assert!(transition.send);
+        assert_eq!(transition.notification.status, AlertEvent::Firing);
+        assert_eq!(rule.status().state, AlertState::Firing);
+        assert_eq!(rule.firing_gauge.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_deduplication_and_resolution() {
+        let mut rule = compile(rule(1));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(rule.observe(&error("billing"), at(0)).is_some());
+        // Already firing: counted, not notified again
+        assert!(rule.observe(&error("billing"), at(30)).is_none());
+        assert!(rule.observe(&error("billing"), at(50)).is_none());
+        assert_eq!(rule.status().count, 3);
+
+        assert!(rule.tick(at(100)).is_none());
+        let transition = rule.tick(at(110)).unwrap();
+        assert_eq!(transition.notification.status, AlertEvent::Resolved);
+        assert_eq!(transition.notification.count, 3);
+        assert!(transition.notification.resolved_at.is_some());
+        assert_eq!(transition.record().level, TraceLevel::Info);
+        assert_eq!(rule.status().state, AlertState::Ok);
+        assert!(rule.tick(at(120)).is_none());
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let mut rule = compile(rule(1));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(rule.observe(&error("billing"), at(0)).unwrap().send);
+        assert!(rule.tick(at(60)).unwrap().send);
+
+        // Fires again within the cooldown: shown and logged, not notified
+        let refire = rule.observe(&error("billing"), at(100)).unwrap();
+        assert!(!refire.send);
+        assert!(refire.record().message.contains("cooldown"));
+        assert!(!rule.tick(at(160)).unwrap().send);
+
+        assert!(rule.observe(&error("billing"), at(300)).unwrap().send);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let metrics = Arc::new(MetricsRegistry::new());
+        let duplicate = AlertConfig {
+            rules: vec![rule(1), rule(2)],
+            ..AlertConfig::default()
+        };
+        assert!(AlertManager::new(&duplicate, &metrics).is_err());
+
+        let zero = AlertConfig {
+            rules: vec![rule(0)],
+            ..AlertConfig::default()
+        };
+        assert!(AlertManager::new(&zero, &metrics).is_err());
+
+        let broken = AlertConfig {
+            rules: vec![AlertRuleConfig {
+                pattern: Some("(".to_string()),
+                ..rule(1)
+            }],
+            ..AlertConfig::default()
+        };
+        assert!(AlertManager::new(&broken, &metrics).is_err());
+    }
+
+    #[test]
+    fn test_stage_emits_alert_records() {
+        let config = AlertConfig {
+            rules: vec![rule(2)],
+            ..AlertConfig::default()
+        };
+        let alerts = AlertManager::new(&config, &Arc::new(MetricsRegistry::new())).unwrap();
+        let mut out = Vec::new();
+
+        alerts.process(error("billing"), &mut |r| out.push(r));
+        alerts.process(error("billing"), &mut |r| out.push(r));
+
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[2].source, ALERT_SOURCE);
+        assert_eq!(
+            out[2].message,
+            "alert 'billing-errors' firing: 2 matching records within 60s"
+        );
+        assert_eq!(alerts.statuses()[0].state, AlertState::Firing);
+    }
+}
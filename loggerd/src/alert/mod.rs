@@ -0,0 +1,123 @@
+//! Alerting on log records.
+//!
+//! Alert rules watch the records flowing through the trace system and fire
+//! when enough of them match within a time window ("at least 5 ERROR records
+//! from `billing` within a minute"). Each state change is logged with the
+//! `alerts` source and POSTed as JSON to the configured webhooks.
+//!
+//! # Architecture
+//!
+//! - `mod.rs` : Configuration
+//! - `manager.rs` : Rule evaluation, exposed as a trace stage (AlertManager)
+//! - `notifier.rs` : Background thread delivering notifications to the webhooks
+//!
+//! # Rule Lifecycle
+//!
+//! ```text
+//!         threshold reached within window_secs
+//!   ok ─────────────────────────────────────────▶ firing ──▶ notification
+//!    ▲                                              │
+//!    └──────── no match during window_secs ─────────┘ ──▶ notification
+//! ```
+//!
+//! While a rule is firing, further matches are only counted: one incident
+//! sends one notification (deduplication). A rule firing again less than
+//! `cooldown_secs` after its last notification is shown as firing but
+//! doesn't notify, so a flapping condition doesn't page every minute.
+//!
+//! # Configuration
+//!
+//! ```toml
+//! [alerts]
+//! webhooks = ["http://pager.example:9000/hooks/loggerd"]
+//! timeout_ms = 5000
+//! retries = 2
+//!
+//! [[alerts.rules]]
+//! name = "critical"
+//! min_level = "critical"
+//!
+//! [[alerts.rules]]
+//! name = "billing-errors"
+//! sources = ["billing"]
+//! min_level = "error"
+//! pattern = "payment|invoice"
+//! threshold = 5
+//! window_secs = 60
+//! cooldown_secs = 300
+//! ```
+
+mod manager;
+mod notifier;
+
+use serde::Deserialize;
+
+use crate::trace::TraceLevel;
+
+pub use manager::{ALERT_SOURCE, AlertManager, AlertState, AlertStatus};
+pub use notifier::{AlertEvent, AlertNotification};
+
+/// Configuration of the alerting subsystem (`[alerts]` section).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertConfig {
+    /// URLs (`http://host:port/path`) receiving every notification
+    pub webhooks: Vec<String>,
+    /// Connect/read/write timeout of a webhook request
+    pub timeout_ms: u64,
+    /// Additional attempts after a failed delivery, one second apart
+    pub retries: u32,
+    /// Alert rules
+    pub rules: Vec<AlertRuleConfig>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            timeout_ms: 5000,
+            retries: 2,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// An alert rule (`[[alerts.rules]]` entry).
+///
+/// Every criterion that is set must match, as for routing rules.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRuleConfig {
+    /// Alert name, unique
+    pub name: String,
+    /// Sources watched (any source when empty)
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Minimum level watched
+    #[serde(default)]
+    pub min_level: Option<TraceLevel>,
+    /// Regular expression the message must match
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Matching records needed within the window to fire
+    #[serde(default = "default_threshold")]
+    pub threshold: u32,
+    /// Length of the window, and time without match before resolving
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Minimum time between two firing notifications of the rule
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_threshold() -> u32 {
+    1
+}
+
+fn default_window_secs() -> u64 {
+    60
+}
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
@@ -0,0 +1,294 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::AlertConfig;
+use crate::http_client::HttpEndpoint;
+use crate::metrics::MetricsRegistry;
+use crate::trace::TraceRecord;
+
+/// Delay between two attempts of a failed delivery.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// State change announced by a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertEvent {
+    /// The rule's threshold was reached
+    Firing,
+    /// No record matched the rule for a whole window
+    Resolved,
+}
+
+/// JSON payload POSTed to the webhooks.
+///
+/// ```text
+/// {"alert":"billing-errors","status":"firing","summary":"...","threshold":5,"window_secs":60,
+///  "count":5,"started_at":"2025-10-14T17:45:32.123+02:00","resolved_at":null,"last_record":{...}}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertNotification {
+    /// Name of the rule
+    pub alert: String,
+    /// Firing or resolved
+    pub status: AlertEvent,
+    /// Human-readable description, also used as the log message
+    pub summary: String,
+    /// Matching records needed to fire
+    pub threshold: u32,
+    /// Window of the rule
+    pub window_secs: u64,
+    /// Matching records since the alert started firing
+    pub count: u64,
+    /// When the alert started firing
+    pub started_at: DateTime<Local>,
+    /// When the alert resolved (resolved notifications only)
+    pub resolved_at: Option<DateTime<Local>>,
+    /// Last record that matched the rule
+    pub last_record: Option<TraceRecord>,
+}
+
+/// Messages sent to the notifier thread.
+enum NotifierMessage {
+    /// Deliver a notification to every webhook
+    Notify(AlertNotification),
+    /// Deliver the pending notifications, then stop
+    Shutdown,
+}
+
+/// A webhook and its failure counter.
+struct Webhook {
+    endpoint: HttpEndpoint,
+    /// `alert_webhook_failures_total{webhook=...}`
+    failures: Arc<AtomicU64>,
+}
+
+/// Sends notifications to the webhooks from a dedicated thread, so that
+/// a slow or unreachable receiver never blocks logging.
+pub(super) struct Notifier {
+    /// Channel sender for communicating with the notifier thread
+    sender: Option<Sender<NotifierMessage>>,
+    /// Handle to the background notifier thread
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    /// Parses the webhook URLs and starts the notifier thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` for an invalid webhook URL, or an
+    /// error if the thread cannot be spawned.
+    pub(super) fn start(
+        config: &AlertConfig,
+        metrics: &MetricsRegistry,
+    ) -> Result<Self, std::io::Error> {
+        let webhooks = config
+            .webhooks
+            .iter()
+            .map(|url| {
+                let endpoint = HttpEndpoint::parse(url)?;
+                let label = format!("{}:{}{}", endpoint.host, endpoint.port, endpoint.path);
+                Ok(Webhook {
+                    failures: metrics
+                        .counter("alert_webhook_failures_total", &[("webhook", &label)]),
+                    endpoint,
+                })
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+
+        let (sender, receiver) = channel();
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let attempts = config.retries + 1;
+        let thread_handle = thread::Builder::new()
+            .name("loggerd-alerts".to_string())
+            .spawn(move || notifier_thread(receiver, webhooks, timeout, attempts))?;
+
+        Ok(Self {
+            sender: Some(sender),
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// Queues a notification for delivery.
+    pub(super) fn notify(&self, notification: AlertNotification) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(NotifierMessage::Notify(notification));
+        }
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(NotifierMessage::Shutdown);
+        }
+
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Dedicated notifier thread.
+///
+/// Notifications are delivered in order, to each webhook in turn. A delivery
+/// succeeds on any 2xx status; otherwise it is attempted up to `attempts`
+/// times, then counted in `alert_webhook_failures_total` and given up.
+fn notifier_thread(
+    receiver: Receiver<NotifierMessage>,
+    webhooks: Vec<Webhook>,
+    timeout: Duration,
+    attempts: u32,
+) {
+    while let Ok(NotifierMessage::Notify(notification)) = receiver.recv() {
+        let body = match serde_json::to_vec(&notification) {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("Failed to serialize alert notification: {}", e);
+                continue;
+            }
+        };
+
+        for webhook in &webhooks {
+            if !deliver(&webhook.endpoint, &body, timeout, attempts) {
+                webhook.failures.fetch_add(1, Ordering::Relaxed);
+                eprintln!(
+                    "Alert webhook http://{}:{}{} failed, notification for '{}' dropped",
+                    webhook.endpoint.host,
+                    webhook.endpoint.port,
+                    webhook.endpoint.path,
+                    notification.alert
+                );
+            }
+        }
+    }
+}
+
+/// POSTs `body` to `endpoint`; returns true once it was acknowledged.
+fn deliver(endpoint: &HttpEndpoint, body: &[u8], timeout: Duration, attempts: u32) -> bool {
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            thread::sleep(RETRY_DELAY);
+        }
+        if let Ok(status) = endpoint.post("application/json", body, timeout)
+            && (200..300).contains(&status)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TraceLevel;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Local stand-in for a webhook receiver: answers each request with
+    /// `status` and returns the JSON bodies it received.
+    fn webhook_receiver(status: u16, requests: usize) -> (String, JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for stream in listener.incoming().take(requests) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(serde_json::from_slice(&body).unwrap());
+                let response = format!("HTTP/1.1 {} OK\r\nContent-Length: 0\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+            bodies
+        });
+        (url, handle)
+    }
+
+    fn notification(alert: &str) -> AlertNotification {
+        AlertNotification {
+            alert: alert.to_string(),
+            status: AlertEvent::Firing,
+            summary: "test".to_string(),
+            threshold: 1,
+            window_secs: 60,
+            count: 1,
+            started_at: Local::now(),
+            resolved_at: None,
+            last_record: Some(TraceRecord::new(TraceLevel::Critical, "db", "disk full")),
+        }
+    }
+
+    #[test]
+    fn test_delivers_to_every_webhook() {
+        let (first_url, first) = webhook_receiver(200, 2);
+        let (second_url, second) = webhook_receiver(204, 2);
+        let config = AlertConfig {
+            webhooks: vec![first_url, second_url],
+            ..AlertConfig::default()
+        };
+
+        let notifier = Notifier::start(&config, &MetricsRegistry::new()).unwrap();
+        notifier.notify(notification("a"));
+        notifier.notify(notification("b"));
+        drop(notifier); // Delivers the queued notifications
+
+        for bodies in [first.join().unwrap(), second.join().unwrap()] {
+            assert_eq!(bodies.len(), 2);
+            assert_eq!(bodies[0]["alert"], "a");
+            assert_eq!(bodies[0]["status"], "firing");
+            assert_eq!(bodies[0]["last_record"]["message"], "disk full");
+            assert_eq!(bodies[1]["alert"], "b");
+        }
+    }
+
+    #[test]
+    fn test_failed_delivery_is_retried_and_counted() {
+        let (url, receiver) = webhook_receiver(500, 2);
+        let config = AlertConfig {
+            webhooks: vec![url],
+            retries: 1,
+            ..AlertConfig::default()
+        };
+        let metrics = MetricsRegistry::new();
+
+        let notifier = Notifier::start(&config, &metrics).unwrap();
+        notifier.notify(notification("a"));
+        drop(notifier);
+
+        assert_eq!(receiver.join().unwrap().len(), 2);
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot["counters"][0]["name"],
+            "alert_webhook_failures_total"
+        );
+        assert_eq!(snapshot["counters"][0]["value"], 1);
+    }
+
+    #[test]
+    fn test_invalid_webhook() {
+        let config = AlertConfig {
+            webhooks: vec!["https://pager.example/hook".to_string()],
+            ..AlertConfig::default()
+        };
+        assert!(Notifier::start(&config, &MetricsRegistry::new()).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use uuid::Uuid;
+
+use super::{AppState, client_addr};
+use crate::trace::{TraceLevel, TraceRecord};
+
+/// Header carrying the request ID, in both directions.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Source of the access log records, usable in `[routing]` rules.
+pub const ACCESS_LOG_SOURCE: &str = "http";
+
+/// Longest request ID accepted from a client.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Returns the client's request ID if it is safe to log and echo back.
+fn incoming_request_id(request: &Request) -> Option<HeaderValue> {
+    let value = request.headers().get(REQUEST_ID_HEADER)?;
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value
+            .as_bytes()
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b));
+    valid.then(|| value.clone())
+}
+
+/// Middleware logging every request and tagging it with a request ID.
+///
+/// The `X-Request-Id` sent by the client is kept if it is a short token,
+/// otherwise a UUID is generated; either way it is set on the request (for
+/// the handlers) and on the response. Each request increments the `requests`
+/// counter and `http_requests_total{route, status}`, where `route` is the
+/// matched route template (`unmatched` for 404s) so that the number of series
+/// stays bounded.
+///
+/// When `enabled`, a record is also logged with the `http` source (INFO,
+/// WARNING for 4xx, ERROR for 5xx):
+///
+/// ```text
+/// [INFO] http - 10.0.0.7:51234 "POST /logs" 202 1.4ms request_id=4f1c...
+/// ```
+pub async fn access_log(
+    State((state, enabled)): State<(AppState, bool)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let request_id = incoming_request_id(&request).unwrap_or_else(|| {
+        HeaderValue::from_str(&Uuid::new_v4().to_string()).expect("UUID is a valid header")
+    });
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, request_id.clone());
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let client = client_addr(&request);
+    state.metrics.requests.fetch_add(1, Ordering::Relaxed);
+
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, request_id.clone());
+
+    let status = response.status();
+    state
+        .metrics
+        .registry
+        .counter(
+            "http_requests_total",
+            &[("route", &route), ("status", status.as_str())],
+        )
+        .fetch_add(1, Ordering::Relaxed);
+
+    if enabled {
+        let level = if status.is_server_error() {
+            TraceLevel::Error
+        } else if status.is_client_error() {
+            TraceLevel::Warning
+        } else {
+            TraceLevel::Info
+        };
+        let message = format!(
+            "{} \"{} {}\" {} {:.1}ms request_id={}",
+            client,
+            method,
+            path,
+            status.as_u16(),
+            start.elapsed().as_secs_f64() * 1000.0,
+            request_id.to_str().unwrap_or_default()
+        );
+        state
+            .trace
+            .log_record(&TraceRecord::new(level, ACCESS_LOG_SOURCE, &message));
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn request_with_id(id: &str) -> Request {
+        Request::builder()
+            .header(REQUEST_ID_HEADER, id)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_incoming_request_id() {
+        assert_eq!(
+            incoming_request_id(&request_with_id("abc-123_4.5:6")).unwrap(),
+            "abc-123_4.5:6"
+        );
+        assert!(incoming_request_id(&request_with_id("has space")).is_none());
+        assert!(incoming_request_id(&request_with_id(&"a".repeat(129))).is_none());
+        assert!(incoming_request_id(&request_with_id("")).is_none());
+        assert!(incoming_request_id(&Request::builder().body(Body::empty()).unwrap()).is_none());
+    }
+}
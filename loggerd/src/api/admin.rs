@@ -0,0 +1,312 @@
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{ErrorKind, SeekFrom};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::AppState;
+use crate::trace::TraceLevel;
+
+/// Part of a file requested by a `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// No (usable) range: the whole file
+    Full,
+    /// First and last byte, inclusive
+    Partial(u64, u64),
+    /// The range starts after the end of the file
+    Unsatisfiable,
+}
+
+/// Interprets a `Range` header for a file of `len` bytes.
+///
+/// Only single byte ranges are honoured (`bytes=0-499`, `bytes=500-`,
+/// `bytes=-500`); other units, multiple ranges and malformed values are
+/// ignored, which RFC 9110 allows, and the whole file is sent.
+fn parse_range(header: Option<&str>, len: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        // bytes=-500: the last 500 bytes
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            (len.saturating_sub(suffix), len.saturating_sub(1))
+        }
+        (Ok(start), Err(_)) if last.is_empty() => (start, len.saturating_sub(1)),
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+        _ => return ByteRange::Full,
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end)
+}
+
+/// Builds an error response with a JSON body.
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+/// HTTP handler for `GET /admin/files`.
+///
+/// Lists the active log files, each followed by its rotation backups,
+/// newest first. Without file output, `enabled` is false and the list is
+/// empty.
+///
+/// # Returns
+///
+/// `{"enabled": true, "files": [{"name", "size", "modified", "active"}, ...]}`
+pub async fn files_handler(State(state): State<AppState>) -> Json<Value> {
+    let files = state
+        .log_files
+        .as_ref()
+        .map(|files| files.list())
+        .unwrap_or_default();
+
+    Json(json!({
+        "enabled": state.log_files.is_some(),
+        "files": files,
+    }))
+}
+
+/// HTTP handler for `GET /admin/files/{name}`.
+///
+/// Streams a file listed by `/admin/files`; any other name, including paths
+/// (`../etc/passwd`), gives `404 Not Found`. A single `Range` is honoured
+/// with `206 Partial Content` (`416 Range Not Satisfiable` when it starts
+/// after the end), so interrupted downloads can be resumed with `curl -C -`.
+///
+/// The size is taken when the file is opened: bytes appended to an active
+/// file during the download are not sent.
+pub async fn download_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(path) = state
+        .log_files
+        .as_ref()
+        .and_then(|files| files.path_of(&name))
+    else {
+        return error(
+            StatusCode::NOT_FOUND,
+            format!("no log file named '{}'", name),
+        );
+    };
+
+    let opened = match tokio::fs::File::open(&path).await {
+        Ok(file) => file.metadata().await.map(|metadata| (file, metadata.len())),
+        Err(e) => Err(e),
+    };
+    let (mut file, len) = match opened {
+        Ok(opened) => opened,
+        // Removed since it was listed (retention, disk guard)
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return error(
+                StatusCode::NOT_FOUND,
+                format!("no log file named '{}'", name),
+            );
+        }
+        Err(e) => {
+            return error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("cannot read {}: {}", name, e),
+            );
+        }
+    };
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let (status, start, end) = match parse_range(range, len) {
+        ByteRange::Full => (StatusCode::OK, 0, len),
+        ByteRange::Partial(first, last) => (StatusCode::PARTIAL_CONTENT, first, last + 1),
+        ByteRange::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+            )
+                .into_response();
+        }
+    };
+    if start > 0
+        && let Err(e) = file.seek(SeekFrom::Start(start)).await
+    {
+        return error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("cannot read {}: {}", name, e),
+        );
+    }
+
+    let body = Body::from_stream(ReaderStream::new(file.take(end - start)));
+    let mut response = (
+        status,
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (header::CONTENT_LENGTH, (end - start).to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", name.replace('"', "")),
+            ),
+        ],
+        body,
+    )
+        .into_response();
+    if status == StatusCode::PARTIAL_CONTENT
+        && let Ok(value) = HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end - 1, len))
+    {
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
+
+/// HTTP handler for `POST /admin/rotate`.
+///
+/// Rotates every active log file now, through their writer threads (as
+/// `SIGUSR1` does). Empty files are left alone and reported with a `null`
+/// backup.
+///
+/// # Returns
+///
+/// `{"rotated": [{"file", "backup"}, ...], "errors": [{"file", "error"}, ...]}`,
+/// with `500 Internal Server Error` if any rotation failed, or
+/// `409 Conflict` when the file output is disabled
+pub async fn rotate_handler(State(state): State<AppState>) -> Response {
+    let Some(files) = state.log_files.clone() else {
+        return error(StatusCode::CONFLICT, "file output is disabled".to_string());
+    };
+
+    let outcomes = tokio::task::spawn_blocking(move || files.rotate())
+        .await
+        .unwrap_or_default();
+    let mut rotated = Vec::new();
+    let mut errors = Vec::new();
+    for (file, result) in outcomes {
+        match result {
+            Ok(backup) => rotated.push(json!({ "file": file, "backup": backup })),
+            Err(e) => errors.push(json!({ "file": file, "error": e.to_string() })),
+        }
+    }
+
+    crate::trace_info!(
+        state.trace,
+        "Log files rotated on request ({} rotated, {} failed)",
+        rotated.len(),
+        errors.len()
+    );
+    let status = if errors.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    (
+        status,
+        Json(json!({ "rotated": rotated, "errors": errors })),
+    )
+        .into_response()
+}
+
+/// HTTP handler for `GET /admin/level`.
+///
+/// # Returns
+///
+/// The minimum level of the trace system: `{"level": "INFO"}`
+pub async fn level_handler(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "level": state.level.get() }))
+}
+
+/// Body of `PUT /admin/level`.
+#[derive(Debug, Deserialize)]
+pub struct LevelChange {
+    /// New minimum level (`"debug"`, `"warning"`, ...)
+    level: TraceLevel,
+}
+
+/// HTTP handler for `PUT /admin/level`.
+///
+/// Changes the minimum level of the trace system at once: records below it
+/// are dropped before any stage or handler, whatever their source. The
+/// change is not persisted and a restart goes back to every level.
+///
+/// # Returns
+///
+/// `{"level": "DEBUG", "previous": "INFO"}`, or `422 Unprocessable Entity`
+/// for an unknown level
+pub async fn set_level_handler(
+    State(state): State<AppState>,
+    Json(change): Json<LevelChange>,
+) -> Json<Value> {
+    let previous = state.level.set(change.level);
+    // Logged as a warning so that it survives most new levels
+    crate::trace_warning!(
+        state.trace,
+        "Minimum level changed from {} to {}",
+        previous.as_str(),
+        change.level.as_str()
+    );
+    Json(json!({ "level": change.level, "previous": previous }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 1000), ByteRange::Full);
+        assert_eq!(
+            parse_range(Some("bytes=0-499"), 1000),
+            ByteRange::Partial(0, 499)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=500-"), 1000),
+            ByteRange::Partial(500, 999)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-100"), 1000),
+            ByteRange::Partial(900, 999)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-5000"), 1000),
+            ByteRange::Partial(0, 999)
+        );
+        // The end is clamped to the file
+        assert_eq!(
+            parse_range(Some("bytes=900-5000"), 1000),
+            ByteRange::Partial(900, 999)
+        );
+
+        assert_eq!(
+            parse_range(Some("bytes=1000-"), 1000),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-0"), 1000),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+
+        // Ignored: multiple ranges, other units, malformed values
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 1000), ByteRange::Full);
+        assert_eq!(parse_range(Some("lines=0-10"), 1000), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=9-2"), 1000), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=abc"), 1000), ByteRange::Full);
+    }
+}
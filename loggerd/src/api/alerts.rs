@@ -0,0 +1,27 @@
+use axum::Json;
+use axum::extract::State;
+use serde_json::{Value, json};
+
+use super::AppState;
+
+/// HTTP handler for `GET /alerts`.
+///
+/// Returns the state of every alert rule, in configuration order. Without
+/// `[alerts]` section, `enabled` is false and the list is empty.
+///
+/// # Returns
+///
+/// `{"enabled": true, "alerts": [{"name", "state", "since", "count", "threshold",
+/// "window_secs", "last_notification", "last_record"}, ...]}`
+pub async fn alerts_handler(State(state): State<AppState>) -> Json<Value> {
+    let alerts = state
+        .alerts
+        .as_ref()
+        .map(|alerts| alerts.statuses())
+        .unwrap_or_default();
+
+    Json(json!({
+        "enabled": state.alerts.is_some(),
+        "alerts": alerts,
+    }))
+}
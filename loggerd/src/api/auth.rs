@@ -0,0 +1,357 @@
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::Ordering;
+
+use super::{AppState, client_addr};
+
+/// Header carrying an API key, as an alternative to `Authorization: Bearer`.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Permission granted by an API key.
+///
+/// `admin` implies every other role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Send records (`POST /logs`)
+    Ingest,
+    /// Read records and metrics
+    Read,
+    /// Administration endpoints
+    Admin,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Ingest => "ingest",
+            Role::Read => "read",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// Configuration of API authentication (`[auth]` section).
+///
+/// ```toml
+/// [auth]
+/// public_health = true
+///
+/// [[auth.keys]]
+/// name = "billing-app"
+/// key = "change-me"
+/// roles = ["ingest"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Leaves `GET /health` reachable without a key
+    pub public_health: bool,
+    /// Accepted keys
+    pub keys: Vec<ApiKeyConfig>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            public_health: true,
+            keys: Vec::new(),
+        }
+    }
+}
+
+/// An API key and the roles it grants.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Name of the client, used in logs (never the key itself)
+    pub name: String,
+    /// Secret sent by the client
+    pub key: String,
+    /// Granted roles
+    pub roles: Vec<Role>,
+}
+
+/// Why a request was rejected.
+#[derive(Debug, PartialEq)]
+enum Rejection {
+    /// No key in the request (401)
+    Missing,
+    /// Unknown key (401)
+    Invalid,
+    /// Known key without the required role (403)
+    Forbidden { name: String },
+}
+
+impl Rejection {
+    fn reason(&self) -> &'static str {
+        match self {
+            Rejection::Missing => "missing",
+            Rejection::Invalid => "invalid",
+            Rejection::Forbidden { .. } => "forbidden",
+        }
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        match self {
+            Rejection::Missing | Rejection::Invalid => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(json!({ "error": format!("{} API key", self.reason()) })),
+            )
+                .into_response(),
+            Rejection::Forbidden { .. } => (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "API key not allowed on this endpoint" })),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Validated set of API keys.
+pub struct ApiKeys {
+    public_health: bool,
+    keys: Vec<ApiKeyConfig>,
+}
+
+impl ApiKeys {
+    /// Validates the `[auth]` section.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if a key is empty or used twice.
+    pub fn new(config: &AuthConfig) -> Result<Self, Error> {
+        for (index, key) in config.keys.iter().enumerate() {
+            if key.key.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("API key '{}' is empty", key.name),
+                ));
+            }
+            if config.keys[..index]
+                .iter()
+                .any(|other| other.key == key.key)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("API key '{}' is already defined", key.name),
+                ));
+            }
+        }
+
+        Ok(Self {
+            public_health: config.public_health,
+            keys: config.keys.clone(),
+        })
+    }
+
+    /// Returns true if `GET /health` needs no key.
+    pub fn public_health(&self) -> bool {
+        self.public_health
+    }
+
+    /// Checks the key of a request against the required role.
+    ///
+    /// Returns the name of the matching key.
+    fn authorize(&self, headers: &HeaderMap, role: Role) -> Result<&str, Rejection> {
+        let presented = request_key(headers).ok_or(Rejection::Missing)?;
+
+        // Compare against every key so that the timing doesn't reveal which one is close
+        let mut found = None;
+        for key in &self.keys {
+            if constant_time_eq(key.key.as_bytes(), presented.as_bytes()) {
+                found = Some(key);
+            }
+        }
+        let key = found.ok_or(Rejection::Invalid)?;
+
+        if key.roles.contains(&role) || key.roles.contains(&Role::Admin) {
+            Ok(&key.name)
+        } else {
+            Err(Rejection::Forbidden {
+                name: key.name.clone(),
+            })
+        }
+    }
+}
+
+/// Extracts the key from `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+fn request_key(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let (scheme, token) = value.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        });
+
+    bearer
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        })
+        .filter(|key| !key.is_empty())
+}
+
+/// Compares two secrets in a time that only depends on their length.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Middleware rejecting requests whose key lacks `role`.
+///
+/// Installed with `route_layer(from_fn_with_state((state, role), require_role))`
+/// on each group of routes. Does nothing when the `[auth]` section is absent.
+/// Rejections are logged as warnings (with the client address and the key
+/// name, never the key) and counted in `auth_rejected_total{reason}`.
+pub async fn require_role(
+    State((state, role)): State<(AppState, Role)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(keys) = &state.auth else {
+        return next.run(request).await;
+    };
+
+    let rejection = match keys.authorize(request.headers(), role) {
+        Ok(_) => return next.run(request).await,
+        Err(rejection) => rejection,
+    };
+
+    let client = client_addr(&request);
+    let key = match &rejection {
+        Rejection::Forbidden { name } => format!(" (key '{}' lacks role {})", name, role.as_str()),
+        _ => String::new(),
+    };
+    crate::trace_warning!(
+        state.trace,
+        "rejected {} {} from {}: {} API key{}",
+        request.method(),
+        request.uri().path(),
+        client,
+        rejection.reason(),
+        key;
+        client = client,
+        reason = rejection.reason(),
+    );
+    state
+        .metrics
+        .registry
+        .counter("auth_rejected_total", &[("reason", rejection.reason())])
+        .fetch_add(1, Ordering::Relaxed);
+
+    rejection.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn keys() -> ApiKeys {
+        ApiKeys::new(&AuthConfig {
+            public_health: true,
+            keys: vec![
+                ApiKeyConfig {
+                    name: "app".to_string(),
+                    key: "ingest-key".to_string(),
+                    roles: vec![Role::Ingest],
+                },
+                ApiKeyConfig {
+                    name: "ops".to_string(),
+                    key: "admin-key".to_string(),
+                    roles: vec![Role::Admin],
+                },
+            ],
+        })
+        .unwrap()
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_bearer_and_api_key_headers() {
+        let keys = keys();
+        let bearer = headers(header::AUTHORIZATION, "Bearer ingest-key");
+        let api_key = headers(
+            header::HeaderName::from_static(API_KEY_HEADER),
+            "ingest-key",
+        );
+
+        assert_eq!(keys.authorize(&bearer, Role::Ingest), Ok("app"));
+        assert_eq!(keys.authorize(&api_key, Role::Ingest), Ok("app"));
+    }
+
+    #[test]
+    fn test_rejections() {
+        let keys = keys();
+
+        assert_eq!(
+            keys.authorize(&HeaderMap::new(), Role::Read),
+            Err(Rejection::Missing)
+        );
+        assert_eq!(
+            keys.authorize(
+                &headers(header::AUTHORIZATION, "Basic ingest-key"),
+                Role::Read
+            ),
+            Err(Rejection::Missing)
+        );
+        assert_eq!(
+            keys.authorize(&headers(header::AUTHORIZATION, "Bearer nope"), Role::Read),
+            Err(Rejection::Invalid)
+        );
+        assert_eq!(
+            keys.authorize(
+                &headers(header::AUTHORIZATION, "Bearer ingest-key"),
+                Role::Read
+            ),
+            Err(Rejection::Forbidden {
+                name: "app".to_string()
+            })
+        );
+        // admin implies every role
+        assert_eq!(
+            keys.authorize(
+                &headers(header::AUTHORIZATION, "Bearer admin-key"),
+                Role::Read
+            ),
+            Ok("ops")
+        );
+    }
+
+    #[test]
+    fn test_invalid_keys_are_refused() {
+        let key = |key: &str| ApiKeyConfig {
+            name: "k".to_string(),
+            key: key.to_string(),
+            roles: vec![Role::Read],
+        };
+        let empty = AuthConfig {
+            keys: vec![key("")],
+            ..AuthConfig::default()
+        };
+        let duplicate = AuthConfig {
+            keys: vec![key("a"), key("a")],
+            ..AuthConfig::default()
+        };
+
+        assert!(ApiKeys::new(&empty).is_err());
+        assert!(ApiKeys::new(&duplicate).is_err());
+    }
+}
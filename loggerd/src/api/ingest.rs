@@ -0,0 +1,162 @@
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use super::AppState;
+use crate::trace::{TraceLevel, TraceRecord, fields_from_json};
+
+/// Source used when an ingested record doesn't name one.
+const UNKNOWN_SOURCE: &str = "unknown";
+
+/// A record as sent by a client.
+///
+/// Only `message` is mandatory; the timestamp defaults to the reception
+/// time, the level to INFO and the source to `unknown`. The optional `fields`
+/// object becomes the structured fields of the record (nested objects are
+/// flattened with dotted names).
+#[derive(Debug, Deserialize)]
+struct IngestRecord {
+    #[serde(default)]
+    timestamp: Option<DateTime<Local>>,
+    #[serde(default = "default_level")]
+    level: TraceLevel,
+    #[serde(default)]
+    source: Option<String>,
+    message: String,
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+fn default_level() -> TraceLevel {
+    TraceLevel::Info
+}
+
+impl From<IngestRecord> for TraceRecord {
+    fn from(record: IngestRecord) -> Self {
+        let mut trace_record = TraceRecord::new(
+            record.level,
+            record.source.as_deref().unwrap_or(UNKNOWN_SOURCE),
+            &record.message,
+        );
+        if let Some(timestamp) = record.timestamp {
+            trace_record.timestamp = timestamp;
+        }
+        trace_record.fields = fields_from_json(&record.fields);
+        trace_record
+    }
+}
+
+/// Parses a request body: a JSON object, a JSON array of objects, or
+/// newline-delimited JSON objects (the format used by log forwarding).
+fn parse_body(body: &str) -> Result<Vec<IngestRecord>, String> {
+    let trimmed = body.trim();
+    if trimmed.is_empty() {
+        return Err("empty body".to_string());
+    }
+
+    if trimmed.starts_with('[') {
+        return serde_json::from_str(trimmed).map_err(|e| e.to_string());
+    }
+    if let Ok(record) = serde_json::from_str(trimmed) {
+        return Ok(vec![record]);
+    }
+
+    trimmed
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))
+        })
+        .collect()
+}
+
+/// HTTP handler for `POST /logs`.
+///
+/// Injects the records into the trace pipeline, where they go through the
+/// processing stages (rate limiting, ...) and reach every handler. The body
+/// is validated as a whole: if any record is malformed, nothing is ingested.
+///
+/// # Returns
+///
+/// * `202 Accepted` with `{"accepted": n}`
+/// * `400 Bad Request` with `{"error": "..."}` if the body cannot be parsed
+pub async fn ingest_handler(
+    State(state): State<AppState>,
+    body: String,
+) -> (StatusCode, Json<Value>) {
+    let records = match parse_body(&body) {
+        Ok(records) => records,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))),
+    };
+
+    let accepted = records.len();
+    for record in records {
+        state.trace.log_record(&record.into());
+    }
+
+    (StatusCode::ACCEPTED, Json(json!({ "accepted": accepted })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_object() {
+        let records =
+            parse_body(r#"{"level": "error", "source": "app", "message": "boom"}"#).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].level, TraceLevel::Error);
+
+        let record: TraceRecord = records.into_iter().next().unwrap().into();
+        assert_eq!(record.source, "app");
+        assert_eq!(record.message, "boom");
+        assert!(record.fields.is_empty());
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let records = parse_body(
+            r#"{"message": "request", "fields": {"status": 500, "user": "bob", "http": {"method": "GET"}}}"#,
+        )
+        .unwrap();
+        let record: TraceRecord = records.into_iter().next().unwrap().into();
+        assert_eq!(record.fields["status"], "500");
+        assert_eq!(record.fields["user"], "bob");
+        assert_eq!(record.fields["http.method"], "GET");
+
+        assert!(parse_body(r#"{"message": "a", "fields": [1]}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_array_and_ndjson() {
+        let array = parse_body(r#"[{"message": "a"}, {"message": "b"}]"#).unwrap();
+        assert_eq!(array.len(), 2);
+
+        let ndjson = parse_body(
+            "{\"message\": \"a\", \"timestamp\": \"2025-10-14T17:45:32+02:00\"}\n\n{\"message\": \"b\"}\n",
+        )
+        .unwrap();
+        assert_eq!(ndjson.len(), 2);
+        assert!(ndjson[0].timestamp.is_some());
+
+        let record: TraceRecord = ndjson.into_iter().nth(1).unwrap().into();
+        assert_eq!(record.level, TraceLevel::Info);
+        assert_eq!(record.source, UNKNOWN_SOURCE);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_body("  ").is_err());
+        assert!(
+            parse_body("{\"message\": \"a\"}\nnot json")
+                .unwrap_err()
+                .starts_with("line 2")
+        );
+        assert!(parse_body(r#"{"level": "loud", "message": "a"}"#).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! HTTP API of the loggerd daemon.
+//!
+//! # Architecture
+//!
+//! - `mod.rs` : Shared state, router and monitoring endpoints
+//! - `ingest.rs` : Log ingestion from other applications
+//! - `auth.rs` : API keys and per-role authorization middleware
+//! - `access_log.rs` : Access logging, request IDs and per-route counters
+//! - `query.rs` : Search of the stored records
+//! - `recent.rs` : Most recent records, served from memory
+//! - `alerts.rs` : State of the alert rules
+//! - `admin.rs` : Log file listing, download and forced rotation, minimum level
+//!
+//! # Endpoints
+//!
+//! - `GET /health` - Health check endpoint ("OK", or the directories low on disk space)
+//! - `GET /metrics` - JSON metrics including request count, log count, and uptime
+//!   (Prometheus text format with `?format=prometheus` or `Accept: text/plain`)
+//! - `POST /logs` - Ingest records (JSON object, JSON array or NDJSON)
+//! - `GET /logs?since=&until=&level=&source=&pattern=&limit=&field.<name>=` -
+//!   Records searched in the log files, as JSON
+//! - `GET /logs/recent?after=&...` - Most recent records, as JSON (same filters)
+//! - `GET /alerts` - State of the alert rules
+//! - `GET /admin/files` - Active log files and backups
+//! - `GET /admin/files/{name}` - Download a log file (`Range` supported)
+//! - `POST /admin/rotate` - Rotate the log files now
+//! - `GET /admin/level`, `PUT /admin/level` - Minimum level of the trace system
+//!
+//! # Authentication
+//!
+//! When the `[auth]` section is configured, every endpoint requires an API
+//! key (`Authorization: Bearer <key>` or `X-API-Key: <key>`) granting its
+//! role: `read` for `/metrics`, `GET /logs`, `/logs/recent` and `/alerts`
+//! (and `/health` unless `public_health`),
+//! `ingest` for `POST /logs`, `admin` for `/admin/*`. `admin` keys are
+//! accepted everywhere.
+
+mod access_log;
+mod admin;
+mod alerts;
+mod auth;
+mod ingest;
+mod query;
+mod recent;
+
+use axum::extract::{ConnectInfo, Query, Request};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router, extract::State};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::alert::AlertManager;
+use crate::metrics::MetricsRegistry;
+use crate::trace::file::{DiskGuard, DiskState, LogFiles};
+use crate::trace::{LevelFilter, RingBufferTraceHandler, Trace};
+
+pub use access_log::{ACCESS_LOG_SOURCE, REQUEST_ID_HEADER};
+pub use auth::{ApiKeyConfig, ApiKeys, AuthConfig, Role};
+
+/// Configuration of the HTTP server (`[server]` section).
+///
+/// ```toml
+/// [server]
+/// bind = "0.0.0.0:8080"   # port 0: any free port
+/// access_log = true
+/// drain_timeout_ms = 5000 # time given to the handlers to write out at shutdown
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address and port the API listens on
+    pub bind: SocketAddr,
+    /// Logs every request with the `http` source
+    pub access_log: bool,
+    /// Time given at shutdown to write out the queued records, once the
+    /// open connections are closed
+    pub drain_timeout_ms: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            access_log: true,
+            drain_timeout_ms: 5000,
+        }
+    }
+}
+
+/// Shared state for application metrics.
+///
+/// Maintains counters and timing information for the HTTP API endpoints
+/// and integrates with the trace system for logging.
+#[derive(Clone)]
+pub struct AppState {
+    /// Application metrics counters
+    pub metrics: Arc<MetricsState>,
+    /// Shared trace system for logging
+    pub trace: Arc<dyn Trace + Send + Sync>,
+    /// Accepted API keys (no authentication when None)
+    pub auth: Option<Arc<ApiKeys>>,
+    /// Most recent records, shared with the trace system
+    pub recent: RingBufferTraceHandler,
+    /// Alert rules, shared with the trace system (None when alerting is off)
+    pub alerts: Option<AlertManager>,
+    /// Free space watchdog of the log directories (None when off)
+    pub disk_guard: Option<Arc<DiskGuard>>,
+    /// Log files served by the admin endpoints and searched by `GET /logs`
+    /// (None without file output)
+    pub log_files: Option<LogFiles>,
+    /// Minimum level of the trace system
+    pub level: LevelFilter,
+}
+
+/// Internal metrics state with atomic counters.
+///
+/// Thread-safe metrics collection using atomic operations to avoid
+/// blocking during concurrent access from multiple request handlers.
+pub struct MetricsState {
+    /// Total number of HTTP requests processed
+    pub requests: AtomicU64,
+    /// Total number of log messages written (shared with file handler)
+    pub log_count: Arc<AtomicU64>,
+    /// Application start time for uptime calculation
+    pub start: Instant,
+    /// Labelled counters and gauges reported by the trace system
+    pub registry: Arc<MetricsRegistry>,
+}
+
+impl MetricsState {
+    /// Creates the metrics state, starting the uptime clock now.
+    pub fn new(log_count: Arc<AtomicU64>, registry: Arc<MetricsRegistry>) -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            log_count,
+            start: Instant::now(),
+            registry,
+        }
+    }
+}
+
+/// Builds the router with every endpoint of the daemon.
+///
+/// Routes are grouped by the role they require, each group behind its own
+/// authorization layer. The access log layer wraps them all, so rejected
+/// requests are logged too.
+pub fn router(state: AppState, server: &ServerConfig) -> Router {
+    let health = Router::new().route("/health", get(health_handler));
+    let health = match &state.auth {
+        Some(keys) if !keys.public_health() => with_role(health, &state, Role::Read),
+        _ => health,
+    };
+    let read = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/logs", get(query::logs_handler))
+        .route("/logs/recent", get(recent::recent_handler))
+        .route("/alerts", get(alerts::alerts_handler));
+    let ingest = Router::new().route("/logs", post(ingest::ingest_handler));
+    let admin = Router::new()
+        .route("/admin/files", get(admin::files_handler))
+        .route("/admin/files/:name", get(admin::download_handler))
+        .route("/admin/rotate", post(admin::rotate_handler))
+        .route(
+            "/admin/level",
+            get(admin::level_handler).put(admin::set_level_handler),
+        );
+
+    Router::new()
+        .merge(health)
+        .merge(with_role(read, &state, Role::Read))
+        .merge(with_role(ingest, &state, Role::Ingest))
+        .merge(with_role(admin, &state, Role::Admin))
+        .layer(middleware::from_fn_with_state(
+            (state.clone(), server.access_log),
+            access_log::access_log,
+        ))
+        .with_state(state)
+}
+
+/// Requires `role` on every route of `routes`.
+fn with_role(routes: Router<AppState>, state: &AppState, role: Role) -> Router<AppState> {
+    routes.route_layer(middleware::from_fn_with_state(
+        (state.clone(), role),
+        auth::require_role,
+    ))
+}
+
+/// Returns the client address of a request, or "unknown" when the server
+/// doesn't provide connection info.
+fn client_addr(request: &Request) -> String {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// HTTP handler for the health check endpoint.
+///
+/// Returns a simple "OK" response to indicate the service is running.
+/// This endpoint can be used by load balancers and monitoring systems
+/// to verify service availability.
+///
+/// When the disk guard reacts to low disk space, the response lists the
+/// affected directories instead:
+///
+/// ```text
+/// DEGRADED
+/// /var/log/loggerd: shedding (7.4% free)
+/// ```
+///
+/// # Returns
+///
+/// "OK"; "DEGRADED" and the directories with `200 OK` while backups are
+/// removed or records shed, `503 Service Unavailable` once file writes are
+/// paused
+async fn health_handler(State(state): State<AppState>) -> Response {
+    let statuses = state
+        .disk_guard
+        .as_ref()
+        .map(|guard| guard.statuses())
+        .unwrap_or_default();
+    let worst = statuses.iter().map(|status| status.state).max();
+    if worst.is_none_or(|state| state == DiskState::Ok) {
+        return "OK".into_response();
+    }
+
+    let mut body = "DEGRADED".to_string();
+    for status in statuses
+        .iter()
+        .filter(|status| status.state != DiskState::Ok)
+    {
+        body.push_str(&format!(
+            "\n{}: {} ({}% free)",
+            status.path, status.state, status.free_percent
+        ));
+    }
+    let code = match worst {
+        Some(DiskState::Paused) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (code, body).into_response()
+}
+
+/// Query string of `GET /metrics`.
+#[derive(Debug, Deserialize)]
+struct MetricsQuery {
+    /// `json` or `prometheus` (`?format=prometheus`)
+    format: Option<String>,
+}
+
+/// Content type of the Prometheus text exposition format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Returns `true` if the client asked for the Prometheus text format.
+///
+/// The `format` query parameter wins; otherwise an `Accept` header naming
+/// `text/plain` or OpenMetrics (as Prometheus scrapers send) selects it.
+fn wants_prometheus(format: Option<&str>, headers: &HeaderMap) -> Result<bool, String> {
+    match format {
+        Some("prometheus") => Ok(true),
+        Some("json") => Ok(false),
+        Some(other) => Err(format!(
+            "unknown metrics format '{}' (expected json or prometheus)",
+            other
+        )),
+        None => Ok(headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| {
+                accept.contains("text/plain") || accept.contains("application/openmetrics-text")
+            })),
+    }
+}
+
+/// HTTP handler for the metrics endpoint.
+///
+/// Returns JSON-formatted metrics including:
+/// - Total HTTP requests processed
+/// - Total log messages written to files
+/// - Service uptime in seconds
+/// - Current service status
+/// - Labelled counters, gauges and histograms from the metrics registry
+///   (forwarding, rate limiting, `[[log_metrics]]` rules, ...)
+///
+/// The request counter covers every endpoint (see the access log layer).
+///
+/// The same metrics are served in the Prometheus text format when asked
+/// for (see [`wants_prometheus`]); the first three are then named
+/// `loggerd_requests_total`, `loggerd_log_count_total` and
+/// `loggerd_uptime_seconds`.
+///
+/// # Returns
+///
+/// JSON object with current metrics, Prometheus text, or `400 Bad Request`
+/// for an unknown `format`
+async fn metrics_handler(
+    State(state): State<AppState>,
+    Query(query): Query<MetricsQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let requests = state.metrics.requests.load(Ordering::Relaxed);
+    let logs = state.metrics.log_count.load(Ordering::Relaxed);
+    let uptime = state.metrics.start.elapsed().as_secs();
+
+    match wants_prometheus(query.format.as_deref(), &headers) {
+        Err(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+        Ok(true) => {
+            let text = format!(
+                "# TYPE loggerd_requests_total counter\n\
+                 loggerd_requests_total {}\n\
+                 # TYPE loggerd_log_count_total counter\n\
+                 loggerd_log_count_total {}\n\
+                 # TYPE loggerd_uptime_seconds gauge\n\
+                 loggerd_uptime_seconds {}\n\
+                 {}",
+                requests,
+                logs,
+                uptime,
+                state.metrics.registry.render_prometheus()
+            );
+            ([(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)], text).into_response()
+        }
+        Ok(false) => {
+            let registry = state.metrics.registry.snapshot();
+            Json(json!({
+                "requests": requests,
+                "log_count": logs,
+                "uptime_seconds": uptime,
+                "status": "running",
+                "counters": registry["counters"],
+                "gauges": registry["gauges"],
+                "histograms": registry["histograms"]
+            }))
+            .into_response()
+        }
+    }
+}
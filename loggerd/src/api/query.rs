@@ -0,0 +1,178 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::AppState;
+use crate::trace::{RecordQuery, TermQuery, TraceLevel};
+
+/// Number of records returned when `limit` is not given.
+pub(super) const DEFAULT_LIMIT: usize = 100;
+
+/// Prefix of the field conditions in the query string (`?field.status=500`).
+const FIELD_PREFIX: &str = "field.";
+
+/// Query string shared by `GET /logs` and `GET /logs/recent`.
+///
+/// `field.<name>=<value>` parameters, read separately, only keep the records
+/// whose field `<name>` is exactly `<value>`.
+#[derive(Debug, Deserialize)]
+pub struct LogsQuery {
+    /// Oldest record, RFC 3339 (`?since=2025-10-14T17:00:00%2B02:00`)
+    since: Option<String>,
+    /// Newest record, RFC 3339
+    until: Option<String>,
+    /// Minimum level (`?level=warning`)
+    level: Option<TraceLevel>,
+    /// Exact source (`?source=billing`)
+    source: Option<String>,
+    /// Regular expression searched in the message (`?pattern=timeout`)
+    pattern: Option<String>,
+    /// Words of the source, message or fields (`?q=timeout db OR refused`)
+    q: Option<String>,
+    /// Maximum number of records (`?limit=20`)
+    pub limit: Option<usize>,
+    /// Only the records logged since this cursor (`/logs/recent` only)
+    pub after: Option<u64>,
+}
+
+impl LogsQuery {
+    /// Builds the record criteria from the query string and the `field.*`
+    /// parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message for an invalid timestamp, pattern or term query.
+    pub fn criteria(&self, params: Vec<(String, String)>) -> Result<RecordQuery, String> {
+        Ok(RecordQuery {
+            since: self.since.as_deref().map(parse_time).transpose()?,
+            until: self.until.as_deref().map(parse_time).transpose()?,
+            level: self.level,
+            source: self.source.clone(),
+            pattern: self
+                .pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| format!("invalid pattern: {}", e))?,
+            terms: self.q.as_deref().map(TermQuery::parse).transpose()?,
+            fields: params
+                .into_iter()
+                .filter_map(|(name, value)| {
+                    Some((name.strip_prefix(FIELD_PREFIX)?.to_string(), value))
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Parses an RFC 3339 timestamp.
+fn parse_time(value: &str) -> Result<DateTime<Local>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Local))
+        .map_err(|_| format!("invalid time '{}' (expected RFC 3339)", value))
+}
+
+/// Builds a `400 Bad Request` response with a JSON body.
+pub(super) fn bad_request(message: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+}
+
+/// HTTP handler for `GET /logs`.
+///
+/// Searches the log files, backups included, for the records matching the
+/// query string and returns the last `limit` ones (100 by default), oldest
+/// first. Without file output, the in-memory buffer of `/logs/recent` is
+/// searched instead.
+///
+/// ```text
+/// /logs?since=2025-10-14T17:00:00%2B02:00&level=error&pattern=timeout&field.status=504
+/// /logs?q=timeout%20db%20OR%20refused
+/// ```
+///
+/// `q` keeps the records containing all the words of one of its
+/// `OR`-separated alternatives; files with a token index answer it without
+/// being scanned.
+///
+/// # Returns
+///
+/// `{"searched": "files", "records": [...]}` (`"memory"` without file
+/// output), or `400 Bad Request` for an invalid time, level, pattern or `q`
+pub async fn logs_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LogsQuery>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Response {
+    let criteria = match query.criteria(params) {
+        Ok(criteria) => criteria,
+        Err(message) => return bad_request(message),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+
+    let (searched, records) = match state.log_files.clone() {
+        Some(files) => {
+            let found = tokio::task::spawn_blocking(move || files.search(&criteria, limit)).await;
+            ("files", found.unwrap_or_default())
+        }
+        None => (
+            "memory",
+            state
+                .recent
+                .recent_matching(limit, |record| criteria.matches(record)),
+        ),
+    };
+
+    Json(json!({
+        "searched": searched,
+        "records": records,
+    }))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(since: Option<&str>, pattern: Option<&str>) -> LogsQuery {
+        LogsQuery {
+            since: since.map(str::to_string),
+            until: None,
+            level: Some(TraceLevel::Warning),
+            source: None,
+            pattern: pattern.map(str::to_string),
+            q: None,
+            limit: None,
+            after: None,
+        }
+    }
+
+    #[test]
+    fn test_criteria() {
+        let params = vec![
+            ("field.status".to_string(), "500".to_string()),
+            ("level".to_string(), "warning".to_string()),
+        ];
+        let criteria = query(Some("2025-10-14T17:00:00+02:00"), Some("time(out)?"))
+            .criteria(params)
+            .unwrap();
+        assert_eq!(criteria.level, Some(TraceLevel::Warning));
+        assert_eq!(
+            criteria.since.unwrap().to_rfc3339(),
+            DateTime::parse_from_rfc3339("2025-10-14T15:00:00Z")
+                .unwrap()
+                .with_timezone(&Local)
+                .to_rfc3339()
+        );
+        assert_eq!(
+            criteria.fields,
+            vec![("status".to_string(), "500".to_string())]
+        );
+
+        assert!(query(Some("yesterday"), None).criteria(vec![]).is_err());
+        assert!(query(None, Some("(")).criteria(vec![]).is_err());
+    }
+}
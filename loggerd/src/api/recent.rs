@@ -0,0 +1,51 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use super::AppState;
+use super::query::{DEFAULT_LIMIT, LogsQuery, bad_request};
+
+/// HTTP handler for `GET /logs/recent`.
+///
+/// Serves the most recent records from memory, oldest first, without
+/// touching the log files. At most the capacity of the ring buffer can be
+/// returned. An unknown level is refused with `400 Bad Request`.
+///
+/// Records are selected with the query string of `GET /logs` (`level`,
+/// `source`, `pattern`, `since`, `until`, `field.<name>`):
+/// `/logs/recent?field.status=500&field.method=POST`.
+///
+/// The response carries a `cursor`: passed back as `?after=<cursor>`, only
+/// the records logged since are returned, oldest first, which lets clients
+/// follow the daemon by polling. The cursor stops at the first record not
+/// returned (see [`RingBufferTraceHandler::recent_after`]), so records
+/// beyond `limit` come with the next poll.
+///
+/// [`RingBufferTraceHandler::recent_after`]: crate::trace::RingBufferTraceHandler::recent_after
+///
+/// # Returns
+///
+/// `{"capacity": 1000, "cursor": 4242, "records": [{"timestamp", "level", "source", "message", "fields"}, ...]}`
+/// (`fields` only when the record has some)
+pub async fn recent_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LogsQuery>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Response {
+    let criteria = match query.criteria(params) {
+        Ok(criteria) => criteria,
+        Err(message) => return bad_request(message),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let (records, cursor) = state
+        .recent
+        .recent_after(query.after, limit, |record| criteria.matches(record));
+
+    Json(json!({
+        "capacity": state.recent.capacity(),
+        "cursor": cursor,
+        "records": records,
+    }))
+    .into_response()
+}
@@ -0,0 +1,844 @@
+//! Command-line client of the loggerd daemon.
+//!
+//! ```text
+//! loggerctl status
+//! loggerctl tail -f --level warning --field status=500
+//! loggerctl query --since 2h --pattern timeout -o json
+//! loggerctl query --terms "timeout db OR refused"
+//! loggerctl level set debug
+//! echo "backup done" | loggerctl send --source cron
+//! loggerctl verify /var/log/loggerd/audit.log --key change-me
+//! loggerctl export /var/log/loggerd/access.seg* --since 2h -o json
+//! ```
+//!
+//! The daemon is reached at `--url` (`LOGGERCTL_URL`, plain `http://` only),
+//! with the API key of `--api-key` (`LOGGERCTL_API_KEY`) when `[auth]` is
+//! configured. `verify` and `export` read the log files directly and need no
+//! daemon.
+//!
+//! # Exit codes
+//!
+//! - `0` - Success
+//! - `1` - The daemon answered with an error, or is not healthy (`status`),
+//!   or the hash chain is broken (`verify`), or a segment cannot be read
+//!   (`export`)
+//! - `2` - Invalid command line
+//! - `3` - The daemon cannot be reached
+
+use chrono::{DateTime, Duration as TimeDelta, Local};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use loggerd::http_client::{HttpEndpoint, HttpResponse};
+use loggerd::trace::file::{SegmentReader, verify_chain};
+use loggerd::trace::{TraceLevel, TraceRecord};
+use serde_json::{Value, json};
+use std::fmt::Write as _;
+use std::io::{BufRead, Write as _, stdin, stdout};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+/// Records fetched at most by each poll of `tail -f`.
+const FOLLOW_BATCH: usize = 10_000;
+
+#[derive(Parser)]
+#[command(
+    name = "loggerctl",
+    version,
+    about = "Command-line client of the loggerd daemon"
+)]
+struct Cli {
+    /// URL of the daemon
+    #[arg(
+        long,
+        env = "LOGGERCTL_URL",
+        default_value = "http://127.0.0.1:8080",
+        global = true
+    )]
+    url: String,
+
+    /// API key, when the daemon requires one
+    #[arg(long, env = "LOGGERCTL_API_KEY", hide_env_values = true, global = true)]
+    api_key: Option<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Output::Table, global = true)]
+    output: Output,
+
+    /// Request timeout, in seconds
+    #[arg(long, default_value_t = 10, global = true)]
+    timeout: u64,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Output format of the commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// Aligned columns, or log lines for records
+    Table,
+    /// JSON, one record per line for records
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Health, uptime and counters of the daemon
+    Status,
+    /// Every metric of the daemon
+    Metrics,
+    /// Most recent records, from the memory of the daemon
+    Tail {
+        /// Number of records shown first
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// Keep showing the new records
+        #[arg(short, long)]
+        follow: bool,
+        /// Polling interval of --follow, in milliseconds
+        #[arg(long, default_value_t = 500)]
+        interval_ms: u64,
+        #[command(flatten)]
+        filter: Filter,
+    },
+    /// Records searched in the log files, backups included
+    Query {
+        /// Oldest record: RFC 3339 time, or age such as 30s, 15m, 2h, 7d
+        #[arg(long)]
+        since: Option<String>,
+        /// Newest record: RFC 3339 time, or age such as 30s, 15m, 2h, 7d
+        #[arg(long)]
+        until: Option<String>,
+        /// Maximum number of records (the most recent ones)
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        #[command(flatten)]
+        filter: Filter,
+    },
+    /// Rotates the log files now
+    Rotate,
+    /// Minimum level of the daemon
+    Level {
+        #[command(subcommand)]
+        action: LevelAction,
+    },
+    /// Sends a record made of the arguments, or one record per line of stdin
+    Send {
+        /// Level of the records
+        #[arg(long, default_value = "info")]
+        level: TraceLevel,
+        /// Source of the records
+        #[arg(long, default_value = "loggerctl")]
+        source: String,
+        /// Field of the records, as name=value (repeatable)
+        #[arg(long = "field", value_name = "NAME=VALUE", value_parser = parse_field)]
+        fields: Vec<(String, String)>,
+        /// Message (read from stdin when absent)
+        message: Vec<String>,
+    },
+    /// Verifies the hash chain of a log file and of its backups
+    Verify {
+        /// Active log file of a target with `hash_chain = true`
+        path: PathBuf,
+        /// Key signing the checkpoints (`chain_key`)
+        #[arg(long, env = "LOGGERCTL_CHAIN_KEY", hide_env_values = true)]
+        key: Option<String>,
+    },
+    /// Exports binary segments as log lines, or as JSON lines with -o json
+    Export {
+        /// Segments, read in the given order
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Oldest record: RFC 3339 time, or age such as 30s, 15m, 2h, 7d
+        #[arg(long)]
+        since: Option<String>,
+        /// Newest record: RFC 3339 time, or age such as 30s, 15m, 2h, 7d
+        #[arg(long)]
+        until: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum LevelAction {
+    /// Shows the minimum level
+    Get,
+    /// Changes the minimum level (until the daemon restarts)
+    Set {
+        /// verbose, debug, info, warning, error, critical or none
+        level: TraceLevel,
+    },
+}
+
+/// Record selection shared by `tail` and `query`.
+#[derive(Args)]
+struct Filter {
+    /// Minimum level
+    #[arg(long)]
+    level: Option<TraceLevel>,
+    /// Exact source
+    #[arg(long)]
+    source: Option<String>,
+    /// Regular expression searched in the message
+    #[arg(long)]
+    pattern: Option<String>,
+    /// Words of the source, message or fields; OR separates alternatives
+    #[arg(long)]
+    terms: Option<String>,
+    /// Exact field value, as name=value (repeatable)
+    #[arg(long = "field", value_name = "NAME=VALUE", value_parser = parse_field)]
+    fields: Vec<(String, String)>,
+}
+
+impl Filter {
+    /// Returns the query string parameters of the filter.
+    fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        if let Some(level) = self.level {
+            params.push(("level".to_string(), level.as_str().to_string()));
+        }
+        if let Some(source) = &self.source {
+            params.push(("source".to_string(), source.clone()));
+        }
+        if let Some(pattern) = &self.pattern {
+            params.push(("pattern".to_string(), pattern.clone()));
+        }
+        if let Some(terms) = &self.terms {
+            params.push(("q".to_string(), terms.clone()));
+        }
+        for (name, value) in &self.fields {
+            params.push((format!("field.{}", name), value.clone()));
+        }
+        params
+    }
+}
+
+/// Failure of a command, mapped to the exit code.
+#[derive(Debug)]
+enum CtlError {
+    /// Invalid argument or input (exit code 2)
+    Usage(String),
+    /// The daemon answered with an error (exit code 1)
+    Failed(String),
+    /// The daemon cannot be reached (exit code 3)
+    Unreachable(String),
+}
+
+impl CtlError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CtlError::Failed(_) => 1,
+            CtlError::Usage(_) => 2,
+            CtlError::Unreachable(_) => 3,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            CtlError::Usage(message)
+            | CtlError::Failed(message)
+            | CtlError::Unreachable(message) => message,
+        }
+    }
+}
+
+/// Connection to the daemon.
+struct Client {
+    endpoint: HttpEndpoint,
+    api_key: Option<String>,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Sends a request; only connection failures are errors.
+    fn call(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<HttpResponse, CtlError> {
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let mut headers = vec![("Accept", "application/json")];
+        if !body.is_empty() {
+            headers.push(("Content-Type", "application/json"));
+        }
+        if let Some(key) = &self.api_key {
+            headers.push(("X-API-Key", key));
+        }
+        self.endpoint
+            .join(path)
+            .request(method, &headers, body.as_bytes(), self.timeout)
+            .map_err(|e| {
+                CtlError::Unreachable(format!(
+                    "cannot reach {}:{}: {}",
+                    self.endpoint.host, self.endpoint.port, e
+                ))
+            })
+    }
+
+    /// Sends a request and decodes the JSON of a 2xx response.
+    fn json(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, CtlError> {
+        let response = self.call(method, path, body)?;
+        if !response.is_success() {
+            return Err(CtlError::Failed(error_message(&response)));
+        }
+        serde_json::from_slice(&response.body)
+            .map_err(|e| CtlError::Failed(format!("invalid response from the daemon: {}", e)))
+    }
+}
+
+/// Describes an error response, using its `error` field when it is JSON.
+fn error_message(response: &HttpResponse) -> String {
+    let detail = serde_json::from_slice::<Value>(&response.body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| response.text().trim().to_string());
+    format!("HTTP {}: {}", response.status, detail)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("loggerctl: {}", e.message());
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+/// Runs the command of the command line.
+fn run(cli: &Cli) -> Result<ExitCode, CtlError> {
+    let client = Client {
+        endpoint: HttpEndpoint::parse(&cli.url).map_err(|e| CtlError::Usage(e.to_string()))?,
+        api_key: cli.api_key.clone(),
+        timeout: Duration::from_secs(cli.timeout),
+    };
+    let output = cli.output;
+
+    match &cli.command {
+        Command::Status => status(&client, output),
+        Command::Metrics => {
+            let metrics = client.json("GET", "/metrics", None)?;
+            match output {
+                Output::Json => print_json(&metrics),
+                Output::Table => print_table(&metric_rows(&metrics)),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Tail {
+            lines,
+            follow,
+            interval_ms,
+            filter,
+        } => tail(
+            &client,
+            output,
+            *lines,
+            follow.then(|| Duration::from_millis(*interval_ms)),
+            filter,
+        ),
+        Command::Query {
+            since,
+            until,
+            limit,
+            filter,
+        } => {
+            let now = Local::now();
+            let mut params = filter.params();
+            for (name, value) in [("since", since), ("until", until)] {
+                if let Some(value) = value {
+                    let time = parse_time(value, now).map_err(CtlError::Usage)?;
+                    params.push((name.to_string(), time.to_rfc3339()));
+                }
+            }
+            params.push(("limit".to_string(), limit.to_string()));
+
+            let found = client.json("GET", &format!("/logs{}", query_string(&params)), None)?;
+            print_records(&found["records"], output)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Rotate => rotate(&client, output),
+        Command::Verify { path, key } => verify(path, key.as_deref(), output),
+        Command::Export {
+            files,
+            since,
+            until,
+        } => {
+            let now = Local::now();
+            let since = since.as_deref().map(|value| parse_time(value, now));
+            let until = until.as_deref().map(|value| parse_time(value, now));
+            export(
+                files,
+                since.transpose().map_err(CtlError::Usage)?,
+                until.transpose().map_err(CtlError::Usage)?,
+                output,
+            )
+        }
+        Command::Level { action } => {
+            let level = match action {
+                LevelAction::Get => client.json("GET", "/admin/level", None)?,
+                LevelAction::Set { level } => client.json(
+                    "PUT",
+                    "/admin/level",
+                    Some(&json!({ "level": level.as_str() })),
+                )?,
+            };
+            match output {
+                Output::Json => print_json(&level),
+                Output::Table => match level["previous"].as_str() {
+                    Some(previous) => println!("{} -> {}", previous, text(&level["level"])),
+                    None => println!("{}", text(&level["level"])),
+                },
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Send {
+            level,
+            source,
+            fields,
+            message,
+        } => {
+            let messages = if message.is_empty() {
+                stdin()
+                    .lock()
+                    .lines()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| CtlError::Usage(format!("cannot read stdin: {}", e)))?
+                    .into_iter()
+                    .filter(|line| !line.trim().is_empty())
+                    .collect()
+            } else {
+                vec![message.join(" ")]
+            };
+            if messages.is_empty() {
+                return Err(CtlError::Usage("nothing to send".to_string()));
+            }
+
+            let fields: serde_json::Map<String, Value> = fields
+                .iter()
+                .map(|(name, value)| (name.clone(), json!(value)))
+                .collect();
+            let records: Vec<Value> = messages
+                .iter()
+                .map(|message| {
+                    json!({
+                        "level": level.as_str(),
+                        "source": source,
+                        "message": message,
+                        "fields": fields,
+                    })
+                })
+                .collect();
+            let accepted = client.json("POST", "/logs", Some(&Value::Array(records)))?;
+            match output {
+                Output::Json => print_json(&accepted),
+                Output::Table => println!("accepted {}", text(&accepted["accepted"])),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// `loggerctl verify`: fails (exit code 1) at the first broken link.
+fn verify(path: &Path, key: Option<&str>, output: Output) -> Result<ExitCode, CtlError> {
+    let report = verify_chain(path, key)
+        .map_err(|e| CtlError::Failed(format!("cannot read {}: {}", path.display(), e)))?;
+    match output {
+        Output::Json => print_json(&json!(report)),
+        Output::Table => {
+            for file in &report.unchained {
+                println!("{}: not chained, skipped", file.display());
+            }
+            println!(
+                "{} files, {} records, {} checkpoints{}",
+                report.files.len(),
+                report.records,
+                report.checkpoints,
+                if report.signatures_checked {
+                    " (signatures checked)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+    match &report.broken {
+        Some(broken) => {
+            eprintln!(
+                "loggerctl: chain broken at {}:{}: {}",
+                broken.file.display(),
+                broken.line,
+                broken.reason
+            );
+            Ok(ExitCode::from(1))
+        }
+        None => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// `loggerctl export`: prints the records of each segment in the time range;
+/// fails (exit code 1) if a segment cannot be read, after the other ones.
+fn export(
+    files: &[PathBuf],
+    since: Option<DateTime<Local>>,
+    until: Option<DateTime<Local>>,
+    output: Output,
+) -> Result<ExitCode, CtlError> {
+    let mut failed = false;
+    let mut out = stdout().lock();
+    // Stops printing once stdout is closed (`loggerctl export ... | head`)
+    let mut closed = false;
+    for file in files {
+        let exported = SegmentReader::open(file).and_then(|mut reader| {
+            reader.read_range(since, until, |record| {
+                let written = match output {
+                    Output::Json => writeln!(out, "{}", json!(record)),
+                    Output::Table => writeln!(out, "{}", record),
+                };
+                closed |= written.is_err();
+            })
+        });
+        if closed {
+            break;
+        }
+        match exported {
+            Ok(0) => {}
+            Ok(corrupted) => eprintln!(
+                "loggerctl: {}: {} corrupted records skipped",
+                file.display(),
+                corrupted
+            ),
+            Err(e) => {
+                eprintln!("loggerctl: {}: {}", file.display(), e);
+                failed = true;
+            }
+        }
+    }
+    Ok(if failed {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// `loggerctl status`: fails (exit code 1) unless the daemon reports "OK".
+fn status(client: &Client, output: Output) -> Result<ExitCode, CtlError> {
+    let health = client.call("GET", "/health", None)?;
+    let report = health.text();
+    if !health.is_success() && !report.starts_with("DEGRADED") {
+        return Err(CtlError::Failed(error_message(&health)));
+    }
+    let healthy = health.is_success() && report.trim() == "OK";
+    let metrics = client.json("GET", "/metrics", None)?;
+
+    match output {
+        Output::Json => print_json(&json!({
+            "healthy": healthy,
+            "health": report.trim(),
+            "uptime_seconds": metrics["uptime_seconds"],
+            "requests": metrics["requests"],
+            "log_count": metrics["log_count"],
+        })),
+        Output::Table => {
+            let mut lines = report.lines();
+            let uptime = metrics["uptime_seconds"].as_u64().unwrap_or(0);
+            print_table(&[
+                ("status".to_string(), lines.next().unwrap_or("").to_string()),
+                ("uptime".to_string(), format_uptime(uptime)),
+                ("requests".to_string(), text(&metrics["requests"])),
+                ("log_count".to_string(), text(&metrics["log_count"])),
+            ]);
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+    }
+    Ok(if healthy {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// `loggerctl tail`: shows the last records, then polls the new ones when
+/// `follow` is given.
+fn tail(
+    client: &Client,
+    output: Output,
+    lines: usize,
+    follow: Option<Duration>,
+    filter: &Filter,
+) -> Result<ExitCode, CtlError> {
+    let mut params = filter.params();
+    params.push(("limit".to_string(), lines.to_string()));
+    let mut recent = client.json(
+        "GET",
+        &format!("/logs/recent{}", query_string(&params)),
+        None,
+    )?;
+    print_records(&recent["records"], output)?;
+
+    let Some(interval) = follow else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    let mut params = filter.params();
+    params.push(("limit".to_string(), FOLLOW_BATCH.to_string()));
+    loop {
+        let cursor = recent["cursor"].as_u64().unwrap_or(0);
+        thread::sleep(interval);
+        let mut page = params.clone();
+        page.push(("after".to_string(), cursor.to_string()));
+        recent = client.json("GET", &format!("/logs/recent{}", query_string(&page)), None)?;
+        print_records(&recent["records"], output)?;
+    }
+}
+
+/// `loggerctl rotate`: fails (exit code 1) if a file could not be rotated.
+fn rotate(client: &Client, output: Output) -> Result<ExitCode, CtlError> {
+    let response = client.call("POST", "/admin/rotate", None)?;
+    let outcome: Value = match serde_json::from_slice(&response.body) {
+        Ok(outcome) if response.is_success() || response.status == 500 => outcome,
+        _ => return Err(CtlError::Failed(error_message(&response))),
+    };
+
+    match output {
+        Output::Json => print_json(&outcome),
+        Output::Table => {
+            for rotated in outcome["rotated"].as_array().into_iter().flatten() {
+                match rotated["backup"].as_str() {
+                    Some(backup) => println!("{} -> {}", text(&rotated["file"]), backup),
+                    None => println!("{} (empty, not rotated)", text(&rotated["file"])),
+                }
+            }
+        }
+    }
+    let errors = outcome["errors"].as_array().cloned().unwrap_or_default();
+    for error in &errors {
+        eprintln!(
+            "loggerctl: {}: {}",
+            text(&error["file"]),
+            text(&error["error"])
+        );
+    }
+    Ok(if errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// Prints records as log lines, or as JSON lines.
+fn print_records(records: &Value, output: Output) -> Result<(), CtlError> {
+    for record in records.as_array().into_iter().flatten() {
+        match output {
+            Output::Json => println!("{}", record),
+            Output::Table => {
+                let record: TraceRecord = serde_json::from_value(record.clone()).map_err(|e| {
+                    CtlError::Failed(format!("invalid record from the daemon: {}", e))
+                })?;
+                println!("{}", record);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints a JSON document, indented.
+fn print_json(value: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).unwrap_or_default()
+    );
+}
+
+/// Prints name/value rows with the values aligned.
+fn print_table(rows: &[(String, String)]) {
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, value) in rows {
+        println!("{:width$}  {}", name, value, width = width);
+    }
+}
+
+/// Returns the rows of `loggerctl metrics`: `name{label="value"}` and value.
+fn metric_rows(metrics: &Value) -> Vec<(String, String)> {
+    let mut rows: Vec<(String, String)> = ["requests", "log_count", "uptime_seconds"]
+        .iter()
+        .map(|name| (name.to_string(), text(&metrics[name])))
+        .collect();
+
+    for kind in ["counters", "gauges", "histograms"] {
+        for metric in metrics[kind].as_array().into_iter().flatten() {
+            let mut name = text(&metric["name"]);
+            if let Some(labels) = metric["labels"].as_object().filter(|l| !l.is_empty()) {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(label, value)| format!("{}=\"{}\"", label, text(value)))
+                    .collect();
+                let _ = write!(name, "{{{}}}", labels.join(","));
+            }
+            let value = if kind == "histograms" {
+                format!(
+                    "count={} sum={}",
+                    text(&metric["count"]),
+                    text(&metric["sum"])
+                )
+            } else {
+                text(&metric["value"])
+            };
+            rows.push((name, value));
+        }
+    }
+    rows
+}
+
+/// Returns a JSON value as text, strings without their quotes.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Formats a number of seconds as `2d 03h 04m 05s`.
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours) = (seconds / 86_400, seconds / 3_600 % 24);
+    let (minutes, seconds) = (seconds / 60 % 60, seconds % 60);
+    if days > 0 {
+        format!("{}d {:02}h {:02}m {:02}s", days, hours, minutes, seconds)
+    } else {
+        format!("{:02}h {:02}m {:02}s", hours, minutes, seconds)
+    }
+}
+
+/// Parses an RFC 3339 time, or an age (`30s`, `15m`, `2h`, `7d`) before `now`.
+fn parse_time(value: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let invalid = || {
+        format!(
+            "invalid time '{}' (expected RFC 3339 or an age such as 15m)",
+            value
+        )
+    };
+    let unit_at = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let amount: i64 = value[..unit_at].parse().map_err(|_| invalid())?;
+    let age = match &value[unit_at..] {
+        "s" => TimeDelta::seconds(amount),
+        "m" => TimeDelta::minutes(amount),
+        "h" => TimeDelta::hours(amount),
+        "d" => TimeDelta::days(amount),
+        _ => return Err(invalid()),
+    };
+    Ok(now - age)
+}
+
+/// Parses a `name=value` field argument.
+fn parse_field(argument: &str) -> Result<(String, String), String> {
+    match argument.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{}'", argument)),
+    }
+}
+
+/// Builds a query string (`?a=1&b=x%2By`), or nothing without parameters.
+fn query_string(params: &[(String, String)]) -> String {
+    let encode = |text: &str| {
+        let mut encoded = String::new();
+        for byte in text.bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+        encoded
+    };
+    let pairs: Vec<String> = params
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect();
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", pairs.join("&"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        let now = Local::now();
+        assert_eq!(
+            parse_time("15m", now).unwrap(),
+            now - TimeDelta::minutes(15)
+        );
+        assert_eq!(parse_time("2d", now).unwrap(), now - TimeDelta::days(2));
+        assert_eq!(
+            parse_time("2025-10-14T17:00:00+02:00", now)
+                .unwrap()
+                .timestamp(),
+            DateTime::parse_from_rfc3339("2025-10-14T15:00:00Z")
+                .unwrap()
+                .timestamp()
+        );
+        for invalid in ["", "m", "15", "15w", "yesterday"] {
+            assert!(parse_time(invalid, now).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_query_string() {
+        assert_eq!(query_string(&[]), "");
+        let params = vec![
+            ("since".to_string(), "2025-10-14T17:00:00+02:00".to_string()),
+            ("field.path".to_string(), "/pay me".to_string()),
+        ];
+        assert_eq!(
+            query_string(&params),
+            "?since=2025-10-14T17%3A00%3A00%2B02%3A00&field.path=%2Fpay%20me"
+        );
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(
+            parse_field("status=500").unwrap(),
+            ("status".to_string(), "500".to_string())
+        );
+        assert!(parse_field("=500").is_err());
+        assert!(parse_field("status").is_err());
+
+        let cli = Cli::try_parse_from([
+            "loggerctl",
+            "tail",
+            "-f",
+            "--level",
+            "warn",
+            "--field",
+            "a=b",
+            "-o",
+            "json",
+        ])
+        .unwrap();
+        assert_eq!(cli.output, Output::Json);
+        let Command::Tail { follow, filter, .. } = cli.command else {
+            panic!("not tail");
+        };
+        assert!(follow);
+        assert_eq!(
+            filter.params(),
+            vec![
+                ("level".to_string(), "WARNING".to_string()),
+                ("field.a".to_string(), "b".to_string())
+            ]
+        );
+        assert!(Cli::try_parse_from(["loggerctl", "level", "set", "loud"]).is_err());
+        assert_eq!(format_uptime(93_784), "1d 02h 03m 04s");
+    }
+}
@@ -0,0 +1,432 @@
+//! Daemon configuration loaded from a TOML file.
+//!
+//! Every section is optional: an empty (or missing) configuration file gives
+//! the historical behaviour of loggerd.
+//!
+//! The configuration file is looked up, in order, from:
+//! 1. The `--config <path>` command-line argument
+//! 2. The `LOGGERD_CONFIG` environment variable
+//!
+//! # Example
+//!
+//! ```toml
+//! [server]
+//! bind = "127.0.0.1:8080"
+//!
+//! [routing.default]
+//! path = "/var/log/loggerd/loggerd.log"
+//!
+//! [[routing.routes]]
+//! name = "billing"
+//! sources = ["billing"]
+//! path = "/var/log/loggerd/billing.log"
+//!
+//! [forward]
+//! url = "http://collector.example:8080/logs"
+//! spool_dir = "/var/spool/loggerd"
+//! ```
+
+use serde::Deserialize;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::alert::AlertConfig;
+use crate::api::{AuthConfig, ServerConfig};
+use crate::tail::TailConfig;
+use crate::tls::TlsConfig;
+use crate::trace::RingBufferConfig;
+use crate::trace::file::{DiskGuardConfig, RoutingConfig};
+use crate::trace::forward::ForwardConfig;
+use crate::trace::stage::{
+    DedupConfig, LogMetricConfig, ParserConfig, RateLimitConfig, RedactionConfig,
+};
+
+/// Environment variable holding the configuration file path.
+pub const CONFIG_ENV: &str = "LOGGERD_CONFIG";
+
+/// Root of the loggerd configuration file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggerdConfig {
+    /// HTTP server settings
+    pub server: ServerConfig,
+    /// File outputs: catch-all file and per-source routes
+    pub routing: RoutingConfig,
+    /// Free space watchdog of the log directories (disabled when absent)
+    pub disk_guard: Option<DiskGuardConfig>,
+    /// In-memory buffer of recent records
+    pub ring_buffer: RingBufferConfig,
+    /// Forwarding to an upstream collector (disabled when absent)
+    pub forward: Option<ForwardConfig>,
+    /// Extraction of structured fields from messages (`[[parsers]]` entries)
+    pub parsers: Vec<ParserConfig>,
+    /// Masking of secrets and personal data (disabled when absent)
+    pub redaction: Option<RedactionConfig>,
+    /// Collapsing of repeated records (disabled when absent)
+    pub dedup: Option<DedupConfig>,
+    /// Per-source rate limiting (disabled when absent)
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Metrics derived from the records (`[[log_metrics]]` entries)
+    pub log_metrics: Vec<LogMetricConfig>,
+    /// Alert rules and webhooks (disabled when absent)
+    pub alerts: Option<AlertConfig>,
+    /// Log files of other applications to follow (disabled when absent)
+    pub tail: Option<TailConfig>,
+    /// API keys of the HTTP endpoints (no authentication when absent)
+    pub auth: Option<AuthConfig>,
+    /// HTTPS listener (plain HTTP when absent)
+    pub tls: Option<TlsConfig>,
+}
+
+impl LoggerdConfig {
+    /// Loads and parses a configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or `ErrorKind::InvalidData`
+    /// if it is not valid TOML for this schema.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parses a configuration from TOML text.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        toml::from_str(content).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Loads the configuration designated by the command line or environment,
+    /// falling back to the defaults when none is given.
+    pub fn from_args_or_env() -> Result<Self, Error> {
+        match config_path() {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Returns the configuration path from `--config <path>` or `LOGGERD_CONFIG`.
+fn config_path() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    std::env::var_os(CONFIG_ENV).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Role;
+    use crate::tail::StartPosition;
+    use crate::trace::TraceLevel;
+    use crate::trace::stage::{LogMetricKind, ParserKind};
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = LoggerdConfig::parse("").unwrap();
+        assert!(config.forward.is_none());
+        assert_eq!(config.routing.default.path, "loggerd.log");
+        assert!(config.routing.routes.is_empty());
+        assert!(config.server.access_log);
+        assert!(config.routing.enabled);
+        assert_eq!(config.ring_buffer.capacity, 1000);
+        assert!(config.log_metrics.is_empty());
+    }
+
+    #[test]
+    fn test_routing_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [routing.default]
+            path = "all.log"
+
+            [[routing.routes]]
+            name = "billing"
+            sources = ["billing"]
+            min_level = "warning"
+            path = "billing.log"
+            max_backups = 2
+            "#,
+        )
+        .unwrap();
+
+        let route = &config.routing.routes[0];
+        assert_eq!(config.routing.default.path, "all.log");
+        assert_eq!(route.sources, vec!["billing"]);
+        assert_eq!(route.min_level, Some(crate::trace::TraceLevel::Warning));
+        assert_eq!(route.target.path, "billing.log");
+        assert_eq!(route.target.max_backups, 2);
+        assert_eq!(route.target.max_size_bytes, 10 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_forward_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [forward]
+            url = "http://collector:9000/logs"
+            max_spool_bytes = 2048
+            "#,
+        )
+        .unwrap();
+
+        let forward = config.forward.unwrap();
+        assert_eq!(forward.url, "http://collector:9000/logs");
+        assert_eq!(forward.max_spool_bytes, 2048);
+        assert_eq!(forward.batch_size, 500);
+    }
+
+    #[test]
+    fn test_rate_limit_section() {
+        let config = LoggerdConfig::parse("[rate_limit]\nrecords_per_second = 5.0").unwrap();
+        let rate_limit = config.rate_limit.unwrap();
+        assert_eq!(rate_limit.records_per_second, 5.0);
+        assert_eq!(rate_limit.burst, 200);
+        assert_eq!(rate_limit.exempt_sources, vec!["loggerd"]);
+    }
+
+    #[test]
+    fn test_dedup_section() {
+        assert!(LoggerdConfig::parse("").unwrap().dedup.is_none());
+        let config = LoggerdConfig::parse("[dedup]").unwrap();
+        assert_eq!(config.dedup.unwrap().window_secs, 30);
+    }
+
+    #[test]
+    fn test_redaction_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [redaction]
+            builtin = ["password"]
+
+            [[redaction.patterns]]
+            name = "iban"
+            regex = "FR\\d{2}"
+            "#,
+        )
+        .unwrap();
+
+        let redaction = config.redaction.unwrap();
+        assert_eq!(redaction.mask, "[REDACTED]");
+        assert_eq!(redaction.builtin, vec!["password"]);
+        assert_eq!(redaction.patterns[0].regex, r"FR\d{2}");
+    }
+
+    #[test]
+    fn test_log_metrics_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [[log_metrics]]
+            name = "refused_total"
+            type = "counter"
+            pattern = "connection refused"
+            min_level = "warning"
+
+            [[log_metrics]]
+            name = "query_ms"
+            type = "histogram"
+            pattern = "took (?P<value>\\d+)ms"
+            buckets = [10, 100]
+            "#,
+        )
+        .unwrap();
+
+        let rules = config.log_metrics;
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].kind, LogMetricKind::Counter);
+        assert_eq!(rules[0].min_level, Some(TraceLevel::Warning));
+        assert_eq!(rules[1].kind, LogMetricKind::Histogram);
+        assert_eq!(rules[1].value, "value");
+        assert_eq!(rules[1].buckets, vec![10.0, 100.0]);
+        assert!(
+            LoggerdConfig::parse(
+                "[[log_metrics]]
+name = \"x\"
+type = \"gauge\"
+pattern = \"x\""
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parsers_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [[parsers]]
+            name = "nginx"
+            type = "regex"
+            sources = ["nginx"]
+            pattern = '"(?P<method>\w+) (?P<path>\S+)'
+
+            [[parsers]]
+            name = "json"
+            type = "json"
+            message_field = "msg"
+            "#,
+        )
+        .unwrap();
+
+        let parsers = config.parsers;
+        assert_eq!(parsers[0].kind, ParserKind::Regex);
+        assert_eq!(
+            parsers[0].pattern.as_deref(),
+            Some(r#""(?P<method>\w+) (?P<path>\S+)"#)
+        );
+        assert_eq!(parsers[1].kind, ParserKind::Json);
+        assert_eq!(parsers[1].message_field, "msg");
+        assert_eq!(parsers[1].level_field, "level");
+        assert!(parsers[1].timestamp_format.is_none());
+    }
+
+    #[test]
+    fn test_disk_guard_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [disk_guard]
+            shed_below_percent = 8.0
+            shed_min_level = "critical"
+            "#,
+        )
+        .unwrap();
+
+        let guard = config.disk_guard.unwrap();
+        assert_eq!(guard.check_interval_secs, 10);
+        assert_eq!(guard.cleanup_below_percent, 15.0);
+        assert_eq!(guard.shed_below_percent, 8.0);
+        assert_eq!(guard.shed_min_level, TraceLevel::Critical);
+        assert!(LoggerdConfig::parse("[disk_guard]\npause_below = 1").is_err());
+    }
+
+    #[test]
+    fn test_alerts_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [alerts]
+            webhooks = ["http://127.0.0.1:9000/hook"]
+
+            [[alerts.rules]]
+            name = "critical"
+            min_level = "critical"
+
+            [[alerts.rules]]
+            name = "billing-errors"
+            sources = ["billing"]
+            threshold = 5
+            window_secs = 120
+            "#,
+        )
+        .unwrap();
+
+        let alerts = config.alerts.unwrap();
+        assert_eq!(alerts.timeout_ms, 5000);
+        assert_eq!(alerts.rules[0].threshold, 1);
+        assert_eq!(alerts.rules[0].window_secs, 60);
+        assert_eq!(alerts.rules[0].min_level, Some(TraceLevel::Critical));
+        assert_eq!(alerts.rules[1].threshold, 5);
+        assert_eq!(alerts.rules[1].cooldown_secs, 300);
+        // A rule needs a name
+        assert!(LoggerdConfig::parse("[[alerts.rules]]\nthreshold = 2").is_err());
+    }
+
+    #[test]
+    fn test_tail_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [tail]
+            state_file = "/var/lib/loggerd/tail.json"
+
+            [[tail.files]]
+            path = "/var/log/legacy/app.log"
+            multiline_start = "^\\d{4}-"
+            start_at = "beginning"
+            "#,
+        )
+        .unwrap();
+
+        let tail = config.tail.unwrap();
+        assert_eq!(tail.poll_interval_ms, 250);
+        let file = &tail.files[0];
+        assert_eq!(file.path, PathBuf::from("/var/log/legacy/app.log"));
+        assert!(file.source.is_none());
+        assert_eq!(file.level, TraceLevel::Info);
+        assert_eq!(file.start_at, StartPosition::Beginning);
+        assert!(LoggerdConfig::parse("[[tail.files]]\nsource = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_auth_section() {
+        let config = LoggerdConfig::parse(
+            r#"
+            [auth]
+            public_health = false
+
+            [[auth.keys]]
+            name = "app"
+            key = "secret"
+            roles = ["ingest", "read"]
+            "#,
+        )
+        .unwrap();
+
+        let auth = config.auth.unwrap();
+        assert!(!auth.public_health);
+        assert_eq!(auth.keys[0].roles, vec![Role::Ingest, Role::Read]);
+        assert!(
+            LoggerdConfig::parse("[[auth.keys]]\nname = \"a\"\nkey = \"b\"\nroles = [\"root\"]")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tls_section() {
+        let config =
+            LoggerdConfig::parse("[tls]\ncert_path = \"s.crt\"\nkey_path = \"s.key\"").unwrap();
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.cert_path, PathBuf::from("s.crt"));
+        assert!(tls.client_ca_path.is_none());
+
+        // Both paths are required
+        assert!(LoggerdConfig::parse("[tls]\ncert_path = \"s.crt\"").is_err());
+    }
+
+    #[test]
+    fn test_server_section() {
+        assert_eq!(LoggerdConfig::default().server.bind.port(), 8080);
+
+        let config =
+            LoggerdConfig::parse("[server]\nbind = \"127.0.0.1:0\"\naccess_log = false").unwrap();
+        assert_eq!(config.server.bind.to_string(), "127.0.0.1:0");
+        assert!(!config.server.access_log);
+        assert!(LoggerdConfig::parse("[server]\nbind = \"localhost\"").is_err());
+    }
+
+    #[test]
+    fn test_unknown_section_is_rejected() {
+        assert!(LoggerdConfig::parse("[forwarding]\nurl = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_misspelled_key_is_rejected() {
+        for content in [
+            "[rate_limit]\nrecords_per_sec = 10.0",
+            "[dedup]\nwindow = 30",
+            "[redaction]\nmask_with = \"*\"",
+            "[forward]\nendpoint = \"http://x\"",
+            "[routing]\nenable = true",
+        ] {
+            assert!(LoggerdConfig::parse(content).is_err(), "{}", content);
+        }
+        // Route keys are still accepted next to the flattened file target
+        let route = "[[routing.routes]]\nname = \"db\"\nsources = [\"db\"]\npath = \"db.log\"";
+        assert!(LoggerdConfig::parse(route).is_ok());
+    }
+}
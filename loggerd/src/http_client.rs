@@ -0,0 +1,313 @@
+//! Minimal blocking HTTP/1.1 client.
+//!
+//! The daemon only needs to POST small payloads to plain `http://` endpoints
+//! from its background threads (log forwarding), so this module talks
+//! HTTP/1.1 directly over a `TcpStream` instead of pulling in an async client
+//! and a second runtime. `loggerctl` uses it too, to talk to the daemon.
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// An HTTP endpoint parsed from an `http://host:port/path` URL.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::http_client::HttpEndpoint;
+///
+/// let endpoint = HttpEndpoint::parse("http://collector:9000/logs").unwrap();
+/// assert_eq!(endpoint.host, "collector");
+/// assert_eq!(endpoint.port, 9000);
+/// assert_eq!(endpoint.path, "/logs");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpEndpoint {
+    /// Host name or IP address
+    pub host: String,
+    /// TCP port (defaults to 80)
+    pub port: u16,
+    /// Request path, including any query string
+    pub path: String,
+}
+
+impl HttpEndpoint {
+    /// Parses an `http://` URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the scheme is not `http` or the
+    /// port is not a valid number.
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported URL '{}': only http:// is supported", url),
+            )
+        })?;
+
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse::<u16>().map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid port in '{}'", url),
+                    )
+                })?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+
+        if host.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("missing host in '{}'", url),
+            ));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Returns the endpoint of `path` below this one's path
+    /// (`http://host/api` and `/logs` give `http://host/api/logs`).
+    pub fn join(&self, path: &str) -> Self {
+        Self {
+            path: format!("{}{}", self.path.trim_end_matches('/'), path),
+            ..self.clone()
+        }
+    }
+
+    /// Sends a POST request and returns the response status code.
+    ///
+    /// The connection is closed after each request (`Connection: close`) and
+    /// the response body is not read.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - Value of the `Content-Type` header
+    /// * `body` - Request body
+    /// * `timeout` - Connect, read and write timeout
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host cannot be reached or the response is not
+    /// a valid HTTP status line. Non-2xx statuses are *not* errors.
+    pub fn post(&self, content_type: &str, body: &[u8], timeout: Duration) -> Result<u16> {
+        let stream = self.send("POST", &[("Content-Type", content_type)], body, timeout)?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        parse_status_line(&status_line)
+    }
+
+    /// Sends a request and reads the whole response.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - `GET`, `POST`, `PUT`, ...
+    /// * `headers` - Additional headers (`Content-Type`, `X-API-Key`, ...)
+    /// * `body` - Request body, possibly empty
+    /// * `timeout` - Connect, read and write timeout
+    ///
+    /// # Errors
+    ///
+    /// Same as [`post`](Self::post); non-2xx statuses are *not* errors.
+    pub fn request(
+        &self,
+        method: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<HttpResponse> {
+        let stream = self.send(method, headers, body, timeout)?;
+        read_response(&mut BufReader::new(stream))
+    }
+
+    /// Connects and writes a request, with `Connection: close`.
+    fn send(
+        &self,
+        method: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<TcpStream> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                Error::new(ErrorKind::NotFound, format!("cannot resolve {}", self.host))
+            })?;
+
+        let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            method,
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        );
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+/// Status and body of a response read by [`HttpEndpoint::request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: u16,
+    /// Body, with the chunked transfer encoding removed
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Returns `true` for a 2xx status.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Returns the body as text (invalid UTF-8 is replaced).
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Reads a response: status line, headers and body (delimited by
+/// `Content-Length`, the chunked encoding, or the end of the connection).
+fn read_response(reader: &mut impl BufRead) -> Result<HttpResponse> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = parse_status_line(&line)?;
+
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bad chunk size '{}'", line.trim()),
+                )
+            })?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            line.clear();
+            reader.read_line(&mut line)?; // CRLF after the chunk
+        }
+    } else if let Some(length) = content_length {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok(HttpResponse { status, body })
+}
+
+/// Extracts the status code from a line such as `HTTP/1.1 200 OK`.
+fn parse_status_line(line: &str) -> Result<u16> {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/") => code.parse().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("bad status line '{}'", line.trim()),
+            )
+        }),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("bad status line '{}'", line.trim()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let endpoint = HttpEndpoint::parse("http://127.0.0.1:8080/logs?batch=1").unwrap();
+        assert_eq!(endpoint.host, "127.0.0.1");
+        assert_eq!(endpoint.port, 8080);
+        assert_eq!(endpoint.path, "/logs?batch=1");
+
+        let endpoint = HttpEndpoint::parse("http://example.com").unwrap();
+        assert_eq!(endpoint.port, 80);
+        assert_eq!(endpoint.path, "/");
+        assert_eq!(endpoint.join("/health").path, "/health");
+        let endpoint = HttpEndpoint::parse("http://example.com/loggerd/").unwrap();
+        assert_eq!(endpoint.join("/logs?limit=5").path, "/loggerd/logs?limit=5");
+
+        assert!(HttpEndpoint::parse("https://example.com/").is_err());
+        assert!(HttpEndpoint::parse("http://example.com:http/").is_err());
+    }
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(
+            parse_status_line("HTTP/1.1 204 No Content\r\n").unwrap(),
+            204
+        );
+        assert!(parse_status_line("garbage").is_err());
+    }
+
+    #[test]
+    fn test_read_response() {
+        let raw = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nOKtrailing";
+        let response = read_response(&mut &raw[..]).unwrap();
+        assert_eq!(response.text(), "OK");
+        assert!(response.is_success());
+
+        let raw = b"HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    4\r\nno s\r\nB;ext=1\r\nuch record\n\r\n0\r\n\r\n";
+        let response = read_response(&mut &raw[..]).unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.text(), "no such record\n");
+
+        let raw = b"HTTP/1.1 204 No Content\r\n\r\n";
+        assert!(read_response(&mut &raw[..]).unwrap().body.is_empty());
+    }
+}
@@ -5,5 +5,29 @@
 //! This library provides the core functionality for the loggerd daemon, including
 //! trace management, file handlers with automatic rotation, and metric collection.
 
+/// Alert rules on log records, with webhook notifications.
+pub mod alert;
+
+/// HTTP API: router, shared state and endpoints.
+pub mod api;
+
+/// Daemon configuration (TOML file).
+pub mod config;
+
+/// Minimal blocking HTTP client used by background threads.
+pub mod http_client;
+
+/// Shared registry of labelled counters and gauges.
+pub mod metrics;
+
+/// Startup of a complete daemon, returning a handle to stop it.
+pub mod server;
+
+/// File tailing input: follows the log files of other applications.
+pub mod tail;
+
+/// TLS (and mutual TLS) for the HTTP listener.
+pub mod tls;
+
 /// Trace management module with file rotation and multiple output handlers.
 pub mod trace;
@@ -1,161 +1,161 @@
-use axum::{Json, Router, extract::State, routing::get};
-use serde_json::json;
-use std::net::SocketAddr;
+use loggerd::config::LoggerdConfig;
+use loggerd::server::{self, ShutdownTrigger};
+use loggerd::tls::ReloadableTls;
+use loggerd::trace::file::LogFiles;
+use loggerd::trace::{Trace, TraceLevel, install_panic_hook};
+use loggerd::{trace_error, trace_info};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::Instant;
-use tokio::net::TcpListener;
 use tokio::signal::unix::{SignalKind, signal};
 
-mod trace;
-use trace::{Trace, TraceLevel};
-
-/// Shared state for application metrics.
-///
-/// Maintains counters and timing information for the HTTP API endpoints
-/// and integrates with the trace system for logging.
-#[derive(Clone)]
-struct AppState {
-    /// Application metrics counters
-    metrics: Arc<MetricsState>,
-    /// Shared trace system for logging
-    trace: Arc<dyn Trace + Send + Sync>,
-}
-
-/// Internal metrics state with atomic counters.
-///
-/// Thread-safe metrics collection using atomic operations to avoid
-/// blocking during concurrent access from multiple request handlers.
-struct MetricsState {
-    /// Total number of HTTP requests processed
-    requests: AtomicU64,
-    /// Total number of log messages written (shared with file handler)
-    log_count: Arc<AtomicU64>,
-    /// Application start time for uptime calculation
-    start: Instant,
-}
-
 /// Main entry point for the loggerd daemon.
 ///
-/// Initializes the trace system with console and file handlers (with rotation),
-/// sets up HTTP API endpoints for health checks and metrics, and runs the server
-/// with graceful shutdown support.
+/// Loads the configuration (`--config <path>` or `LOGGERD_CONFIG`) and starts
+/// the daemon with [`server::start`]: the trace system with console and file
+/// handlers (with rotation) and optional forwarding, the HTTP API endpoints
+/// for health checks, metrics and log ingestion, and the following of the
+/// configured log files of other applications. The server listens on the
+/// `bind` address of the `[server]` section (`0.0.0.0:8080` by default).
 ///
 /// # HTTP Endpoints
 ///
-/// - `GET /health` - Health check endpoint (returns "OK")
+/// - `GET /health` - Health check endpoint (returns "OK" unless disk space is low)
 /// - `GET /metrics` - JSON metrics including request count, log count, and uptime
+/// - `POST /logs` - Ingest records from other applications
+/// - `GET /logs` - Records searched in the log files
+/// - `GET /logs/recent` - Most recent records, from memory
+/// - `GET /alerts` - State of the alert rules
+/// - `GET /admin/files`, `GET /admin/files/{name}`, `POST /admin/rotate` -
+///   Log file listing, download and forced rotation
+/// - `GET /admin/level`, `PUT /admin/level` - Minimum level of the trace system
+///
+/// Endpoints require an API key when the `[auth]` section is configured, and
+/// are served over HTTPS when the `[tls]` section is.
+///
+/// Panics of any task or thread are reported as Critical records with the
+/// `panic` source, see [`install_panic_hook`].
+///
+/// # Signals
+///
+/// The daemon shuts down gracefully on SIGTERM: it stops accepting records,
+/// drains the queues of the handlers to disk within `drain_timeout_ms`, and
+/// reports how many records were flushed or lost. SIGHUP reloads the TLS
+/// certificates and SIGUSR1 rotates the log files.
 ///
-/// # Graceful Shutdown
+/// # Exit status
 ///
-/// The daemon listens for SIGTERM and SIGHUP signals and shuts down gracefully,
-/// ensuring all pending logs are written and resources are cleaned up.
+/// - `0` - Every queued record was written and synced
+/// - `1` - Records were lost, or a file could not be synced, at shutdown
 #[tokio::main]
 async fn main() {
-    // Initialize trace system (console + file with rotation)
-    let (trace_system, log_count) =
-        trace::create_trace().expect("Failed to initialize trace system");
-    let trace_arc: Arc<dyn Trace + Send + Sync> = Arc::new(trace_system);
-
-    trace_arc.log(TraceLevel::Info, "Initializing loggerd daemon...");
-
-    // Shared state for metrics
-    let state = AppState {
-        metrics: Arc::new(MetricsState {
-            requests: AtomicU64::new(0),
-            log_count,
-            start: Instant::now(),
-        }),
-        trace: trace_arc.clone(),
-    };
-
-    // Configure routes
-    let app = Router::new()
-        .route("/health", get(health_handler))
-        .route("/metrics", get(metrics_handler))
-        .with_state(state.clone());
-
-    // Bind TCP
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    let listener = TcpListener::bind(addr).await.unwrap();
-
-    let msg = format!(
-        "loggerd started on http://{}/ (GET /health, /metrics)",
-        listener.local_addr().unwrap()
-    );
-    state.trace.log(TraceLevel::Info, &msg);
-
-    // Server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal(state.trace.clone()))
+    let config = LoggerdConfig::from_args_or_env().expect("Failed to load configuration");
+    let server = server::start(config)
         .await
-        .unwrap();
+        .expect("Failed to start loggerd");
+    install_panic_hook(server.trace());
 
-    state
-        .trace
-        .log(TraceLevel::Info, "loggerd shutdown complete");
-}
+    tokio::spawn(shutdown_signal(server.trace(), server.shutdown_trigger()));
+    tokio::spawn(reload_on_sighup(server.trace(), server.tls()));
+    tokio::spawn(rotate_on_sigusr1(server.trace(), server.log_files()));
 
-/// HTTP handler for the health check endpoint.
-///
-/// Returns a simple "OK" response to indicate the service is running.
-/// This endpoint can be used by load balancers and monitoring systems
-/// to verify service availability.
-///
-/// # Returns
-///
-/// Static string "OK"
-async fn health_handler() -> &'static str {
-    "OK"
+    let report = server.wait().await.expect("loggerd server failed");
+    if report.is_clean() {
+        println!("loggerd stopped: {}", report);
+        return;
+    }
+    eprintln!("loggerd stopped: {}", report);
+    for failure in &report.failures {
+        eprintln!("  {}", failure);
+    }
+    // Don't wait on the writer threads that missed the deadline
+    std::process::exit(1);
 }
 
-/// HTTP handler for the metrics endpoint.
-///
-/// Returns JSON-formatted metrics including:
-/// - Total HTTP requests processed
-/// - Total log messages written to files
-/// - Service uptime in seconds
-/// - Current service status
+/// Handles graceful shutdown on SIGTERM.
 ///
-/// This endpoint increments the request counter each time it's called.
+/// SIGTERM is commonly used by process managers and container orchestrators
+/// to request graceful shutdown. When it is received, the function logs the
+/// event and triggers the shutdown of the server, which lets `main` return.
 ///
-/// # Returns
+/// # Arguments
 ///
-/// JSON object with current metrics
-async fn metrics_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
-    // Increment request counter
-    let requests = state.metrics.requests.fetch_add(1, Ordering::Relaxed) + 1;
-    let logs = state.metrics.log_count.load(Ordering::Relaxed);
-    let uptime = state.metrics.start.elapsed().as_secs();
+/// * `trace` - Shared trace instance for logging shutdown events
+/// * `shutdown` - Trigger of the graceful shutdown of the server
+async fn shutdown_signal(trace: Arc<dyn Trace + Send + Sync>, shutdown: ShutdownTrigger) {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to setup SIGTERM handler");
 
-    Json(json!({
-        "requests": requests,
-        "log_count": logs,
-        "uptime_seconds": uptime,
-        "status": "running"
-    }))
+    sigterm.recv().await;
+    trace.log(
+        TraceLevel::Warning,
+        "Received SIGTERM, shutting down gracefully...",
+    );
+    shutdown.trigger();
 }
 
-/// Handles graceful shutdown on SIGTERM and SIGHUP signals.
+/// Reloads the TLS certificates on each SIGHUP.
 ///
-/// This function sets up signal handlers for SIGTERM and SIGHUP, which are
-/// commonly used by process managers and container orchestrators to request
-/// graceful shutdown. When either signal is received, the function logs the
-/// event and returns, allowing the main server loop to shut down cleanly.
+/// A failed reload is logged and the current certificates stay in use, so a
+/// half-renewed certificate never takes the listener down.
 ///
 /// # Arguments
 ///
-/// * `trace` - Shared trace instance for logging shutdown events
-async fn shutdown_signal(trace: Arc<dyn Trace + Send + Sync>) {
-    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to setup SIGTERM handler");
+/// * `trace` - Shared trace instance for logging reload events
+/// * `tls` - Certificates of the listener (None when serving plain HTTP)
+async fn reload_on_sighup(trace: Arc<dyn Trace + Send + Sync>, tls: Option<ReloadableTls>) {
     let mut sighup = signal(SignalKind::hangup()).expect("Failed to setup SIGHUP handler");
 
-    tokio::select! {
-        _ = sigterm.recv() => {
-            trace.log(TraceLevel::Warning, "Received SIGTERM, shutting down gracefully...");
+    while sighup.recv().await.is_some() {
+        let Some(tls) = &tls else {
+            trace.log(TraceLevel::Info, "Received SIGHUP, nothing to reload");
+            continue;
+        };
+        match tls.reload() {
+            Ok(()) => trace.log(
+                TraceLevel::Info,
+                "Received SIGHUP, TLS certificates reloaded",
+            ),
+            Err(e) => trace_error!(
+                trace,
+                "Received SIGHUP, TLS reload failed (keeping current certificates): {}",
+                e
+            ),
         }
-        _ = sighup.recv() => {
-            trace.log(TraceLevel::Warning, "Received SIGHUP, shutting down gracefully...");
+    }
+}
+
+/// Rotates the log files on each SIGUSR1, like `logrotate` expects.
+///
+/// # Arguments
+///
+/// * `trace` - Shared trace instance for logging rotation events
+/// * `log_files` - Files of the file output (None when it is disabled)
+async fn rotate_on_sigusr1(trace: Arc<dyn Trace + Send + Sync>, log_files: Option<LogFiles>) {
+    let mut sigusr1 = signal(SignalKind::user_defined1()).expect("Failed to setup SIGUSR1 handler");
+
+    while sigusr1.recv().await.is_some() {
+        let Some(files) = log_files.clone() else {
+            trace.log(
+                TraceLevel::Info,
+                "Received SIGUSR1, file output disabled: nothing to rotate",
+            );
+            continue;
+        };
+        let outcomes = tokio::task::spawn_blocking(move || files.rotate())
+            .await
+            .unwrap_or_default();
+        for (file, result) in outcomes {
+            match result {
+                Ok(Some(backup)) => trace_info!(
+                    trace,
+                    "Received SIGUSR1, {} rotated to {}", file, backup;
+                    file = file
+                ),
+                Ok(None) => {} // Empty file, nothing to rotate
+                Err(e) => trace_error!(
+                    trace,
+                    "Received SIGUSR1, rotation of {} failed: {}", file, e;
+                    file = file
+                ),
+            }
         }
     }
 }
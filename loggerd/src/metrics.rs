@@ -0,0 +1,386 @@
+//! Registry of named, labelled metrics shared across the daemon.
+//!
+//! Components that need to report activity (forwarding, rate limiting, ...)
+//! ask the registry for a counter or gauge once and then update the returned
+//! atomic directly, so the hot path never takes the registry lock.
+//!
+//! The registry can be exported as JSON ([`MetricsRegistry::snapshot`]) or in
+//! the Prometheus text format ([`MetricsRegistry::render_prometheus`]).
+
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::trace::lock;
+
+/// Identifies a metric: a name plus a sorted list of label pairs.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MetricKey {
+    /// Metric name (e.g. `forward_records_sent_total`)
+    pub name: String,
+    /// Label pairs, sorted by label name
+    pub labels: Vec<(String, String)>,
+}
+
+impl MetricKey {
+    fn new(name: &str, labels: &[(&str, &str)]) -> Self {
+        let mut labels: Vec<(String, String)> = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        labels.sort();
+        Self {
+            name: name.to_string(),
+            labels,
+        }
+    }
+
+    fn labels_json(&self) -> Map<String, Value> {
+        self.labels
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect()
+    }
+
+    /// Formats the labels as `{a="1",b="2"}`, plus an optional extra pair
+    /// (the `le` of histogram buckets).
+    fn labels_prometheus(&self, extra: Option<(&str, &str)>) -> String {
+        let pairs: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(extra)
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+            .collect();
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", pairs.join(","))
+        }
+    }
+}
+
+/// Escapes a label value for the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Distribution of observed values over fixed buckets.
+///
+/// Observations only touch atomics: recording a value never takes a lock.
+#[derive(Debug)]
+pub struct Histogram {
+    /// Upper bounds of the buckets, increasing (the `+Inf` bucket is implicit)
+    bounds: Vec<f64>,
+    /// Observations per bucket (not cumulative), plus the `+Inf` bucket
+    buckets: Vec<AtomicU64>,
+    /// Number of observations
+    count: AtomicU64,
+    /// Sum of the observations, as `f64` bits
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        Self {
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            bounds,
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    /// Records one value.
+    pub fn observe(&self, value: f64) {
+        let index = self.bounds.partition_point(|&bound| bound < value);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+
+    /// Returns the number of observations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the sum of the observations.
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum.load(Ordering::Relaxed))
+    }
+
+    /// Returns `(upper bound, cumulative count)` for each bucket, `+Inf` last.
+    pub fn cumulative_buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        self.bounds
+            .iter()
+            .copied()
+            .chain([f64::INFINITY])
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                total += count.load(Ordering::Relaxed);
+                (bound, total)
+            })
+            .collect()
+    }
+}
+
+/// Thread-safe registry of counters, gauges and histograms.
+///
+/// Asking twice for the same name and labels returns the same atomic, so
+/// independent components can share a metric without coordinating.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::metrics::MetricsRegistry;
+/// use std::sync::atomic::Ordering;
+///
+/// let registry = MetricsRegistry::new();
+/// let sent = registry.counter("records_sent_total", &[("target", "upstream")]);
+/// sent.fetch_add(3, Ordering::Relaxed);
+///
+/// let same = registry.counter("records_sent_total", &[("target", "upstream")]);
+/// assert_eq!(same.load(Ordering::Relaxed), 3);
+/// ```
+#[derive(Default)]
+pub struct MetricsRegistry {
+    /// Monotonically increasing values
+    counters: Mutex<BTreeMap<MetricKey, Arc<AtomicU64>>>,
+    /// Values that can go up and down
+    gauges: Mutex<BTreeMap<MetricKey, Arc<AtomicU64>>>,
+    /// Distributions of observed values
+    histograms: Mutex<BTreeMap<MetricKey, Arc<Histogram>>>,
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the counter for `name` and `labels`, creating it at zero.
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> Arc<AtomicU64> {
+        let mut counters = lock(&self.counters);
+        counters
+            .entry(MetricKey::new(name, labels))
+            .or_default()
+            .clone()
+    }
+
+    /// Returns the gauge for `name` and `labels`, creating it at zero.
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> Arc<AtomicU64> {
+        let mut gauges = lock(&self.gauges);
+        gauges
+            .entry(MetricKey::new(name, labels))
+            .or_default()
+            .clone()
+    }
+
+    /// Returns the histogram for `name` and `labels`, creating it with the
+    /// given bucket upper bounds.
+    ///
+    /// The bounds of an existing histogram are kept.
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)], bounds: &[f64]) -> Arc<Histogram> {
+        let mut histograms = lock(&self.histograms);
+        histograms
+            .entry(MetricKey::new(name, labels))
+            .or_insert_with(|| Arc::new(Histogram::new(bounds)))
+            .clone()
+    }
+
+    /// Returns the current value of every metric as JSON.
+    ///
+    /// ```text
+    /// {"counters": [{"name": "...", "labels": {"source": "app"}, "value": 3}], "gauges": [...],
+    ///  "histograms": [{"name": "...", "labels": {}, "count": 2, "sum": 12.5, "buckets": [[10, 1], ...]}]}
+    /// ```
+    pub fn snapshot(&self) -> Value {
+        let histograms: Vec<Value> = self
+            .histograms
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, histogram)| {
+                let buckets: Vec<Value> = histogram
+                    .cumulative_buckets()
+                    .into_iter()
+                    .map(|(bound, count)| {
+                        let bound = if bound.is_finite() {
+                            json!(bound)
+                        } else {
+                            json!("+Inf")
+                        };
+                        json!([bound, count])
+                    })
+                    .collect();
+                json!({
+                    "name": key.name,
+                    "labels": key.labels_json(),
+                    "count": histogram.count(),
+                    "sum": histogram.sum(),
+                    "buckets": buckets,
+                })
+            })
+            .collect();
+
+        json!({
+            "counters": Self::snapshot_map(&lock(&self.counters)),
+            "gauges": Self::snapshot_map(&lock(&self.gauges)),
+            "histograms": histograms,
+        })
+    }
+
+    fn snapshot_map(metrics: &BTreeMap<MetricKey, Arc<AtomicU64>>) -> Value {
+        metrics
+            .iter()
+            .map(|(key, value)| {
+                json!({
+                    "name": key.name,
+                    "labels": key.labels_json(),
+                    "value": value.load(Ordering::Relaxed),
+                })
+            })
+            .collect()
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    ///
+    /// ```text
+    /// # TYPE http_requests_total counter
+    /// http_requests_total{route="/health",status="200"} 12
+    /// ```
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        Self::render_values(&mut out, "counter", &lock(&self.counters));
+        Self::render_values(&mut out, "gauge", &lock(&self.gauges));
+
+        let histograms = lock(&self.histograms);
+        let mut last_name = None;
+        for (key, histogram) in histograms.iter() {
+            if last_name != Some(&key.name) {
+                let _ = writeln!(out, "# TYPE {} histogram", key.name);
+                last_name = Some(&key.name);
+            }
+            for (bound, count) in histogram.cumulative_buckets() {
+                let le = if bound.is_finite() {
+                    bound.to_string()
+                } else {
+                    "+Inf".to_string()
+                };
+                let labels = key.labels_prometheus(Some(("le", &le)));
+                let _ = writeln!(out, "{}_bucket{} {}", key.name, labels, count);
+            }
+            let labels = key.labels_prometheus(None);
+            let _ = writeln!(out, "{}_sum{} {}", key.name, labels, histogram.sum());
+            let _ = writeln!(out, "{}_count{} {}", key.name, labels, histogram.count());
+        }
+        out
+    }
+
+    fn render_values(out: &mut String, kind: &str, metrics: &BTreeMap<MetricKey, Arc<AtomicU64>>) {
+        let mut last_name = None;
+        for (key, value) in metrics {
+            // Keys are sorted by name: each name is a contiguous block
+            if last_name != Some(&key.name) {
+                let _ = writeln!(out, "# TYPE {} {}", key.name, kind);
+                last_name = Some(&key.name);
+            }
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                key.name,
+                key.labels_prometheus(None),
+                value.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_order_does_not_matter() {
+        let registry = MetricsRegistry::new();
+        let a = registry.counter("hits", &[("a", "1"), ("b", "2")]);
+        let b = registry.counter("hits", &[("b", "2"), ("a", "1")]);
+        a.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(b.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter("hits", &[("source", "app")])
+            .fetch_add(2, Ordering::Relaxed);
+        registry.gauge("queue", &[]).store(7, Ordering::Relaxed);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot["counters"][0]["name"], "hits");
+        assert_eq!(snapshot["counters"][0]["labels"]["source"], "app");
+        assert_eq!(snapshot["counters"][0]["value"], 2);
+        assert_eq!(snapshot["gauges"][0]["value"], 7);
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let registry = MetricsRegistry::new();
+        let histogram = registry.histogram("latency_ms", &[], &[100.0, 10.0]);
+        for value in [5.0, 10.0, 50.0, 500.0] {
+            histogram.observe(value);
+        }
+
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.sum(), 565.0);
+        assert_eq!(
+            histogram.cumulative_buckets(),
+            vec![(10.0, 2), (100.0, 3), (f64::INFINITY, 4)]
+        );
+        assert_eq!(
+            registry.snapshot()["histograms"][0]["buckets"][2][0],
+            "+Inf"
+        );
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter("hits_total", &[("source", "a\"b")])
+            .fetch_add(2, Ordering::Relaxed);
+        registry.counter("hits_total", &[("source", "c")]);
+        registry.gauge("queue", &[]).store(7, Ordering::Relaxed);
+        registry
+            .histogram("latency_ms", &[("source", "c")], &[10.0])
+            .observe(3.5);
+
+        let text = registry.render_prometheus();
+        let expected = "\
+# TYPE hits_total counter
+hits_total{source=\"a\\\"b\"} 2
+hits_total{source=\"c\"} 0
+# TYPE queue gauge
+queue 7
+# TYPE latency_ms histogram
+latency_ms_bucket{source=\"c\",le=\"10\"} 1
+latency_ms_bucket{source=\"c\",le=\"+Inf\"} 1
+latency_ms_sum{source=\"c\"} 3.5
+latency_ms_count{source=\"c\"} 1
+";
+        assert_eq!(text, expected);
+    }
+}
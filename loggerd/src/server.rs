@@ -0,0 +1,267 @@
+//! Startup of a complete loggerd daemon from its configuration.
+//!
+//! [`start`] builds the trace system, the API and the optional file tailing,
+//! binds the listener of the `[server]` section and serves in a background
+//! task. The returned [`RunningServer`] gives the bound address (useful with
+//! an ephemeral `127.0.0.1:0` port) and stops the daemon gracefully, draining
+//! the queued records to their files before reporting what was lost. The
+//! `loggerd` binary adds the Unix signals on top; integration tests run
+//! several daemons side by side in one process.
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use loggerd::config::LoggerdConfig;
+//!
+//! let config = LoggerdConfig::parse("[server]\nbind = \"127.0.0.1:0\"")?;
+//! let server = loggerd::server::start(config).await?;
+//! println!("listening on {}", server.url());
+//! let report = server.stop().await?;
+//! println!("{}", report);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{Error, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::api::{self, ApiKeys, AppState, MetricsState};
+use crate::config::LoggerdConfig;
+use crate::metrics::MetricsRegistry;
+use crate::tail::FileTailer;
+use crate::tls::ReloadableTls;
+use crate::trace::file::LogFiles;
+use crate::trace::{self, DrainReport, Trace, TraceLevel};
+
+/// Time given to open connections to finish once shutdown is requested.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Requests the graceful shutdown of a [`RunningServer`]; clones trigger the
+/// same server.
+#[derive(Clone)]
+pub struct ShutdownTrigger {
+    token: CancellationToken,
+}
+
+impl ShutdownTrigger {
+    /// Stops accepting connections and lets the open ones finish. Calling
+    /// it again does nothing.
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+}
+
+/// A daemon serving in the background, returned by [`start`].
+pub struct RunningServer {
+    /// Address the listener is bound to
+    local_addr: SocketAddr,
+    /// `http` or `https`
+    scheme: &'static str,
+    /// State shared with the endpoints
+    state: AppState,
+    /// Certificates of the listener (None when serving plain HTTP)
+    tls: Option<ReloadableTls>,
+    shutdown: ShutdownTrigger,
+    task: JoinHandle<Result<DrainReport>>,
+}
+
+impl RunningServer {
+    /// Returns the address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the base URL of the API (`http://127.0.0.1:41234`).
+    pub fn url(&self) -> String {
+        format!("{}://{}", self.scheme, self.local_addr)
+    }
+
+    /// Returns the trace system of the daemon.
+    pub fn trace(&self) -> Arc<dyn Trace + Send + Sync> {
+        self.state.trace.clone()
+    }
+
+    /// Returns the log files of the file output (None when it is disabled).
+    pub fn log_files(&self) -> Option<LogFiles> {
+        self.state.log_files.clone()
+    }
+
+    /// Returns the certificates of the listener (None for plain HTTP).
+    pub fn tls(&self) -> Option<ReloadableTls> {
+        self.tls.clone()
+    }
+
+    /// Returns a trigger stopping this server, to hand to a signal handler.
+    pub fn shutdown_trigger(&self) -> ShutdownTrigger {
+        self.shutdown.clone()
+    }
+
+    /// Waits until the server has stopped (after a trigger, or on error)
+    /// and its handlers are drained.
+    ///
+    /// Once the open connections are closed and the file tailing stopped,
+    /// the trace system stops accepting records and each handler gets up to
+    /// `drain_timeout_ms` (`[server]` section) to write out its queue and
+    /// sync its files.
+    ///
+    /// # Returns
+    ///
+    /// The number of records flushed and lost by the drain.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped the server, if any.
+    pub async fn wait(self) -> Result<DrainReport> {
+        self.task.await.map_err(Error::other)?
+    }
+
+    /// Shuts the server down gracefully and waits until it has stopped.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`wait`](Self::wait).
+    pub async fn stop(self) -> Result<DrainReport> {
+        self.shutdown.trigger();
+        self.wait().await
+    }
+}
+
+/// Starts a daemon described by `config` and returns once it listens.
+///
+/// # Errors
+///
+/// Returns an error if the trace system, the API keys, the TLS certificates
+/// or the file tailing cannot be set up, or if the bind address is not
+/// available.
+pub async fn start(config: LoggerdConfig) -> Result<RunningServer> {
+    let registry = Arc::new(MetricsRegistry::new());
+
+    // Initialize trace system (console + file with rotation + forwarding)
+    let (trace_system, handles) = trace::create_trace_with_config(&config, &registry)?;
+    let trace: Arc<dyn Trace + Send + Sync> = Arc::new(trace_system);
+
+    trace.log(TraceLevel::Info, "Initializing loggerd daemon...");
+
+    let auth = config
+        .auth
+        .as_ref()
+        .map(|auth| ApiKeys::new(auth).map(Arc::new))
+        .transpose()?;
+    if auth.is_none() {
+        trace.log(
+            TraceLevel::Warning,
+            "API authentication disabled: every client can send and read logs",
+        );
+    }
+
+    let tls = config.tls.clone().map(ReloadableTls::new).transpose()?;
+
+    // Follow the log files of other applications
+    let tailer = config
+        .tail
+        .clone()
+        .map(|tail| FileTailer::new(tail, trace.clone(), &registry)?.start())
+        .transpose()?;
+
+    // Shared state for metrics
+    let state = AppState {
+        metrics: Arc::new(MetricsState::new(handles.log_counter, registry)),
+        trace: trace.clone(),
+        auth,
+        recent: handles.recent,
+        alerts: handles.alerts,
+        disk_guard: handles.disk_guard,
+        log_files: handles.log_files,
+        level: handles.level,
+    };
+
+    // Configure routes
+    let app = api::router(state.clone(), &config.server)
+        .into_make_service_with_connect_info::<SocketAddr>();
+
+    // Bind TCP
+    let listener = TcpListener::bind(config.server.bind).await?;
+    let local_addr = listener.local_addr()?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    log_started(&trace, scheme, local_addr);
+
+    let shutdown = ShutdownTrigger {
+        token: CancellationToken::new(),
+    };
+    let token = shutdown.token.clone();
+    let drain_timeout = Duration::from_millis(config.server.drain_timeout_ms);
+    let server_tls = tls.clone();
+    let server_trace = trace.clone();
+    let task = tokio::spawn(async move {
+        let served = match server_tls {
+            Some(tls) => {
+                let handle = axum_server::Handle::new();
+                let graceful = handle.clone();
+                tokio::spawn(async move {
+                    token.cancelled().await;
+                    graceful.graceful_shutdown(Some(SHUTDOWN_GRACE));
+                });
+                axum_server::from_tcp_rustls(listener.into_std()?, tls.rustls_config())
+                    .handle(handle)
+                    .serve(app)
+                    .await
+            }
+            // Server with graceful shutdown, bounded like the TLS one
+            None => {
+                let serve = axum::serve(listener, app)
+                    .with_graceful_shutdown(token.clone().cancelled_owned())
+                    .into_future();
+                tokio::pin!(serve);
+                tokio::select! {
+                    served = &mut serve => served,
+                    () = token.cancelled() => {
+                        match tokio::time::timeout(SHUTDOWN_GRACE, serve).await {
+                            Ok(served) => served,
+                            Err(_) => {
+                                server_trace.log(
+                                    TraceLevel::Warning,
+                                    "connections still open after the shutdown grace period, closing them",
+                                );
+                                Ok(())
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        // Stop tailing and save the read offsets
+        drop(tailer);
+
+        server_trace.log(TraceLevel::Info, "loggerd shutdown complete");
+
+        // Write out the queued records, the message above being the last one
+        let deadline = Instant::now() + drain_timeout;
+        let report = tokio::task::spawn_blocking(move || server_trace.drain(deadline))
+            .await
+            .map_err(Error::other)?;
+        served.map(|()| report)
+    });
+
+    Ok(RunningServer {
+        local_addr,
+        scheme,
+        state,
+        tls,
+        shutdown,
+        task,
+    })
+}
+
+/// Logs the startup message with the listening URL.
+fn log_started(trace: &Arc<dyn Trace + Send + Sync>, scheme: &str, addr: SocketAddr) {
+    let msg = format!(
+        "loggerd started on {}://{}/ (GET /health, /metrics, /logs, /logs/recent, /alerts, /admin/files, /admin/level; POST /logs, /admin/rotate)",
+        scheme, addr
+    );
+    trace.log(TraceLevel::Info, &msg);
+}
@@ -0,0 +1,508 @@
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::offsets::{FileIdentity, SavedOffset};
+use super::{StartPosition, TailFileConfig};
+use crate::metrics::MetricsRegistry;
+use crate::trace::TraceRecord;
+
+/// Longest record: a longer line, or multi-line record, is cut there.
+const MAX_RECORD_BYTES: usize = 1024 * 1024;
+
+/// Size of the reads; the lines of each read are emitted before the next.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Most bytes read by one poll, so that a long backlog is fed in steps
+/// between which the offsets are saved and the tailer can stop.
+const MAX_READ_PER_POLL: u64 = 4 * 1024 * 1024;
+
+/// Time after which a multi-line record is emitted if no line follows it.
+const MULTILINE_FLUSH: Duration = Duration::from_secs(1);
+
+/// A multi-line record being assembled.
+struct PendingRecord {
+    message: String,
+    /// Bytes of the file it spans, newlines included
+    bytes: u64,
+}
+
+/// Tailing counters of one file.
+struct TailStats {
+    /// `tail_records_total{file=...}`
+    records: Arc<AtomicU64>,
+    /// `tail_rotations_total{file=...}`
+    rotations: Arc<AtomicU64>,
+    /// `tail_truncations_total{file=...}`
+    truncations: Arc<AtomicU64>,
+}
+
+/// One followed file.
+///
+/// The follower keeps the file open between polls, which is what lets it
+/// finish reading a file after it was renamed. Records are only emitted for
+/// complete lines: a line still being written stays buffered until its
+/// newline arrives.
+pub struct FileFollower {
+    config: TailFileConfig,
+    source: String,
+    multiline_start: Option<Regex>,
+    file: Option<File>,
+    identity: Option<FileIdentity>,
+    /// Offset of the next byte to read
+    offset: u64,
+    /// Bytes read after the last newline
+    partial: Vec<u8>,
+    record: Option<PendingRecord>,
+    last_line: Instant,
+    /// Where to start reading when the file is next opened
+    start: Start,
+    stats: TailStats,
+}
+
+/// Where to start reading a newly opened file.
+enum Start {
+    /// First open: the saved offset if it belongs to the file, else `start_at`
+    Resume(Option<SavedOffset>),
+    /// The file replaces one that was rotated: read it all
+    Beginning,
+}
+
+impl FileFollower {
+    /// Creates a follower; the file is opened by the first poll.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The followed file
+    /// * `saved` - Offset saved by a previous run, if any
+    /// * `metrics` - Registry receiving the tailing counters
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if `multiline_start` is not a valid
+    /// regular expression.
+    pub fn new(
+        config: TailFileConfig,
+        saved: Option<SavedOffset>,
+        metrics: &MetricsRegistry,
+    ) -> Result<Self, Error> {
+        let multiline_start = config
+            .multiline_start
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "invalid multiline_start for {}: {}",
+                        config.path.display(),
+                        e
+                    ),
+                )
+            })?;
+        let file_label = config.path.display().to_string();
+        let labels = [("file", file_label.as_str())];
+        let stats = TailStats {
+            records: metrics.counter("tail_records_total", &labels),
+            rotations: metrics.counter("tail_rotations_total", &labels),
+            truncations: metrics.counter("tail_truncations_total", &labels),
+        };
+
+        Ok(Self {
+            source: config.source.clone().unwrap_or(file_label),
+            config,
+            multiline_start,
+            file: None,
+            identity: None,
+            offset: 0,
+            partial: Vec::new(),
+            record: None,
+            last_line: Instant::now(),
+            start: Start::Resume(saved),
+            stats,
+        })
+    }
+
+    /// Returns the path of the followed file.
+    pub fn path(&self) -> &Path {
+        &self.config.path
+    }
+
+    /// Returns the position to save: the bytes fed into the pipeline, so a
+    /// restart re-reads whatever was still buffered.
+    pub fn saved_offset(&self) -> Option<SavedOffset> {
+        let buffered = self.partial.len() as u64 + self.record.as_ref().map_or(0, |r| r.bytes);
+        self.identity.map(|identity| SavedOffset {
+            identity,
+            offset: self.offset - buffered,
+        })
+    }
+
+    /// Reads what was appended since the last poll and follows rotations.
+    ///
+    /// Returns `true` if the poll stopped before the end of the file (see
+    /// [`MAX_READ_PER_POLL`]): the tailer polls again without waiting.
+    pub fn poll(&mut self, now: Instant, emit: &mut dyn FnMut(TraceRecord)) -> bool {
+        if self.file.is_none() {
+            self.open();
+        }
+        if self.file.is_none() {
+            return false;
+        }
+
+        let truncated = self
+            .file
+            .as_ref()
+            .and_then(|file| file.metadata().ok())
+            .is_some_and(|metadata| metadata.len() < self.offset);
+        if truncated {
+            self.stats.truncations.fetch_add(1, Ordering::Relaxed);
+            self.flush(emit);
+            self.partial.clear();
+            self.offset = 0;
+            if let Some(file) = &mut self.file {
+                let _ = file.seek(SeekFrom::Start(0));
+            }
+        }
+
+        let (read_lines, more) = self.read_available(now, emit);
+        if more {
+            return true;
+        }
+
+        // The path now names another file, or nothing: it was rotated
+        let rotated = match fs::metadata(&self.config.path) {
+            Ok(metadata) => Some(FileIdentity::of(&metadata)) != self.identity,
+            Err(e) => e.kind() == ErrorKind::NotFound,
+        };
+        if rotated {
+            // Lines written just before the rename; the rotation is handled
+            // by the poll that reaches the end of the old file
+            if self.read_available(now, emit).1 {
+                return true;
+            }
+            self.stats.rotations.fetch_add(1, Ordering::Relaxed);
+            self.flush_partial(emit);
+            self.flush(emit);
+            self.file = None;
+            self.identity = None;
+            self.offset = 0;
+            self.start = Start::Beginning;
+            return false;
+        }
+
+        if !read_lines && now.saturating_duration_since(self.last_line) >= MULTILINE_FLUSH {
+            self.flush(emit);
+        }
+        false
+    }
+
+    /// Opens the file if it exists and positions the read offset.
+    fn open(&mut self) {
+        let Ok(mut file) = File::open(&self.config.path) else {
+            // A file created after startup is read from its first line
+            if matches!(self.start, Start::Resume(None)) {
+                self.start = Start::Beginning;
+            }
+            return;
+        };
+        let Ok(metadata) = file.metadata() else {
+            return;
+        };
+        let identity = FileIdentity::of(&metadata);
+
+        let offset = match std::mem::replace(&mut self.start, Start::Beginning) {
+            Start::Beginning => 0,
+            Start::Resume(Some(saved)) if saved.identity == identity => {
+                saved.offset.min(metadata.len())
+            }
+            // Rotated while loggerd was stopped: this is a new file
+            Start::Resume(Some(_)) => 0,
+            Start::Resume(None) => match self.config.start_at {
+                StartPosition::Beginning => 0,
+                StartPosition::End => metadata.len(),
+            },
+        };
+        if file.seek(SeekFrom::Start(offset)).is_err() {
+            return;
+        }
+
+        self.file = Some(file);
+        self.identity = Some(identity);
+        self.offset = offset;
+    }
+
+    /// Reads up to the end of the file, or [`MAX_READ_PER_POLL`] bytes.
+    ///
+    /// Returns whether any line was complete, and whether the read stopped
+    /// before the end of the file.
+    fn read_available(&mut self, now: Instant, emit: &mut dyn FnMut(TraceRecord)) -> (bool, bool) {
+        let mut read_lines = false;
+        let mut total = 0u64;
+        let mut lines = Vec::new();
+        let mut chunk = vec![0u8; READ_CHUNK];
+        loop {
+            if total >= MAX_READ_PER_POLL {
+                return (read_lines, true);
+            }
+            let Some(file) = &mut self.file else {
+                break;
+            };
+            let read = match file.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            self.offset += read as u64;
+            total += read as u64;
+            for &byte in &chunk[..read] {
+                if byte == b'\n' {
+                    let line = std::mem::take(&mut self.partial);
+                    let consumed = line.len() as u64 + 1;
+                    lines.push((line, consumed));
+                } else if self.partial.len() < MAX_RECORD_BYTES {
+                    self.partial.push(byte);
+                } else {
+                    // Overlong line: cut it, the rest becomes the next line
+                    let line = std::mem::replace(&mut self.partial, vec![byte]);
+                    let consumed = line.len() as u64;
+                    lines.push((line, consumed));
+                }
+            }
+
+            if !lines.is_empty() {
+                read_lines = true;
+                self.last_line = now;
+            }
+            for (line, consumed) in lines.drain(..) {
+                self.line(&line, consumed, emit);
+            }
+        }
+        (read_lines, false)
+    }
+
+    /// Handles one complete line.
+    fn line(&mut self, bytes: &[u8], consumed: u64, emit: &mut dyn FnMut(TraceRecord)) {
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.strip_suffix('\r').unwrap_or(&text);
+
+        let Some(start) = &self.multiline_start else {
+            self.emit(text.to_string(), emit);
+            return;
+        };
+
+        match &mut self.record {
+            Some(record)
+                if !start.is_match(text)
+                    && record.message.len() + text.len() < MAX_RECORD_BYTES =>
+            {
+                record.message.push('\n');
+                record.message.push_str(text);
+                record.bytes += consumed;
+            }
+            _ => {
+                self.flush(emit);
+                self.record = Some(PendingRecord {
+                    message: text.to_string(),
+                    bytes: consumed,
+                });
+            }
+        }
+    }
+
+    /// Emits the multi-line record being assembled, if any.
+    fn flush(&mut self, emit: &mut dyn FnMut(TraceRecord)) {
+        if let Some(record) = self.record.take() {
+            self.emit(record.message, emit);
+        }
+    }
+
+    /// Emits the last line of a file that will not be written anymore.
+    fn flush_partial(&mut self, emit: &mut dyn FnMut(TraceRecord)) {
+        if !self.partial.is_empty() {
+            let partial = std::mem::take(&mut self.partial);
+            self.line(&partial, partial.len() as u64, emit);
+        }
+    }
+
+    fn emit(&self, message: String, emit: &mut dyn FnMut(TraceRecord)) {
+        self.stats.records.fetch_add(1, Ordering::Relaxed);
+        emit(TraceRecord::new(self.config.level, &self.source, &message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TraceLevel;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn config(path: &Path) -> TailFileConfig {
+        TailFileConfig {
+            path: path.to_path_buf(),
+            source: Some("app".to_string()),
+            level: TraceLevel::Info,
+            multiline_start: None,
+            start_at: StartPosition::Beginning,
+        }
+    }
+
+    fn append(path: &Path, content: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn poll(follower: &mut FileFollower) -> Vec<String> {
+        let mut messages = Vec::new();
+        follower.poll(Instant::now(), &mut |r| messages.push(r.message));
+        messages
+    }
+
+    #[test]
+    fn test_backlog_is_read_in_steps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let line = format!("{}\n", "x".repeat(99));
+        let lines = (MAX_READ_PER_POLL as usize / line.len()) * 3 / 2;
+        append(&path, &line.repeat(lines));
+        let mut follower = FileFollower::new(config(&path), None, &MetricsRegistry::new()).unwrap();
+
+        let mut read = 0;
+        assert!(follower.poll(Instant::now(), &mut |_| read += 1));
+        assert!(read > 0 && read < lines);
+        let saved = follower.saved_offset().unwrap().offset;
+        assert_eq!(saved, (read * line.len()) as u64);
+
+        assert!(!follower.poll(Instant::now(), &mut |_| read += 1));
+        assert_eq!(read, lines);
+    }
+
+    #[test]
+    fn test_follows_complete_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let mut follower = FileFollower::new(config(&path), None, &MetricsRegistry::new()).unwrap();
+
+        // The file doesn't exist yet
+        assert!(poll(&mut follower).is_empty());
+
+        append(&path, "first\nsecond\r\nthi");
+        assert_eq!(poll(&mut follower), vec!["first", "second"]);
+        assert_eq!(follower.saved_offset().unwrap().offset, 14);
+
+        append(&path, "rd\n");
+        assert_eq!(poll(&mut follower), vec!["third"]);
+        assert!(poll(&mut follower).is_empty());
+    }
+
+    #[test]
+    fn test_start_at_end_and_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "old\n");
+        let metrics = MetricsRegistry::new();
+
+        let end = TailFileConfig {
+            start_at: StartPosition::End,
+            ..config(&path)
+        };
+        let mut follower = FileFollower::new(end.clone(), None, &metrics).unwrap();
+        assert!(poll(&mut follower).is_empty());
+        append(&path, "new\n");
+        assert_eq!(poll(&mut follower), vec!["new"]);
+
+        // A file created after startup is read entirely
+        let created = dir.path().join("created.log");
+        let mut late = FileFollower::new(
+            TailFileConfig {
+                path: created.clone(),
+                ..end
+            },
+            None,
+            &metrics,
+        )
+        .unwrap();
+        assert!(poll(&mut late).is_empty());
+        append(&created, "first\n");
+        assert_eq!(poll(&mut late), vec!["first"]);
+
+        // A restart resumes where the previous run stopped
+        let saved = follower.saved_offset();
+        drop(follower);
+        append(&path, "while stopped\n");
+        let mut follower = FileFollower::new(config(&path), saved, &metrics).unwrap();
+        assert_eq!(poll(&mut follower), vec!["while stopped"]);
+    }
+
+    #[test]
+    fn test_rename_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "a\n");
+        let mut follower = FileFollower::new(config(&path), None, &MetricsRegistry::new()).unwrap();
+        assert_eq!(poll(&mut follower), vec!["a"]);
+
+        // Written to the old file right before it is renamed
+        append(&path, "b\n");
+        fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        append(&path, "c\n");
+
+        assert_eq!(poll(&mut follower), vec!["b"]);
+        assert_eq!(poll(&mut follower), vec!["c"]);
+        assert_eq!(follower.stats.rotations.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "a long first line\n");
+        let mut follower = FileFollower::new(config(&path), None, &MetricsRegistry::new()).unwrap();
+        assert_eq!(poll(&mut follower), vec!["a long first line"]);
+
+        fs::write(&path, "b\n").unwrap();
+        assert_eq!(poll(&mut follower), vec!["b"]);
+        assert_eq!(follower.stats.truncations.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_multiline_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let mut follower = FileFollower::new(
+            TailFileConfig {
+                multiline_start: Some(r"^\d{4}-".to_string()),
+                ..config(&path)
+            },
+            None,
+            &MetricsRegistry::new(),
+        )
+        .unwrap();
+
+        append(
+            &path,
+            "2025-10-14 panic\n  at main.rs:3\n  at lib.rs:9\n2025-10-14 next\n",
+        );
+        assert_eq!(
+            poll(&mut follower),
+            vec!["2025-10-14 panic\n  at main.rs:3\n  at lib.rs:9"]
+        );
+        // The last record is still open, and not counted as read yet
+        assert_eq!(follower.saved_offset().unwrap().offset, 46);
+
+        let mut messages = Vec::new();
+        let later = Instant::now() + MULTILINE_FLUSH;
+        follower.poll(later, &mut |r| messages.push(r.message));
+        assert_eq!(messages, vec!["2025-10-14 next"]);
+        assert_eq!(follower.saved_offset().unwrap().offset, 62);
+    }
+}
@@ -0,0 +1,111 @@
+//! File tailing input: follows the log files of other applications.
+//!
+//! Configured files are followed the way `tail -F` does: new lines are fed
+//! into the trace pipeline as records whose source is the file (or the
+//! configured `source`), so they go through the same stages, outputs and
+//! alert rules as any other record.
+//!
+//! # Architecture
+//!
+//! - `mod.rs` : Configuration
+//! - `tailer.rs` : Public facade (FileTailer) and polling thread
+//! - `follower.rs` : One followed file: rotation, truncation, multi-line records
+//! - `offsets.rs` : Read offsets persisted across restarts
+//!
+//! # Rotation and Truncation
+//!
+//! ```text
+//! app.log (inode 12) ──▶ renamed app.log.1 ──▶ read to the end, then
+//! app.log (inode 57) ──▶ followed from its first byte
+//!
+//! app.log truncated (size < offset) ──▶ followed again from its first byte
+//! ```
+//!
+//! Offsets are saved with the file identity (device and inode). On restart,
+//! a file that is still the same resumes at its saved offset; a file that
+//! was rotated in the meantime is read from the beginning.
+//!
+//! # Configuration
+//!
+//! ```toml
+//! [tail]
+//! state_file = "/var/lib/loggerd/tail-offsets.json"
+//! poll_interval_ms = 250
+//!
+//! [[tail.files]]
+//! path = "/var/log/legacy/app.log"
+//! source = "legacy"                        # default: the path
+//! level = "info"
+//! multiline_start = "^\\d{4}-\\d{2}-\\d{2}"  # continuation lines are appended
+//! start_at = "end"                         # or "beginning", without saved offset
+//! ```
+
+mod follower;
+mod offsets;
+mod tailer;
+
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::trace::TraceLevel;
+
+pub use tailer::FileTailer;
+
+/// Configuration of the file tailing input (`[tail]` section).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TailConfig {
+    /// File holding the read offsets
+    pub state_file: PathBuf,
+    /// Delay between two checks of the files
+    pub poll_interval_ms: u64,
+    /// Followed files
+    pub files: Vec<TailFileConfig>,
+}
+
+impl Default for TailConfig {
+    fn default() -> Self {
+        Self {
+            state_file: PathBuf::from("tail-offsets.json"),
+            poll_interval_ms: 250,
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Where to start reading a file without saved offset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartPosition {
+    /// Only follow the lines written from now on
+    #[default]
+    End,
+    /// Read the existing content first
+    Beginning,
+}
+
+/// A followed file (`[[tail.files]]` entry).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TailFileConfig {
+    /// Path of the file; it may not exist yet
+    pub path: PathBuf,
+    /// Source of the records (the path when absent)
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Level of the records
+    #[serde(default = "default_level")]
+    pub level: TraceLevel,
+    /// Regular expression matching the first line of a record; the other
+    /// lines are appended to the current record
+    #[serde(default)]
+    pub multiline_start: Option<String>,
+    /// Where to start when no offset is saved for the file (a file created
+    /// after startup is always read from its beginning)
+    #[serde(default)]
+    pub start_at: StartPosition,
+}
+
+fn default_level() -> TraceLevel {
+    TraceLevel::Info
+}
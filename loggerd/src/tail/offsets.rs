@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Identity of a file, stable across renames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    /// Device holding the file
+    pub dev: u64,
+    /// Inode number
+    pub ino: u64,
+}
+
+impl FileIdentity {
+    /// Returns the identity of the file described by `metadata`.
+    pub fn of(metadata: &fs::Metadata) -> Self {
+        Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        }
+    }
+}
+
+/// Saved read position of a followed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedOffset {
+    /// File the offset belongs to
+    #[serde(flatten)]
+    pub identity: FileIdentity,
+    /// Bytes already fed into the pipeline
+    pub offset: u64,
+}
+
+/// Read offsets of the followed files, by path.
+pub type Offsets = BTreeMap<PathBuf, SavedOffset>;
+
+/// Content of the state file.
+///
+/// ```text
+/// {"files":{"/var/log/legacy/app.log":{"dev":2049,"ino":1835023,"offset":48213}}}
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StateFile {
+    files: Offsets,
+}
+
+/// File persisting the read offsets across restarts.
+pub struct OffsetStore {
+    path: PathBuf,
+}
+
+impl OffsetStore {
+    /// Creates a store backed by `path` (created on the first save).
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Reads the saved offsets; a missing file means none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or `ErrorKind::InvalidData`
+    /// if it is corrupted.
+    pub fn load(&self) -> Result<Offsets> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Offsets::new()),
+            Err(e) => return Err(e),
+        };
+        let state: StateFile = serde_json::from_str(&content).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("corrupted tail state {}: {}", self.path.display(), e),
+            )
+        })?;
+        Ok(state.files)
+    }
+
+    /// Atomically replaces the saved offsets (write + fsync + rename).
+    pub fn save(&self, offsets: &Offsets) -> Result<()> {
+        let state = StateFile {
+            files: offsets.clone(),
+        };
+        let mut tmp_name = self.path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp = PathBuf::from(tmp_name);
+        {
+            let mut file = File::create(&tmp)?;
+            serde_json::to_writer(&mut file, &state)?;
+            file.write_all(b"\n")?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = OffsetStore::new(&dir.path().join("offsets.json"));
+        assert!(store.load().unwrap().is_empty());
+
+        let mut offsets = Offsets::new();
+        offsets.insert(
+            PathBuf::from("/var/log/app.log"),
+            SavedOffset {
+                identity: FileIdentity { dev: 1, ino: 42 },
+                offset: 1234,
+            },
+        );
+        store.save(&offsets).unwrap();
+        assert_eq!(store.load().unwrap(), offsets);
+
+        fs::write(dir.path().join("offsets.json"), "{").unwrap();
+        assert_eq!(store.load().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,257 @@
+use std::io::Error;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError, channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::TailConfig;
+use super::follower::FileFollower;
+use super::offsets::{OffsetStore, Offsets};
+use crate::metrics::MetricsRegistry;
+use crate::trace::Trace;
+
+/// File tailing input.
+///
+/// A background thread polls every configured file each `poll_interval_ms`
+/// and feeds the new records into `trace`, which is normally the whole
+/// trace system so that they go through the stages and reach every handler.
+/// Offsets are saved after each poll that read something, and when the
+/// tailer is dropped. A backlog (a file read from its beginning, or after
+/// downtime) is read a few megabytes per poll, polling again at once until
+/// it is caught up.
+///
+/// # Examples
+///
+/// ```no_run
+/// use loggerd::metrics::MetricsRegistry;
+/// use loggerd::tail::{FileTailer, TailConfig};
+/// use loggerd::trace::{RingBufferTraceHandler, Trace};
+/// use std::sync::Arc;
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let trace: Arc<dyn Trace + Send + Sync> = Arc::new(RingBufferTraceHandler::new(100));
+/// let tailer = FileTailer::new(TailConfig::default(), trace, &MetricsRegistry::new())?.start()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FileTailer {
+    /// Channel sender used to stop the tailer thread
+    sender: Option<Sender<()>>,
+    /// Handle to the background tailer thread
+    thread_handle: Option<JoinHandle<()>>,
+    /// Followers, moved into the tailer thread by `start()`
+    followers: Option<Vec<FileFollower>>,
+    /// Destination of the records
+    trace: Arc<dyn Trace + Send + Sync>,
+    store: Option<OffsetStore>,
+    offsets: Offsets,
+    poll_interval: Duration,
+}
+
+impl FileTailer {
+    /// Creates the followers, resuming from the saved offsets.
+    ///
+    /// **Note**: Call `.start()` to begin the tailer thread
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[tail]` section
+    /// * `trace` - Destination of the records
+    /// * `metrics` - Registry receiving the tailing counters
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state file cannot be read or is corrupted, or
+    /// `ErrorKind::InvalidInput` for an invalid `multiline_start`.
+    pub fn new(
+        config: TailConfig,
+        trace: Arc<dyn Trace + Send + Sync>,
+        metrics: &MetricsRegistry,
+    ) -> Result<Self, Error> {
+        let store = OffsetStore::new(&config.state_file);
+        let offsets = store.load()?;
+        let followers = config
+            .files
+            .iter()
+            .map(|file| {
+                let saved = offsets.get(&file.path).copied();
+                FileFollower::new(file.clone(), saved, metrics)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            sender: None,
+            thread_handle: None,
+            followers: Some(followers),
+            trace,
+            store: Some(store),
+            offsets,
+            poll_interval: Duration::from_millis(config.poll_interval_ms),
+        })
+    }
+
+    /// Starts the tailer thread and returns self for method chaining.
+    pub fn start(mut self) -> Result<Self, Error> {
+        let (Some(followers), Some(store)) = (self.followers.take(), self.store.take()) else {
+            return Ok(self); // Already started
+        };
+
+        let (sender, receiver) = channel();
+        let trace = self.trace.clone();
+        let offsets = std::mem::take(&mut self.offsets);
+        let poll_interval = self.poll_interval;
+
+        let thread_handle = thread::Builder::new()
+            .name("loggerd-tail".to_string())
+            .spawn(move || {
+                tailer_thread(followers, receiver, trace, store, offsets, poll_interval)
+            })?;
+
+        self.sender = Some(sender);
+        self.thread_handle = Some(thread_handle);
+        Ok(self)
+    }
+}
+
+impl Drop for FileTailer {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up
+        self.sender.take();
+
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Dedicated tailer thread.
+///
+/// Polls the followers until the tailer is dropped, then saves the offsets
+/// one last time. Records still buffered at that point (an incomplete line,
+/// an open multi-line record) are not lost: the saved offsets point before
+/// them, so the next run reads them again.
+fn tailer_thread(
+    mut followers: Vec<FileFollower>,
+    receiver: Receiver<()>,
+    trace: Arc<dyn Trace + Send + Sync>,
+    store: OffsetStore,
+    mut offsets: Offsets,
+    poll_interval: Duration,
+) {
+    loop {
+        let now = Instant::now();
+        let mut behind = false;
+        for follower in &mut followers {
+            behind |= follower.poll(now, &mut |record| trace.log_record(&record));
+        }
+        save_offsets(&followers, &store, &mut offsets);
+
+        let stop = if behind {
+            matches!(
+                receiver.try_recv(),
+                Err(TryRecvError::Disconnected) | Ok(())
+            )
+        } else {
+            !matches!(
+                receiver.recv_timeout(poll_interval),
+                Err(RecvTimeoutError::Timeout)
+            )
+        };
+        if stop {
+            break;
+        }
+    }
+}
+
+/// Saves the offsets if any of them moved.
+///
+/// A file that is currently missing (rotated, not recreated yet) keeps its
+/// last saved offset.
+fn save_offsets(followers: &[FileFollower], store: &OffsetStore, offsets: &mut Offsets) {
+    let mut changed = false;
+    for follower in followers {
+        if let Some(saved) = follower.saved_offset()
+            && offsets.get(follower.path()) != Some(&saved)
+        {
+            offsets.insert(follower.path().to_path_buf(), saved);
+            changed = true;
+        }
+    }
+
+    if changed && let Err(e) = store.save(offsets) {
+        eprintln!("Failed to save tail offsets: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tail::{StartPosition, TailFileConfig};
+    use crate::trace::{RingBufferTraceHandler, TraceLevel};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+
+    fn append(path: &Path, content: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    /// Waits until `buffer` holds `count` records and returns their messages.
+    fn wait_for(buffer: &RingBufferTraceHandler, count: usize) -> Vec<String> {
+        for _ in 0..200 {
+            let records = buffer.recent(None, 100);
+            if records.len() >= count {
+                return records.into_iter().map(|r| r.message).collect();
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out waiting for {} records", count);
+    }
+
+    #[test]
+    fn test_tails_into_trace_and_persists_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        append(&log, "before\n");
+        let config = TailConfig {
+            state_file: dir.path().join("offsets.json"),
+            poll_interval_ms: 10,
+            files: vec![TailFileConfig {
+                path: log.clone(),
+                source: None,
+                level: TraceLevel::Warning,
+                multiline_start: None,
+                start_at: StartPosition::Beginning,
+            }],
+        };
+        let metrics = MetricsRegistry::new();
+
+        let buffer = RingBufferTraceHandler::new(100);
+        let tailer = FileTailer::new(config.clone(), Arc::new(buffer.clone()), &metrics)
+            .unwrap()
+            .start()
+            .unwrap();
+        append(&log, "after\n");
+        assert_eq!(wait_for(&buffer, 2), vec!["before", "after"]);
+
+        let record = &buffer.recent(None, 1)[0];
+        assert_eq!(record.source, log.display().to_string());
+        assert_eq!(record.level, TraceLevel::Warning);
+        drop(tailer);
+
+        // Restarted: only the lines written in the meantime are read
+        append(&log, "while stopped\n");
+        let buffer = RingBufferTraceHandler::new(100);
+        let _tailer = FileTailer::new(config, Arc::new(buffer.clone()), &metrics)
+            .unwrap()
+            .start()
+            .unwrap();
+        assert_eq!(wait_for(&buffer, 1), vec!["while stopped"]);
+        assert!(fs::read_to_string(dir.path().join("offsets.json")).is_ok());
+    }
+}
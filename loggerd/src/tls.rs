@@ -0,0 +1,324 @@
+//! TLS for the HTTP listener.
+//!
+//! Certificates are read from PEM files given in the `[tls]` section. When
+//! `client_ca_path` is set, clients must present a certificate signed by one
+//! of those CAs (mutual TLS). The files can be re-read while the daemon runs
+//! (on SIGHUP): new connections use the new certificates, established ones
+//! keep theirs.
+//!
+//! # Example
+//!
+//! ```toml
+//! [tls]
+//! cert_path = "/etc/loggerd/tls/server.crt"
+//! key_path = "/etc/loggerd/tls/server.key"
+//! client_ca_path = "/etc/loggerd/tls/clients-ca.crt"
+//! ```
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::ServerConfig;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Configuration of the TLS listener (`[tls]` section).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Server certificate chain (PEM), leaf first
+    pub cert_path: PathBuf,
+    /// Private key of the server certificate (PEM, PKCS#8, PKCS#1 or SEC1)
+    pub key_path: PathBuf,
+    /// CA certificates (PEM) of the accepted clients; enables mutual TLS
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// Builds the rustls server configuration described by `config`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read, or `ErrorKind::InvalidData` if
+/// it holds no usable certificate or key.
+pub fn server_config(config: &TlsConfig) -> Result<ServerConfig, Error> {
+    let provider = Arc::new(ring::default_provider());
+    let certs = read_certs(&config.cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|e| pem_error(&config.key_path, e))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => builder.with_client_cert_verifier(client_verifier(ca_path, provider)?),
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_single_cert(certs, key).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {}", config.cert_path.display(), e),
+        )
+    })?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+/// Reads every certificate of a PEM file.
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(path, e))?;
+
+    if certs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: no certificate found", path.display()),
+        ));
+    }
+    Ok(certs)
+}
+
+/// Builds the verifier accepting clients signed by the CAs of `ca_path`.
+fn client_verifier(
+    ca_path: &Path,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>, Error> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in read_certs(ca_path)? {
+        roots.add(cert).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", ca_path.display(), e),
+            )
+        })?;
+    }
+
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", ca_path.display(), e),
+            )
+        })
+}
+
+/// Converts a PEM error, keeping the file name and the I/O error kind.
+fn pem_error(path: &Path, error: rustls::pki_types::pem::Error) -> Error {
+    match error {
+        rustls::pki_types::pem::Error::Io(e) => {
+            Error::new(e.kind(), format!("{}: {}", path.display(), e))
+        }
+        e => Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// TLS configuration of a running listener, reloadable from its files.
+///
+/// Clones share the same certificates: reloading one reloads them all.
+#[derive(Clone)]
+pub struct ReloadableTls {
+    config: TlsConfig,
+    rustls: RustlsConfig,
+}
+
+impl ReloadableTls {
+    /// Loads the certificates of `config`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`server_config`].
+    pub fn new(config: TlsConfig) -> Result<Self, Error> {
+        let rustls = RustlsConfig::from_config(Arc::new(server_config(&config)?));
+        Ok(Self { config, rustls })
+    }
+
+    /// Returns the configuration to give to `axum_server::bind_rustls`.
+    pub fn rustls_config(&self) -> RustlsConfig {
+        self.rustls.clone()
+    }
+
+    /// Re-reads the certificate, key and client CA files.
+    ///
+    /// On error, the previous certificates stay in use.
+    pub fn reload(&self) -> Result<(), Error> {
+        let server_config = server_config(&self.config)?;
+        self.rustls.reload_from_config(Arc::new(server_config));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::routing::get;
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use rustls::pki_types::ServerName;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+
+    /// A certificate authority able to sign server and client certificates.
+    struct TestCa {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    impl TestCa {
+        fn new() -> Self {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let cert = params.self_signed(&key).unwrap();
+            Self { cert, key }
+        }
+
+        /// Returns (certificate PEM, key PEM) for `purpose`.
+        fn issue(&self, purpose: ExtendedKeyUsagePurpose) -> (String, String) {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+            params.extended_key_usages = vec![purpose];
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+            (cert.pem(), key.serialize_pem())
+        }
+    }
+
+    /// Writes the server certificate and key of `ca` where `config` expects them.
+    fn install_server_cert(config: &TlsConfig, ca: &TestCa) {
+        let (cert, key) = ca.issue(ExtendedKeyUsagePurpose::ServerAuth);
+        fs::write(&config.cert_path, cert).unwrap();
+        fs::write(&config.key_path, key).unwrap();
+    }
+
+    /// Serves `GET /health` over TLS on a random port.
+    fn serve(tls: &ReloadableTls) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/health", get(|| async { "OK" }));
+        tokio::spawn(
+            axum_server::from_tcp_rustls(listener, tls.rustls_config())
+                .serve(app.into_make_service()),
+        );
+        addr
+    }
+
+    /// Calls `GET /health` trusting `ca`, optionally with a client certificate.
+    fn get_health(
+        addr: SocketAddr,
+        ca: CertificateDer<'static>,
+        client_cert: Option<(String, String)>,
+    ) -> Result<String, Error> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(ca).unwrap();
+        let builder =
+            rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots);
+        let config = match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(
+                    vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+                    PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let connection =
+            rustls::ClientConnection::new(Arc::new(config), server_name).map_err(Error::other)?;
+        let mut stream = rustls::StreamOwned::new(connection, TcpStream::connect(addr)?);
+
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    fn is_ok(response: &Result<String, Error>) -> bool {
+        response
+            .as_ref()
+            .is_ok_and(|body| body.starts_with("HTTP/1.1 200") && body.ends_with("OK"))
+    }
+
+    fn config(dir: &Path, client_ca_path: Option<PathBuf>) -> TlsConfig {
+        TlsConfig {
+            cert_path: dir.join("server.crt"),
+            key_path: dir.join("server.key"),
+            client_ca_path,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_https_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), None);
+        let (old_ca, new_ca) = (TestCa::new(), TestCa::new());
+        install_server_cert(&config, &old_ca);
+
+        let tls = ReloadableTls::new(config.clone()).unwrap();
+        let addr = serve(&tls);
+        let check = |ca: &TestCa| {
+            let ca = ca.cert.der().clone();
+            tokio::task::spawn_blocking(move || get_health(addr, ca, None))
+        };
+
+        assert!(is_ok(&check(&old_ca).await.unwrap()));
+        assert!(!is_ok(&check(&new_ca).await.unwrap()));
+
+        // A broken file is refused and the current certificate stays in use
+        fs::write(&config.key_path, "not a key").unwrap();
+        assert!(tls.reload().is_err());
+        assert!(is_ok(&check(&old_ca).await.unwrap()));
+
+        install_server_cert(&config, &new_ca);
+        tls.reload().unwrap();
+        assert!(is_ok(&check(&new_ca).await.unwrap()));
+        assert!(!is_ok(&check(&old_ca).await.unwrap()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_client_certificates() {
+        let dir = tempfile::tempdir().unwrap();
+        let server_ca = TestCa::new();
+        let client_ca = TestCa::new();
+        let stranger_ca = TestCa::new();
+
+        let client_ca_path = dir.path().join("clients.crt");
+        fs::write(&client_ca_path, client_ca.cert.pem()).unwrap();
+        let config = config(dir.path(), Some(client_ca_path));
+        install_server_cert(&config, &server_ca);
+
+        let addr = serve(&ReloadableTls::new(config).unwrap());
+        let check = |cert| {
+            let ca = server_ca.cert.der().clone();
+            tokio::task::spawn_blocking(move || get_health(addr, ca, cert))
+        };
+
+        let trusted = client_ca.issue(ExtendedKeyUsagePurpose::ClientAuth);
+        let untrusted = stranger_ca.issue(ExtendedKeyUsagePurpose::ClientAuth);
+        assert!(is_ok(&check(Some(trusted)).await.unwrap()));
+        assert!(!is_ok(&check(Some(untrusted)).await.unwrap()));
+        assert!(!is_ok(&check(None).await.unwrap()));
+    }
+
+    #[test]
+    fn test_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = server_config(&config(dir.path(), None)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains("server.crt"));
+    }
+}
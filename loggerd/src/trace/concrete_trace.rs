@@ -1,8 +1,55 @@
-use std::sync::{Arc, Mutex};
+use arc_swap::ArcSwap;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Once, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use super::drain::DrainReport;
 use super::handlers::TraceHandler;
-use super::level::TraceLevel;
-use super::trace::{HandlerRegister, Trace};
+use super::level::{LevelFilter, TraceLevel};
+use super::record::{DEFAULT_SOURCE, TraceRecord};
+use super::stage::{TraceStage, run_stages, tick_stages};
+use super::trace::{HandlerId, HandlerInfo, HandlerRegister, Trace};
+
+/// Interval between two ticks of the processing stages.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shared list of handlers, replaced by a new copy at each registration.
+type Handlers = Arc<ArcSwap<Vec<Registration>>>;
+/// Shared list of processing stages, replaced like the handlers.
+type Stages = Arc<ArcSwap<Vec<Arc<dyn TraceStage>>>>;
+
+/// A handler with the identifier of its registration.
+#[derive(Clone)]
+struct Registration {
+    id: HandlerId,
+    handler: Arc<dyn TraceHandler>,
+}
+
+thread_local! {
+    /// Set while the thread dispatches a record to the handlers.
+    static DISPATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as dispatching until dropped (unwinding
+/// included).
+struct DispatchGuard;
+
+impl DispatchGuard {
+    /// Returns None if the thread is already dispatching: a record logged
+    /// from a handler or stage (the report of its panic, ...) would come
+    /// back to it, possibly forever.
+    fn enter() -> Option<Self> {
+        (!DISPATCHING.replace(true)).then_some(Self)
+    }
+}
+
+impl Drop for DispatchGuard {
+    fn drop(&mut self) {
+        DISPATCHING.set(false);
+    }
+}
 
 /// Concrete implementation of the Trace trait for the loggerd daemon.
 ///
@@ -11,11 +58,26 @@ use super::trace::{HandlerRegister, Trace};
 /// logging configurations where messages can be sent to multiple destinations
 /// (console, file, network, etc.) simultaneously.
 ///
+/// Before reaching the handlers, records go through the registered
+/// [`TraceStage`]s (rate limiting, ...), in registration order. Records
+/// below the minimum level (see [`ConcreteTrace::level_filter`]) are dropped
+/// first.
+///
+/// [`Trace::drain`] closes the trace: the records logged afterwards are
+/// dropped, and each handler writes out what it has queued.
+///
+/// A panic in a handler or stage doesn't disable the trace, which holds no
+/// lock while dispatching; the handlers and stages of the crate recover the
+/// locks it poisons. Records logged while the same thread is
+/// dispatching (from a handler, or a panic hook reporting the panic of a
+/// handler) are dropped.
+///
 /// # Thread Safety
 ///
-/// This implementation is fully thread-safe, using Arc<Mutex<>> to protect
-/// the handler collection. Multiple threads can log simultaneously and
-/// register new handlers without data races.
+/// Logging never waits for other logging calls: each record reads the
+/// current snapshot of the handler and stage lists, which registrations
+/// replace with an updated copy (copy-on-write). Handlers and stages
+/// synchronize their own state.
 ///
 /// # Usage
 ///
@@ -28,8 +90,18 @@ use super::trace::{HandlerRegister, Trace};
 /// trace.log(TraceLevel::Info, "Hello, world!");
 /// ```
 pub struct ConcreteTrace {
-    /// Thread-safe collection of registered trace handlers
-    handlers: Arc<Mutex<Vec<Box<dyn TraceHandler>>>>,
+    /// Registered trace handlers (copy-on-write snapshot)
+    handlers: Handlers,
+    /// Identifier of the next registration
+    next_id: AtomicU64,
+    /// Processing stages applied before the handlers
+    stages: Stages,
+    /// Starts the tick thread with the first stage
+    ticker: Once,
+    /// Minimum level of the records accepted
+    min_level: LevelFilter,
+    /// Set by the drain at shutdown: records are no longer accepted
+    closed: AtomicBool,
 }
 
 impl ConcreteTrace {
@@ -40,27 +112,391 @@ impl ConcreteTrace {
     /// A new ConcreteTrace ready to accept handler registrations
     pub fn new() -> Self {
         Self {
-            handlers: Arc::new(Mutex::new(Vec::new())),
+            handlers: Arc::new(ArcSwap::from_pointee(Vec::new())),
+            next_id: AtomicU64::new(1),
+            stages: Arc::new(ArcSwap::from_pointee(Vec::new())),
+            ticker: Once::new(),
+            min_level: LevelFilter::default(),
+            closed: AtomicBool::new(false),
         }
     }
+
+    /// Returns the minimum level of the records accepted (every level at
+    /// first); changing it through the returned handle applies at once.
+    pub fn level_filter(&self) -> LevelFilter {
+        self.min_level.clone()
+    }
+
+    /// Appends a processing stage to the pipeline.
+    ///
+    /// The first registered stage starts a background thread that ticks the
+    /// stages every second; it stops once the ConcreteTrace is dropped.
+    pub fn add_stage<S: TraceStage + 'static>(&self, stage: S) {
+        push(&self.stages, Arc::new(stage));
+
+        self.ticker.call_once(|| {
+            let handlers = Arc::downgrade(&self.handlers);
+            let stages = Arc::downgrade(&self.stages);
+            let _ = thread::Builder::new()
+                .name("loggerd-trace-tick".to_string())
+                .spawn(move || tick_thread(handlers, stages));
+        });
+    }
+}
+
+impl Default for ConcreteTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Periodically ticks the stages and dispatches what they emit.
+fn tick_thread(
+    handlers: Weak<ArcSwap<Vec<Registration>>>,
+    stages: Weak<ArcSwap<Vec<Arc<dyn TraceStage>>>>,
+) {
+    loop {
+        thread::sleep(TICK_INTERVAL);
+        let (Some(handlers), Some(stages)) = (handlers.upgrade(), stages.upgrade()) else {
+            break; // ConcreteTrace dropped
+        };
+
+        let Some(_dispatching) = DispatchGuard::enter() else {
+            continue;
+        };
+        let stages = stages.load();
+        let handlers = handlers.load();
+        tick_stages(&stages, Instant::now(), &mut |record| {
+            dispatch(&handlers, &record);
+        });
+    }
+}
+
+/// Sends a record to every handler.
+fn dispatch(handlers: &[Registration], record: &TraceRecord) {
+    for registration in handlers.iter() {
+        registration.handler.log_record(record);
+    }
+}
+
+/// Publishes a copy of `list` with `item` appended; records being dispatched
+/// keep the snapshot they started with.
+fn push<T: Clone>(list: &ArcSwap<Vec<T>>, item: T) {
+    list.rcu(|current| {
+        let mut next = Vec::clone(current);
+        next.push(item.clone());
+        next
+    });
+}
+
+/// Drains a handler taken out of the list, then drops it once the records
+/// being dispatched are done with it, so that its flush and thread join
+/// happen now.
+///
+/// Past `deadline`, or from a handler (the thread is dispatching, and its
+/// own snapshot keeps the handler alive), the last record dispatched to it
+/// drops it instead; only the first case is reported as a failure.
+fn retire(handler: Arc<dyn TraceHandler>, deadline: Instant) -> DrainReport {
+    let mut report = handler.drain(deadline);
+    if !DISPATCHING.get() {
+        while Arc::strong_count(&handler) > 1 {
+            if Instant::now() >= deadline {
+                report.failures.push(format!(
+                    "{}: still dispatching at the deadline, dropped by its last record",
+                    handler.describe()
+                ));
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    report
+}
+
+impl ConcreteTrace {
+    /// Publishes a copy of the handler list where `id` is replaced by
+    /// `replacement` (removed when None), and returns the handler taken out.
+    fn take_handler(
+        &self,
+        id: HandlerId,
+        replacement: Option<Arc<dyn TraceHandler>>,
+    ) -> Option<Arc<dyn TraceHandler>> {
+        let previous = self.handlers.rcu(|current| {
+            current
+                .iter()
+                .filter_map(|registration| match (&replacement, registration.id == id) {
+                    (_, false) => Some(registration.clone()),
+                    (Some(handler), true) => Some(Registration {
+                        id,
+                        handler: handler.clone(),
+                    }),
+                    (None, true) => None,
+                })
+                .collect::<Vec<_>>()
+        });
+        previous
+            .iter()
+            .find(|registration| registration.id == id)
+            .map(|registration| registration.handler.clone())
+    }
 }
 
 impl HandlerRegister for ConcreteTrace {
-    fn register<T: TraceHandler + 'static>(&self, handler: T) {
-        let mut handlers = self.handlers.lock().unwrap();
-        handlers.push(Box::new(handler));
+    fn register<T: TraceHandler + 'static>(&self, handler: T) -> HandlerId {
+        let id = HandlerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        push(
+            &self.handlers,
+            Registration {
+                id,
+                handler: Arc::new(handler),
+            },
+        );
+        id
+    }
+
+    fn unregister(&self, id: HandlerId, deadline: Instant) -> Option<DrainReport> {
+        let handler = self.take_handler(id, None)?;
+        Some(retire(handler, deadline))
+    }
+
+    fn replace<T: TraceHandler + 'static>(
+        &self,
+        id: HandlerId,
+        handler: T,
+        deadline: Instant,
+    ) -> Option<DrainReport> {
+        let handler = self.take_handler(id, Some(Arc::new(handler)))?;
+        Some(retire(handler, deadline))
+    }
+
+    fn list(&self) -> Vec<HandlerInfo> {
+        self.handlers
+            .load()
+            .iter()
+            .map(|registration| HandlerInfo {
+                id: registration.id,
+                description: registration.handler.describe(),
+            })
+            .collect()
     }
 }
 
 impl Trace for ConcreteTrace {
     fn log(&self, level: TraceLevel, message: &str) {
-        let handlers = self.handlers.lock().unwrap();
-        for handler in handlers.iter() {
-            handler.log(level, message);
+        self.log_record(&TraceRecord::new(level, DEFAULT_SOURCE, message));
+    }
+
+    fn log_record(&self, record: &TraceRecord) {
+        if !self.enabled(record.level) {
+            return;
+        }
+        let Some(_dispatching) = DispatchGuard::enter() else {
+            return;
+        };
+        let stages = self.stages.load();
+        let handlers = self.handlers.load();
+        if stages.is_empty() {
+            dispatch(&handlers, record);
+        } else {
+            run_stages(&stages, record.clone(), &mut |record| {
+                dispatch(&handlers, &record);
+            });
+        }
+    }
+
+    fn enabled(&self, level: TraceLevel) -> bool {
+        self.min_level.allows(level) && !self.closed.load(Ordering::Relaxed)
+    }
+
+    fn drain(&self, deadline: Instant) -> DrainReport {
+        self.closed.store(true, Ordering::Relaxed);
+        let handlers = self.handlers.load();
+        let mut report = DrainReport::default();
+        for registration in handlers.iter() {
+            report.merge(registration.handler.drain(deadline));
         }
+        report
     }
 }
 
-// ConcreteTrace is Send + Sync because Arc<Mutex<...>> is already Send + Sync
-unsafe impl Send for ConcreteTrace {}
-unsafe impl Sync for ConcreteTrace {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+    use std::sync::Mutex;
+    use std::sync::mpsc::{Receiver, Sender, channel};
+
+    /// Panics on "boom", keeps the other messages.
+    struct Fragile(Arc<Mutex<Vec<String>>>);
+    impl Trace for Fragile {
+        fn log(&self, _level: TraceLevel, message: &str) {
+            assert_ne!(message, "boom", "fragile handler");
+            self.0.lock().unwrap().push(message.to_string());
+        }
+    }
+    impl TraceHandler for Fragile {}
+
+    /// Logs back into the trace from a handler.
+    struct Echo(Arc<ConcreteTrace>);
+    impl Trace for Echo {
+        fn log(&self, _level: TraceLevel, message: &str) {
+            self.0.log(TraceLevel::Info, &format!("echo {}", message));
+        }
+    }
+    impl TraceHandler for Echo {}
+
+    #[test]
+    fn test_handler_panic_and_reentrant_log() {
+        let trace = Arc::new(ConcreteTrace::new());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        trace.register(Fragile(seen.clone()));
+        trace.register(Echo(trace.clone()));
+
+        trace.log(TraceLevel::Info, "before");
+        let panicked = catch_unwind(AssertUnwindSafe(|| trace.log(TraceLevel::Error, "boom")));
+        assert!(panicked.is_err());
+
+        // Logging goes on, and the echo is dropped
+        trace.log(TraceLevel::Info, "after");
+        assert_eq!(*seen.lock().unwrap(), ["before", "after"]);
+    }
+
+    /// Registers a Fragile handler on its first record.
+    struct Spawner(Arc<ConcreteTrace>, Arc<Mutex<Vec<String>>>, Once);
+    impl Trace for Spawner {
+        fn log(&self, _level: TraceLevel, _message: &str) {
+            self.2.call_once(|| {
+                self.0.register(Fragile(self.1.clone()));
+            });
+        }
+    }
+    impl TraceHandler for Spawner {}
+
+    #[test]
+    fn test_register_during_dispatch() {
+        let trace = Arc::new(ConcreteTrace::new());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        trace.register(Spawner(trace.clone(), seen.clone(), Once::new()));
+
+        // The record being dispatched keeps its snapshot of the handlers
+        trace.log(TraceLevel::Info, "first");
+        trace.log(TraceLevel::Info, "second");
+        assert_eq!(*seen.lock().unwrap(), ["second"]);
+    }
+
+    /// Sets its flag when dropped.
+    struct Dropped(Arc<AtomicBool>);
+    impl Trace for Dropped {
+        fn log(&self, _level: TraceLevel, _message: &str) {}
+    }
+    impl TraceHandler for Dropped {
+        fn describe(&self) -> String {
+            "dropped".to_string()
+        }
+    }
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_unregister_and_replace() {
+        let trace = ConcreteTrace::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let dropped = Arc::new(AtomicBool::new(false));
+        let first = trace.register(Dropped(dropped.clone()));
+        let second = trace.register(Fragile(seen.clone()));
+        assert_ne!(first, second);
+
+        let list = trace.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(
+            (list[0].id, list[0].description.as_str()),
+            (first, "dropped")
+        );
+        assert_eq!(
+            (list[1].id, list[1].description.as_str()),
+            (second, "Fragile")
+        );
+
+        // The old handler is dropped (flushed) before replace returns
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let replaced = Arc::new(AtomicBool::new(false));
+        let report = trace.replace(first, Dropped(replaced.clone()), deadline);
+        assert_eq!(report, Some(DrainReport::default()));
+        assert!(dropped.load(Ordering::SeqCst));
+        assert_eq!(trace.list()[0].id, first);
+
+        assert_eq!(
+            trace.unregister(first, deadline),
+            Some(DrainReport::default())
+        );
+        assert!(replaced.load(Ordering::SeqCst));
+        assert_eq!(trace.unregister(first, deadline), None);
+        assert_eq!(
+            trace.replace(first, Dropped(dropped.clone()), deadline),
+            None
+        );
+
+        trace.log(TraceLevel::Info, "still here");
+        assert_eq!(*seen.lock().unwrap(), ["still here"]);
+        let list = trace.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].id, second);
+    }
+
+    /// Blocks in `log` until released, then sets its flag when dropped.
+    struct Stuck {
+        entered: Mutex<Sender<()>>,
+        release: Mutex<Receiver<()>>,
+        dropped: Arc<AtomicBool>,
+    }
+    impl Trace for Stuck {
+        fn log(&self, _level: TraceLevel, _message: &str) {
+            let _ = self.entered.lock().unwrap().send(());
+            let _ = self.release.lock().unwrap().recv();
+        }
+    }
+    impl TraceHandler for Stuck {
+        fn describe(&self) -> String {
+            "stuck".to_string()
+        }
+    }
+    impl Drop for Stuck {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_unregister_deadline() {
+        let trace = Arc::new(ConcreteTrace::new());
+        let (entered, inside) = channel();
+        let (release, released) = channel();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let id = trace.register(Stuck {
+            entered: Mutex::new(entered),
+            release: Mutex::new(released),
+            dropped: dropped.clone(),
+        });
+        let logger = {
+            let trace = trace.clone();
+            thread::spawn(move || trace.log(TraceLevel::Info, "stuck"))
+        };
+        inside.recv().unwrap();
+
+        // Gives up at the deadline, and the pending record drops the handler
+        let report = trace
+            .unregister(id, Instant::now() + Duration::from_millis(50))
+            .unwrap();
+        assert_eq!(
+            report.failures,
+            ["stuck: still dispatching at the deadline, dropped by its last record"]
+        );
+        assert!(!dropped.load(Ordering::SeqCst));
+        release.send(()).unwrap();
+        logger.join().unwrap();
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, Sender, channel};
+use std::time::Instant;
+
+/// Outcome of draining the handlers at shutdown, see [`super::Trace::drain`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DrainReport {
+    /// Records that were queued when the drain started and reached storage
+    pub flushed: u64,
+    /// Records still queued at the deadline, or queued to a stopped thread
+    pub lost: u64,
+    /// Handlers that missed the deadline or failed to sync, with the reason
+    pub failures: Vec<String>,
+}
+
+impl DrainReport {
+    /// Adds the outcome of another handler.
+    pub fn merge(&mut self, other: DrainReport) {
+        self.flushed += other.flushed;
+        self.lost += other.lost;
+        self.failures.extend(other.failures);
+    }
+
+    /// Returns `true` if nothing was lost and every handler synced.
+    pub fn is_clean(&self) -> bool {
+        self.lost == 0 && self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for DrainReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} records flushed, {} lost", self.flushed, self.lost)
+    }
+}
+
+/// Number of records sent to a handler thread and not yet taken off its
+/// queue.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QueueDepth(Arc<AtomicU64>);
+
+impl QueueDepth {
+    /// Counts a record about to be sent.
+    pub(crate) fn push(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a record taken off the queue (or that could not be sent).
+    pub(crate) fn pop(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of queued records.
+    pub(crate) fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Drains the queue of a handler thread.
+///
+/// Sends the message built by `request` to the thread, which answers once
+/// everything queued before it is written and synced, and waits for the
+/// answer until `deadline`. `name` identifies the handler in the failures.
+pub(crate) fn drain_queue<M>(
+    name: &str,
+    sender: &Sender<M>,
+    request: impl FnOnce(Sender<io::Result<()>>) -> M,
+    depth: &QueueDepth,
+    deadline: Instant,
+) -> DrainReport {
+    let queued = depth.get();
+    let (reply, answer) = channel();
+    let outcome = match sender.send(request(reply)) {
+        Ok(()) => answer.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+        Err(_) => Err(RecvTimeoutError::Disconnected),
+    };
+    match outcome {
+        Ok(Ok(())) => DrainReport {
+            flushed: queued,
+            ..DrainReport::default()
+        },
+        // Written, but maybe not on disk
+        Ok(Err(e)) => DrainReport {
+            flushed: queued,
+            lost: 0,
+            failures: vec![format!("{}: sync failed: {}", name, e)],
+        },
+        Err(error) => {
+            let lost = depth.get();
+            let reason = match error {
+                RecvTimeoutError::Timeout => "drain deadline exceeded",
+                RecvTimeoutError::Disconnected => "thread stopped",
+            };
+            DrainReport {
+                flushed: queued.saturating_sub(lost),
+                lost,
+                failures: vec![format!("{}: {}", name, reason)],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_drain_queue() {
+        let (sender, receiver) = channel::<Option<Sender<io::Result<()>>>>();
+        let depth = QueueDepth::default();
+        let worker_depth = depth.clone();
+        let worker = thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    None => {
+                        thread::sleep(Duration::from_millis(30));
+                        worker_depth.pop();
+                    }
+                    Some(reply) => {
+                        let _ = reply.send(Ok(()));
+                    }
+                }
+            }
+        });
+
+        for _ in 0..3 {
+            depth.push();
+            sender.send(None).unwrap();
+        }
+        let report = drain_queue(
+            "test",
+            &sender,
+            Some,
+            &depth,
+            Instant::now() + Duration::from_secs(5),
+        );
+        assert_eq!(
+            report,
+            DrainReport {
+                flushed: 3,
+                ..DrainReport::default()
+            }
+        );
+
+        for _ in 0..10 {
+            depth.push();
+            sender.send(None).unwrap();
+        }
+        let report = drain_queue(
+            "test",
+            &sender,
+            Some,
+            &depth,
+            Instant::now() + Duration::from_millis(50),
+        );
+        assert!(!report.is_clean());
+        assert!(report.lost > 0 && report.flushed + report.lost == 10);
+        assert_eq!(report.failures, ["test: drain deadline exceeded"]);
+
+        drop(sender);
+        worker.join().unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::record::TraceRecord;
+
+/// Structured fields of a record, by name.
+pub type Fields = BTreeMap<String, String>;
+
+/// Conditions on the fields of a record (`fields = { status = "^5\\d\\d$" }`).
+///
+/// Each configured field must be present and its value must match the
+/// regular expression; an empty filter matches every record. Routing routes
+/// and log metric rules share this syntax.
+#[derive(Debug, Clone, Default)]
+pub struct FieldFilter {
+    conditions: Vec<(String, Regex)>,
+}
+
+impl FieldFilter {
+    /// Compiles the conditions.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first invalid regular expression.
+    pub fn new(conditions: &BTreeMap<String, String>) -> Result<Self, regex::Error> {
+        let conditions = conditions
+            .iter()
+            .map(|(name, pattern)| Ok((name.clone(), Regex::new(pattern)?)))
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self { conditions })
+    }
+
+    /// Returns `true` if the record satisfies every condition.
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.conditions.iter().all(|(name, pattern)| {
+            record
+                .fields
+                .get(name)
+                .is_some_and(|value| pattern.is_match(value))
+        })
+    }
+}
+
+/// Formats fields as logfmt (`status=500 user="jane doe"`).
+pub fn format_logfmt(fields: &Fields) -> String {
+    let mut out = String::new();
+    for (name, value) in fields {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        let quote = value.is_empty()
+            || value
+                .chars()
+                .any(|c| c.is_whitespace() || c == '"' || c == '=' || c == '\\');
+        if quote {
+            let _ = write!(out, "{}={:?}", name, value);
+        } else {
+            let _ = write!(out, "{}={}", name, value);
+        }
+    }
+    out
+}
+
+/// Parses logfmt (`a=1 b="x y" c=`); returns None unless every token is a
+/// `key=value` pair, so that plain sentences are left alone.
+pub(crate) fn parse_logfmt(text: &str) -> Option<Fields> {
+    let mut fields = Fields::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        if key.is_empty() || chars.next() != Some('=') {
+            return None;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        c => value.push(c),
+                    },
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        fields.insert(key, value);
+    }
+
+    (!fields.is_empty()).then_some(fields)
+}
+
+/// Converts a JSON object into fields.
+///
+/// Nested objects are flattened with dotted names (`{"http":{"status":500}}`
+/// gives `http.status=500`), strings are taken as is, other values use their
+/// JSON text and `null` values are skipped.
+pub fn fields_from_json(object: &Map<String, Value>) -> Fields {
+    fn flatten(prefix: &str, object: &Map<String, Value>, fields: &mut Fields) {
+        for (key, value) in object {
+            let name = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                Value::Null => {}
+                Value::String(text) => {
+                    fields.insert(name, text.clone());
+                }
+                Value::Object(nested) => flatten(&name, nested, fields),
+                other => {
+                    fields.insert(name, other.to_string());
+                }
+            }
+        }
+    }
+
+    let mut fields = Fields::new();
+    flatten("", object, &mut fields);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TraceLevel;
+
+    #[test]
+    fn test_filter() {
+        let mut record = TraceRecord::new(TraceLevel::Info, "web", "request");
+        record
+            .fields
+            .insert("status".to_string(), "503".to_string());
+
+        let conditions = BTreeMap::from([("status".to_string(), r"^5\d\d$".to_string())]);
+        assert!(FieldFilter::new(&conditions).unwrap().matches(&record));
+
+        let missing = BTreeMap::from([("user".to_string(), ".*".to_string())]);
+        assert!(!FieldFilter::new(&missing).unwrap().matches(&record));
+        assert!(FieldFilter::default().matches(&record));
+        assert!(FieldFilter::new(&BTreeMap::from([("a".to_string(), "(".to_string())])).is_err());
+    }
+
+    #[test]
+    fn test_format_logfmt() {
+        let fields = Fields::from([
+            ("status".to_string(), "500".to_string()),
+            ("user".to_string(), "jane doe".to_string()),
+            ("empty".to_string(), String::new()),
+        ]);
+        assert_eq!(
+            format_logfmt(&fields),
+            r#"empty="" status=500 user="jane doe""#
+        );
+    }
+}
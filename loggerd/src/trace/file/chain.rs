@@ -0,0 +1,573 @@
+use super::rotation::backup_files;
+use crate::trace::record::DEFAULT_SOURCE;
+use crate::trace::{TraceLevel, TraceRecord};
+use ring::digest::{SHA256, digest};
+use ring::hmac;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// Separates a record from the hash linking it to the previous record.
+const LINK_MARKER: &str = " #chain=";
+
+/// Replaces [`LINK_MARKER`] in the record text, so that a line of a
+/// multi-line message can't pass for a linked line.
+const ESCAPED_MARKER: &str = " #chain\\=";
+
+/// Hash preceding the first record of a new chain.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Bytes added to each line by its link.
+pub(super) const LINK_LEN: usize = LINK_MARKER.len() + GENESIS.len();
+
+/// Message of the record opening each chained file.
+const ANCHOR_MESSAGE: &str = "hash chain start";
+
+/// Message of the record sealing a file before its rotation.
+const CHECKPOINT_MESSAGE: &str = "hash chain checkpoint";
+
+/// Hash chain of one log file, kept by its writer thread.
+///
+/// Every line gets the SHA-256 of the previous hash and of its own text:
+/// ```text
+/// 2025-10-14T17:45:32.123+02:00 [INFO] web - GET / #chain=9f86d0...
+/// ```
+/// Each file opens with an anchor record giving the hash it continues
+/// (`prev`), and a rotated file ends with a checkpoint record giving the
+/// number of records and the head of the chain, signed with HMAC-SHA256 when
+/// a key is configured. Editing, removing or reordering a line breaks every
+/// following hash; without a key, the checkpoints and the anchor of the next
+/// file still reveal a truncated or recomputed backup.
+pub(super) struct HashChain {
+    /// Hash of the last record written
+    head: String,
+    /// Records of the current file, anchor included
+    records: u64,
+    /// Key signing the checkpoints
+    key: Option<hmac::Key>,
+}
+
+impl HashChain {
+    /// Creates a chain starting from [`GENESIS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the key is empty.
+    pub(super) fn new(key: Option<&str>) -> Result<Self> {
+        if key.is_some_and(str::is_empty) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "chain_key must not be empty",
+            ));
+        }
+        Ok(Self {
+            head: GENESIS.to_string(),
+            records: 0,
+            key: key.map(signing_key),
+        })
+    }
+
+    /// Continues the chain of the existing file at `path`.
+    ///
+    /// An empty file continues the newest chained backup. Returns `false`
+    /// (and starts from [`GENESIS`]) when the file has content but no link,
+    /// i.e. was written before the chain was enabled.
+    pub(super) fn resume(&mut self, path: &Path) -> Result<bool> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e),
+        };
+        let mut lines = 0;
+        self.records = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            lines += 1;
+            if let Some((_, link)) = split_link(&line) {
+                self.head = link.to_string();
+                self.records += 1;
+            }
+        }
+        if self.records > 0 {
+            return Ok(true);
+        }
+        if lines > 0 {
+            self.head = GENESIS.to_string();
+            return Ok(false);
+        }
+
+        self.head = backup_files(path)
+            .last()
+            .and_then(|(_, backup)| last_link(backup))
+            .unwrap_or_else(|| GENESIS.to_string());
+        Ok(true)
+    }
+
+    /// Returns the number of records written to the current file, anchor
+    /// included.
+    pub(super) fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Links a formatted record (ending with a newline) to the chain.
+    ///
+    /// The link marker is escaped in the record text (the escaped text is
+    /// what gets hashed and written): only the link ending the record is
+    /// read as one.
+    pub(super) fn link(&mut self, formatted: &str) -> String {
+        let text = formatted.strip_suffix('\n').unwrap_or(formatted);
+        let text = text.replace(LINK_MARKER, ESCAPED_MARKER);
+        self.head = link_hash(&self.head, &text);
+        self.records += 1;
+        format!("{}{}{}\n", text, LINK_MARKER, self.head)
+    }
+
+    /// Returns the linked record opening a new file.
+    pub(super) fn anchor(&mut self) -> String {
+        self.records = 0;
+        let mut record = TraceRecord::new(TraceLevel::Info, DEFAULT_SOURCE, ANCHOR_MESSAGE);
+        record.fields.insert("prev".to_string(), self.head.clone());
+        self.link(&record.to_string())
+    }
+
+    /// Returns the linked record sealing the current file.
+    pub(super) fn checkpoint(&mut self) -> String {
+        let mut record = TraceRecord::new(TraceLevel::Info, DEFAULT_SOURCE, CHECKPOINT_MESSAGE);
+        record
+            .fields
+            .insert("records".to_string(), self.records.to_string());
+        record.fields.insert("head".to_string(), self.head.clone());
+        if let Some(key) = &self.key {
+            let signature =
+                hmac::sign(key, checkpoint_payload(self.records, &self.head).as_bytes());
+            record
+                .fields
+                .insert("signature".to_string(), hex(signature.as_ref()));
+        }
+        self.link(&record.to_string())
+    }
+}
+
+/// Returns the record text of a chained line, without its link.
+///
+/// Lines without a link are returned unchanged.
+pub(super) fn strip_link(line: &str) -> &str {
+    split_link(line).map_or(line, |(text, _)| text)
+}
+
+/// Splits a line into its record text and its link.
+fn split_link(line: &str) -> Option<(&str, &str)> {
+    let (text, link) = line.rsplit_once(LINK_MARKER)?;
+    let valid = link.len() == GENESIS.len()
+        && link
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    valid.then_some((text, link))
+}
+
+/// Returns the last link of a file, if any.
+fn last_link(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| split_link(&line).map(|(_, link)| link.to_string()))
+        .last()
+}
+
+/// Hash linking `text` to the record hashed `prev`.
+fn link_hash(prev: &str, text: &str) -> String {
+    let mut input = Vec::with_capacity(prev.len() + 1 + text.len());
+    input.extend_from_slice(prev.as_bytes());
+    input.push(b'\n');
+    input.extend_from_slice(text.as_bytes());
+    hex(digest(&SHA256, &input).as_ref())
+}
+
+/// Text signed by a checkpoint.
+fn checkpoint_payload(records: u64, head: &str) -> String {
+    format!("{}:{}", records, head)
+}
+
+fn signing_key(key: &str) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Where and why a chain is broken.
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokenLink {
+    /// File holding the faulty record
+    pub file: PathBuf,
+    /// Line of the faulty record (1-based)
+    pub line: usize,
+    /// What doesn't match
+    pub reason: String,
+}
+
+/// Outcome of [`verify_chain`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChainReport {
+    /// Files verified, oldest first
+    pub files: Vec<PathBuf>,
+    /// Leading files written before the chain was enabled (not verified)
+    pub unchained: Vec<PathBuf>,
+    /// Records verified, anchors and checkpoints included
+    pub records: u64,
+    /// Checkpoints verified
+    pub checkpoints: u64,
+    /// `true` if the checkpoint signatures were checked against a key
+    pub signatures_checked: bool,
+    /// First broken link (None when the chain is intact)
+    pub broken: Option<BrokenLink>,
+}
+
+impl ChainReport {
+    /// Returns `true` if no link is broken.
+    pub fn is_intact(&self) -> bool {
+        self.broken.is_none()
+    }
+}
+
+/// Walks the backups of the log file at `path`, oldest first, then the file
+/// itself, and reports the first broken link of their hash chain.
+///
+/// Each file must open with an anchor continuing the previous file, every
+/// record must carry the hash of its text chained to the previous record,
+/// and each backup must end with a checkpoint matching its records. With
+/// `key`, the checkpoints must be signed by it. The oldest file may continue
+/// a backup deleted by the retention; files written before the chain was
+/// enabled are skipped when they come first.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use loggerd::trace::file::verify_chain;
+/// use std::path::Path;
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let report = verify_chain(Path::new("audit.log"), Some("secret"))?;
+/// if let Some(broken) = &report.broken {
+///     eprintln!("{}:{}: {}", broken.file.display(), broken.line, broken.reason);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn verify_chain(path: &Path, key: Option<&str>) -> Result<ChainReport> {
+    let key = key.map(signing_key);
+    let mut report = ChainReport {
+        signatures_checked: key.is_some(),
+        ..ChainReport::default()
+    };
+    let mut files: Vec<PathBuf> = backup_files(path)
+        .into_iter()
+        .map(|(_, backup)| backup)
+        .collect();
+    let backups = files.len();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+
+    // Hash ending the previous chained file
+    let mut previous: Option<String> = None;
+    for (index, file) in files.iter().enumerate() {
+        let sealed = index < backups;
+        let outcome = verify_file(file, sealed, previous.as_deref(), key.as_ref(), &mut report)?;
+        match outcome {
+            FileOutcome::Unchained if previous.is_none() => report.unchained.push(file.clone()),
+            FileOutcome::Unchained => {
+                report.broken = Some(BrokenLink {
+                    file: file.clone(),
+                    line: 1,
+                    reason: "file is not chained".to_string(),
+                });
+            }
+            FileOutcome::Chained(head) => {
+                report.files.push(file.clone());
+                previous = Some(head);
+            }
+            FileOutcome::Broken(broken) => report.broken = Some(broken),
+        }
+        if report.broken.is_some() {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+enum FileOutcome {
+    /// No link at all
+    Unchained,
+    /// Intact, with the hash of its last record
+    Chained(String),
+    Broken(BrokenLink),
+}
+
+/// Verifies one file of the chain.
+fn verify_file(
+    path: &Path,
+    sealed: bool,
+    previous: Option<&str>,
+    key: Option<&hmac::Key>,
+    report: &mut ChainReport,
+) -> Result<FileOutcome> {
+    let broken = |line: usize, reason: String| {
+        Ok(FileOutcome::Broken(BrokenLink {
+            file: path.to_path_buf(),
+            line,
+            reason,
+        }))
+    };
+
+    let mut head: Option<String> = None;
+    let mut records = 0u64;
+    let mut checkpoint_seen = false;
+    let mut last_line = 0;
+    // Record spanning several lines: its first line and its text so far
+    let mut pending: Option<(usize, String)> = None;
+
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        last_line = number;
+        let (start, text) = match pending.take() {
+            Some((start, mut text)) => {
+                text.push('\n');
+                text.push_str(&line);
+                (start, text)
+            }
+            None => (number, line),
+        };
+        let Some((record_text, link)) = split_link(&text) else {
+            pending = Some((start, text));
+            continue;
+        };
+
+        let record = record_text.parse::<TraceRecord>().ok();
+        let prev = match &head {
+            Some(head) => head.clone(),
+            None => {
+                // The anchor gives the hash the file continues
+                let Some(prev) = record
+                    .as_ref()
+                    .filter(|record| record.message == ANCHOR_MESSAGE)
+                    .and_then(|record| record.fields.get("prev"))
+                else {
+                    return broken(start, "file does not open with a chain anchor".to_string());
+                };
+                match previous {
+                    Some(previous) if prev != previous => {
+                        return broken(
+                            start,
+                            format!("anchor continues {} instead of {}", prev, previous),
+                        );
+                    }
+                    None if prev != GENESIS && !report.unchained.is_empty() => {
+                        return broken(start, "previous file is not chained".to_string());
+                    }
+                    _ => prev.clone(),
+                }
+            }
+        };
+
+        if checkpoint_seen {
+            return broken(start, "record after the checkpoint".to_string());
+        }
+        if link_hash(&prev, record_text) != link {
+            return broken(
+                start,
+                "hash mismatch: the record or one before it was modified, removed or reordered"
+                    .to_string(),
+            );
+        }
+
+        if let Some(record) = record.filter(|record| record.message == CHECKPOINT_MESSAGE) {
+            let field = |name: &str| record.fields.get(name).map(String::as_str);
+            if field("records") != Some(records.to_string().as_str()) {
+                return broken(
+                    start,
+                    format!(
+                        "checkpoint counts {} records, the file has {}",
+                        field("records").unwrap_or("no"),
+                        records
+                    ),
+                );
+            }
+            if field("head") != Some(prev.as_str()) {
+                return broken(
+                    start,
+                    "checkpoint head does not match the chain".to_string(),
+                );
+            }
+            if let Some(key) = key {
+                let signature = field("signature").unwrap_or_default();
+                let valid = signature.len() % 2 == 0
+                    && (0..signature.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&signature[i..i + 2], 16))
+                        .collect::<std::result::Result<Vec<u8>, _>>()
+                        .is_ok_and(|bytes| {
+                            hmac::verify(key, checkpoint_payload(records, &prev).as_bytes(), &bytes)
+                                .is_ok()
+                        });
+                if !valid {
+                    return broken(start, "checkpoint signature is invalid".to_string());
+                }
+            }
+            checkpoint_seen = true;
+            report.checkpoints += 1;
+        }
+
+        head = Some(link.to_string());
+        records += 1;
+        report.records += 1;
+    }
+
+    let Some(head) = head else {
+        return Ok(match pending {
+            None if !sealed => FileOutcome::Chained(previous.unwrap_or(GENESIS).to_string()),
+            _ => FileOutcome::Unchained,
+        });
+    };
+    if let Some((start, _)) = pending {
+        return broken(start, "record without link".to_string());
+    }
+    if sealed && !checkpoint_seen {
+        return broken(
+            last_line,
+            "backup does not end with a checkpoint (truncated?)".to_string(),
+        );
+    }
+    Ok(FileOutcome::Chained(head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::Trace;
+    use crate::trace::file::FileTraceHandler;
+    use std::fs;
+
+    fn chained_handler(path: &Path) -> FileTraceHandler {
+        FileTraceHandler::new(path.to_str().unwrap())
+            .unwrap()
+            .with_hash_chain(Some("secret"))
+            .unwrap()
+            .start()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_chain_across_rotations_and_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+
+        let handler = chained_handler(&path);
+        handler.log(TraceLevel::Info, "first");
+        handler.log(TraceLevel::Info, "multi\nline");
+        handler.rotate().unwrap().unwrap();
+        handler.log(TraceLevel::Info, "after rotation");
+        drop(handler);
+        // A restart continues the chain of the active file
+        let handler = chained_handler(&path);
+        handler.log(TraceLevel::Info, "after restart");
+        drop(handler);
+
+        let report = verify_chain(&path, Some("secret")).unwrap();
+        assert!(report.is_intact(), "{:?}", report.broken);
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.checkpoints, 1);
+        // anchor, 2 records, checkpoint; anchor, 2 records
+        assert_eq!(report.records, 7);
+
+        let wrong_key = verify_chain(&path, Some("other")).unwrap();
+        assert!(wrong_key.broken.unwrap().reason.contains("signature"));
+    }
+
+    #[test]
+    fn test_tampering_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let handler = chained_handler(&path);
+        for message in ["one", "two", "three"] {
+            handler.log(TraceLevel::Info, message);
+        }
+        handler.rotate().unwrap().unwrap();
+        handler.log(TraceLevel::Info, "four");
+        drop(handler);
+        let backup = backup_files(&path).pop().unwrap().1;
+        let original = fs::read_to_string(&backup).unwrap();
+
+        // Edited record
+        fs::write(&backup, original.replace("- two", "- 2")).unwrap();
+        let broken = verify_chain(&path, None).unwrap().broken.unwrap();
+        assert_eq!((broken.file.as_path(), broken.line), (backup.as_path(), 3));
+        assert!(broken.reason.contains("hash mismatch"));
+
+        // Removed record
+        let lines: Vec<&str> = original.lines().collect();
+        let removed = [&lines[..2], &lines[3..]].concat().join("\n") + "\n";
+        fs::write(&backup, removed).unwrap();
+        assert_eq!(verify_chain(&path, None).unwrap().broken.unwrap().line, 3);
+
+        // Truncated backup
+        fs::write(&backup, lines[..3].join("\n") + "\n").unwrap();
+        let broken = verify_chain(&path, None).unwrap().broken.unwrap();
+        assert!(broken.reason.contains("checkpoint"));
+
+        fs::write(&backup, &original).unwrap();
+        assert!(verify_chain(&path, None).unwrap().is_intact());
+    }
+
+    #[test]
+    fn test_marker_in_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let handler = chained_handler(&path);
+        let forged = format!("x{}{}\ny", LINK_MARKER, "a".repeat(GENESIS.len()));
+        handler.log(TraceLevel::Info, &forged);
+        handler.log(
+            TraceLevel::Info,
+            &format!("ends with{}{}", LINK_MARKER, GENESIS),
+        );
+        drop(handler);
+
+        let report = verify_chain(&path, None).unwrap();
+        assert!(report.is_intact(), "{:?}", report.broken);
+        assert_eq!(report.records, 3);
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.matches(LINK_MARKER).count(), 3);
+        assert!(content.contains(&format!("x{}{}\ny", ESCAPED_MARKER, "a".repeat(64))));
+    }
+
+    #[test]
+    fn test_unchained_file_is_rotated_away() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        fs::write(&path, "2025-10-14T12:00:00.000+00:00 [INFO] web - plain\n").unwrap();
+
+        let handler = chained_handler(&path);
+        handler.log(TraceLevel::Info, "chained");
+        drop(handler);
+
+        let report = verify_chain(&path, None).unwrap();
+        assert!(report.is_intact(), "{:?}", report.broken);
+        assert_eq!(report.unchained.len(), 1);
+        assert_eq!(report.files, vec![path.clone()]);
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            strip_link(content.lines().last().unwrap())
+                .split(" - ")
+                .last(),
+            Some("chained")
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use super::rotation::backup_files;
+use crate::metrics::MetricsRegistry;
+use crate::trace::TraceLevel;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// Configuration of the disk-space guard (`[disk_guard]` section).
+///
+/// Thresholds are percentages of free space on the filesystem holding each
+/// log directory, from the least to the most drastic reaction:
+///
+/// ```toml
+/// [disk_guard]
+/// check_interval_secs = 10
+/// cleanup_below_percent = 15.0   # remove the oldest rotated backups
+/// shed_below_percent = 10.0      # drop records below shed_min_level
+/// shed_min_level = "error"
+/// pause_below_percent = 5.0      # stop writing the log files
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskGuardConfig {
+    /// Delay between two checks of the free space
+    pub check_interval_secs: u64,
+    /// Below this, rotated backups are removed, oldest first
+    pub cleanup_below_percent: f64,
+    /// Below this, records less severe than `shed_min_level` are not written
+    pub shed_below_percent: f64,
+    /// Minimum level still written while shedding
+    pub shed_min_level: TraceLevel,
+    /// Below this, nothing is written to the log files
+    pub pause_below_percent: f64,
+}
+
+impl Default for DiskGuardConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 10,
+            cleanup_below_percent: 15.0,
+            shed_below_percent: 10.0,
+            shed_min_level: TraceLevel::Error,
+            pause_below_percent: 5.0,
+        }
+    }
+}
+
+impl DiskGuardConfig {
+    /// Checks that the thresholds are percentages, in decreasing order.
+    fn validate(&self) -> Result<(), Error> {
+        let ordered = (0.0..=100.0).contains(&self.cleanup_below_percent)
+            && self.cleanup_below_percent >= self.shed_below_percent
+            && self.shed_below_percent >= self.pause_below_percent
+            && self.pause_below_percent >= 0.0;
+        if !ordered {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "disk guard thresholds must satisfy \
+                 100 >= cleanup_below_percent >= shed_below_percent >= pause_below_percent >= 0",
+            ));
+        }
+        if self.check_interval_secs == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "disk guard check_interval_secs must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the state matching a free space percentage.
+    fn state_for(&self, free_percent: f64) -> DiskState {
+        if free_percent < self.pause_below_percent {
+            DiskState::Paused
+        } else if free_percent < self.shed_below_percent {
+            DiskState::Shedding
+        } else if free_percent < self.cleanup_below_percent {
+            DiskState::Cleanup
+        } else {
+            DiskState::Ok
+        }
+    }
+}
+
+/// Reaction of the guard to the free space of a log directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskState {
+    /// Enough free space
+    Ok = 0,
+    /// Rotated backups are being removed
+    Cleanup = 1,
+    /// Only severe records are written
+    Shedding = 2,
+    /// Nothing is written
+    Paused = 3,
+}
+
+impl DiskState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => DiskState::Ok,
+            1 => DiskState::Cleanup,
+            2 => DiskState::Shedding,
+            _ => DiskState::Paused,
+        }
+    }
+
+    /// Returns the lowercase name of the state.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiskState::Ok => "ok",
+            DiskState::Cleanup => "cleanup",
+            DiskState::Shedding => "shedding",
+            DiskState::Paused => "paused",
+        }
+    }
+}
+
+impl fmt::Display for DiskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Space of a filesystem, as reported by `statvfs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    /// Bytes available to unprivileged processes
+    pub available_bytes: u64,
+    /// Size of the filesystem
+    pub total_bytes: u64,
+}
+
+impl DiskSpace {
+    /// Returns the space of the filesystem holding `path`.
+    pub fn of(path: &Path) -> Result<Self, Error> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        // SAFETY: statvfs only writes into the zeroed struct we pass
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        let block_size = stat.f_frsize as u64;
+        Ok(Self {
+            available_bytes: stat.f_bavail as u64 * block_size,
+            total_bytes: stat.f_blocks as u64 * block_size,
+        })
+    }
+
+    /// Returns the available space in percent of the filesystem size.
+    pub fn free_percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 100.0;
+        }
+        self.available_bytes as f64 * 100.0 / self.total_bytes as f64
+    }
+}
+
+/// Write admission of the file handlers of one directory.
+///
+/// Set by the guard thread, read by [`super::FileTraceHandler`] for every
+/// record, so it is a plain atomic.
+#[derive(Debug)]
+pub struct DiskGate {
+    state: AtomicU8,
+    shed_min_level: TraceLevel,
+    /// `disk_guard_dropped_total{path=...}`
+    dropped: Arc<AtomicU64>,
+}
+
+impl DiskGate {
+    /// Returns the current state of the directory.
+    pub fn state(&self) -> DiskState {
+        DiskState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    /// Returns `true` if a record of `level` may be written; refused records
+    /// are counted.
+    pub fn admits(&self, level: TraceLevel) -> bool {
+        let admitted = match self.state() {
+            DiskState::Ok | DiskState::Cleanup => true,
+            DiskState::Shedding => level >= self.shed_min_level,
+            DiskState::Paused => false,
+        };
+        if !admitted {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        admitted
+    }
+}
+
+/// State of a watched directory, as served by `/health`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskStatus {
+    /// Watched directory
+    pub path: String,
+    /// Current reaction of the guard
+    pub state: DiskState,
+    /// Bytes available at the last check
+    pub available_bytes: u64,
+    /// Size of the filesystem
+    pub total_bytes: u64,
+    /// Available space, in percent (one decimal)
+    pub free_percent: f64,
+}
+
+/// A log directory and the files written in it.
+struct WatchedDir {
+    dir: PathBuf,
+    /// Active log files (their rotated backups may be removed)
+    files: Vec<PathBuf>,
+    gate: Arc<DiskGate>,
+    /// `disk_available_bytes{path=...}`
+    available: Arc<AtomicU64>,
+    /// `disk_total_bytes{path=...}`
+    total: Arc<AtomicU64>,
+    /// `disk_guard_state{path=...}`: 0 ok, 1 cleanup, 2 shedding, 3 paused
+    state_gauge: Arc<AtomicU64>,
+    /// `disk_guard_removed_files_total{path=...}`
+    removed: Arc<AtomicU64>,
+}
+
+/// Function returning the space of the filesystem holding a directory.
+type SpaceProbe = fn(&Path) -> Result<DiskSpace, Error>;
+
+impl WatchedDir {
+    /// Checks the free space, removes backups if needed and updates the gate.
+    fn check(&self, config: &DiskGuardConfig, probe: SpaceProbe) {
+        let mut space = match probe(&self.dir) {
+            Ok(space) => space,
+            Err(e) => {
+                eprintln!("Disk guard: cannot check {}: {}", self.dir.display(), e);
+                return;
+            }
+        };
+
+        if space.free_percent() < config.cleanup_below_percent {
+            for backup in self.backups() {
+                if space.free_percent() >= config.cleanup_below_percent {
+                    break;
+                }
+                match fs::remove_file(&backup) {
+                    Ok(()) => {
+                        self.removed.fetch_add(1, Ordering::Relaxed);
+                        eprintln!("Disk guard: removed old backup {}", backup.display());
+                    }
+                    Err(e) => eprintln!("Disk guard: cannot remove {}: {}", backup.display(), e),
+                }
+                space = probe(&self.dir).unwrap_or(space);
+            }
+        }
+
+        let state = config.state_for(space.free_percent());
+        let previous = DiskState::from_u8(self.gate.state.swap(state as u8, Ordering::Relaxed));
+        if previous != state {
+            eprintln!(
+                "Disk guard: {} is now {} ({:.1}% free)",
+                self.dir.display(),
+                state,
+                space.free_percent()
+            );
+        }
+        self.available
+            .store(space.available_bytes, Ordering::Relaxed);
+        self.total.store(space.total_bytes, Ordering::Relaxed);
+        self.state_gauge.store(state as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the rotated backups of the watched files, oldest first.
+    fn backups(&self) -> Vec<PathBuf> {
+        let mut backups: Vec<(SystemTime, PathBuf)> = self
+            .files
+            .iter()
+            .flat_map(|file| backup_files(file))
+            .collect();
+        backups.sort();
+        backups.into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn status(&self) -> DiskStatus {
+        let space = DiskSpace {
+            available_bytes: self.available.load(Ordering::Relaxed),
+            total_bytes: self.total.load(Ordering::Relaxed),
+        };
+        DiskStatus {
+            path: self.dir.display().to_string(),
+            state: self.gate.state(),
+            available_bytes: space.available_bytes,
+            total_bytes: space.total_bytes,
+            free_percent: (space.free_percent() * 10.0).round() / 10.0,
+        }
+    }
+}
+
+/// Watchdog of the free space of the log directories.
+///
+/// A background thread checks, every `check_interval_secs`, the filesystem
+/// holding the directory of each log file (`statvfs`). As free space goes
+/// down, it successively:
+///
+/// 1. removes rotated backups of the log files, oldest first, until free
+///    space is back above `cleanup_below_percent`;
+/// 2. drops the records below `shed_min_level`;
+/// 3. pauses the writes to the log files of the directory.
+///
+/// Writes resume by themselves once space is freed. The console, the ring
+/// buffer and forwarding are not affected. States are exported as metrics
+/// (`disk_guard_state{path}`, `disk_available_bytes{path}`, ...) and by
+/// `/health`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use loggerd::metrics::MetricsRegistry;
+/// use loggerd::trace::file::{DiskGuard, DiskGuardConfig, FileTraceHandler};
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let guard =
+///     DiskGuard::new(&DiskGuardConfig::default(), &["app.log"], &MetricsRegistry::new())?.start()?;
+/// let handler = FileTraceHandler::new("app.log")?
+///     .with_disk_gate(guard.gate("app.log").unwrap())
+///     .start()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct DiskGuard {
+    /// Channel sender used to stop the guard thread
+    sender: Option<Sender<()>>,
+    /// Handle to the background guard thread
+    thread_handle: Option<JoinHandle<()>>,
+    dirs: Arc<Vec<WatchedDir>>,
+    config: DiskGuardConfig,
+}
+
+impl DiskGuard {
+    /// Creates the guard of the directories of `files` and checks them once,
+    /// so that the gates are right before the first write.
+    ///
+    /// **Note**: Call `.start()` to begin the guard thread
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[disk_guard]` section
+    /// * `files` - Paths of the guarded log files
+    /// * `metrics` - Registry receiving the guard metrics
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` for inconsistent thresholds.
+    pub fn new(
+        config: &DiskGuardConfig,
+        files: &[&str],
+        metrics: &MetricsRegistry,
+    ) -> Result<Self, Error> {
+        config.validate()?;
+
+        let mut dirs: Vec<WatchedDir> = Vec::new();
+        for file in files {
+            let file = PathBuf::from(file);
+            let dir = match file.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            if let Some(watched) = dirs.iter_mut().find(|watched| watched.dir == dir) {
+                watched.files.push(file);
+                continue;
+            }
+
+            let path = dir.display().to_string();
+            let labels = [("path", path.as_str())];
+            dirs.push(WatchedDir {
+                gate: Arc::new(DiskGate {
+                    state: AtomicU8::new(DiskState::Ok as u8),
+                    shed_min_level: config.shed_min_level,
+                    dropped: metrics.counter("disk_guard_dropped_total", &labels),
+                }),
+                available: metrics.gauge("disk_available_bytes", &labels),
+                total: metrics.gauge("disk_total_bytes", &labels),
+                state_gauge: metrics.gauge("disk_guard_state", &labels),
+                removed: metrics.counter("disk_guard_removed_files_total", &labels),
+                dir,
+                files: vec![file],
+            });
+        }
+
+        for dir in &dirs {
+            dir.check(config, DiskSpace::of);
+        }
+
+        Ok(Self {
+            sender: None,
+            thread_handle: None,
+            dirs: Arc::new(dirs),
+            config: config.clone(),
+        })
+    }
+
+    /// Starts the guard thread and returns self for method chaining.
+    pub fn start(mut self) -> Result<Self, Error> {
+        if self.sender.is_some() {
+            return Ok(self); // Already started
+        }
+
+        let (sender, receiver) = channel();
+        let dirs = self.dirs.clone();
+        let config = self.config.clone();
+
+        let thread_handle = thread::Builder::new()
+            .name("loggerd-disk-guard".to_string())
+            .spawn(move || guard_thread(dirs, receiver, config))?;
+
+        self.sender = Some(sender);
+        self.thread_handle = Some(thread_handle);
+        Ok(self)
+    }
+
+    /// Returns the gate of the directory holding `file`, if it is guarded.
+    pub fn gate(&self, file: &str) -> Option<Arc<DiskGate>> {
+        self.dirs
+            .iter()
+            .find(|dir| dir.files.iter().any(|watched| watched == Path::new(file)))
+            .map(|dir| dir.gate.clone())
+    }
+
+    /// Returns the state of every watched directory.
+    pub fn statuses(&self) -> Vec<DiskStatus> {
+        self.dirs.iter().map(WatchedDir::status).collect()
+    }
+}
+
+impl Drop for DiskGuard {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up
+        self.sender.take();
+
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Dedicated guard thread: checks every directory until the guard is dropped.
+fn guard_thread(dirs: Arc<Vec<WatchedDir>>, receiver: Receiver<()>, config: DiskGuardConfig) {
+    let interval = Duration::from_secs(config.check_interval_secs);
+    while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(interval) {
+        for dir in dirs.iter() {
+            dir.check(&config, DiskSpace::of);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        /// Free percentage reported by `fake_space`
+        static FREE_PERCENT: Cell<u64> = const { Cell::new(50) };
+    }
+
+    /// Reports FREE_PERCENT, plus 4% per file removed from the directory.
+    fn fake_space(dir: &Path) -> Result<DiskSpace, Error> {
+        let files = fs::read_dir(dir)?.count() as u64;
+        let removed = 4u64.saturating_sub(files);
+        Ok(DiskSpace {
+            available_bytes: FREE_PERCENT.get() + removed * 4,
+            total_bytes: 100,
+        })
+    }
+
+    #[test]
+    fn test_states_and_gate() {
+        let config = DiskGuardConfig::default();
+        assert_eq!(config.state_for(50.0), DiskState::Ok);
+        assert_eq!(config.state_for(12.0), DiskState::Cleanup);
+        assert_eq!(config.state_for(7.0), DiskState::Shedding);
+        assert_eq!(config.state_for(1.0), DiskState::Paused);
+
+        let metrics = MetricsRegistry::new();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.log").to_string_lossy().into_owned();
+        let guard = DiskGuard::new(&config, &[&file], &metrics).unwrap();
+        let gate = guard.gate(&file).unwrap();
+        assert!(guard.gate("other.log").is_none());
+
+        gate.state
+            .store(DiskState::Shedding as u8, Ordering::Relaxed);
+        assert!(gate.admits(TraceLevel::Critical));
+        assert!(!gate.admits(TraceLevel::Warning));
+        gate.state.store(DiskState::Paused as u8, Ordering::Relaxed);
+        assert!(!gate.admits(TraceLevel::Critical));
+
+        let path = dir.path().display().to_string();
+        let dropped = metrics.counter("disk_guard_dropped_total", &[("path", &path)]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_cleanup_removes_oldest_backups_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.log").to_string_lossy().into_owned();
+        // Created oldest first; app.log.billing is another log, not a backup
+        for name in [
+            "app.log.2",
+            "app.log.1.20251014_120000",
+            "app.log.billing",
+            "app.log",
+        ] {
+            fs::write(dir.path().join(name), name).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+        let guard = DiskGuard::new(
+            &DiskGuardConfig::default(),
+            &[&file, &format!("{}.billing", file)],
+            &MetricsRegistry::new(),
+        )
+        .unwrap();
+        let watched = &guard.dirs[0];
+        assert_eq!(watched.backups().len(), 2);
+
+        // 8% free: removing app.log.2 gives 12%, app.log.1.* gives 16%
+        FREE_PERCENT.set(8);
+        watched.check(&guard.config, fake_space);
+        assert!(!dir.path().join("app.log.2").exists());
+        assert!(!dir.path().join("app.log.1.20251014_120000").exists());
+        assert!(dir.path().join("app.log.billing").exists());
+        assert_eq!(watched.gate.state(), DiskState::Ok);
+
+        // Nothing left to remove: 0% + 8% freed
+        FREE_PERCENT.set(0);
+        watched.check(&guard.config, fake_space);
+        let status = &guard.statuses()[0];
+        assert_eq!(status.state, DiskState::Shedding);
+        assert_eq!(status.free_percent, 8.0);
+    }
+
+    #[test]
+    fn test_invalid_thresholds() {
+        let config = DiskGuardConfig {
+            shed_below_percent: 20.0,
+            ..DiskGuardConfig::default()
+        };
+        let error = DiskGuard::new(&config, &["app.log"], &MetricsRegistry::new())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,456 @@
+use super::chain::strip_link;
+use super::rotation::backup_files;
+use super::segment::{SegmentReader, is_segment};
+use super::token_index::{SharedIndex, backup_candidates, starts_record};
+use super::writer::TraceMessage;
+use crate::trace::{RecordQuery, TraceRecord, lock};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Error, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, channel};
+
+/// Path, writer channel and token index of a started
+/// [`super::FileTraceHandler`].
+#[derive(Clone)]
+pub(super) struct FileControl {
+    path: PathBuf,
+    sender: Sender<TraceMessage>,
+    index: Option<SharedIndex>,
+}
+
+impl FileControl {
+    pub(super) fn new(
+        path: &str,
+        sender: Sender<TraceMessage>,
+        index: Option<SharedIndex>,
+    ) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            sender,
+            index,
+        }
+    }
+
+    /// Adds the records of `path` (the active file or one of its backups)
+    /// matching `query` to `found`, keeping the last `limit` ones.
+    ///
+    /// Term queries on an indexed target only read the records given by the
+    /// token index; the file is scanned if its index cannot be read or
+    /// rebuilt.
+    fn search(
+        &self,
+        path: &Path,
+        query: &RecordQuery,
+        limit: usize,
+        found: &mut VecDeque<TraceRecord>,
+    ) {
+        if let (Some(terms), Some(index)) = (&query.terms, &self.index)
+            && !is_segment(path)
+        {
+            let candidates = if path == self.path {
+                Ok(lock(index).candidates(terms))
+            } else {
+                backup_candidates(path, terms)
+            };
+            if let Ok(offsets) = candidates {
+                return read_records_at(path, &offsets, query, limit, found);
+            }
+        }
+        search_file(path, query, limit, found);
+    }
+
+    /// Asks the writer thread to rotate the file and waits for the outcome.
+    pub(super) fn rotate(&self) -> Result<Option<String>, Error> {
+        let (reply, outcome) = channel();
+        self.sender
+            .send(TraceMessage::Rotate(reply))
+            .map_err(|_| Error::other("writer thread stopped"))?;
+        outcome
+            .recv()
+            .map_err(|_| Error::other("writer thread stopped"))?
+    }
+}
+
+/// An active log file or one of its rotation backups.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogFileInfo {
+    /// File name, as accepted by [`LogFiles::path_of`]
+    pub name: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification
+    pub modified: Option<DateTime<Local>>,
+    /// `true` for a file being written, `false` for a backup
+    pub active: bool,
+}
+
+impl LogFileInfo {
+    fn of(path: &Path, active: bool) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            name: path.file_name()?.to_string_lossy().into_owned(),
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::from),
+            active,
+        })
+    }
+}
+
+/// Log files of the file output, as served by the admin endpoints.
+///
+/// Files are designated by their name only: [`LogFiles::path_of`] resolves
+/// nothing but the active files and their backups, so a request cannot
+/// reach any other file of the host. When two targets in different
+/// directories share a name, the first one in the routing table wins.
+#[derive(Clone)]
+pub struct LogFiles {
+    targets: Vec<FileControl>,
+}
+
+impl LogFiles {
+    pub(super) fn new(targets: Vec<FileControl>) -> Self {
+        Self { targets }
+    }
+
+    /// Lists every active file followed by its backups, newest first.
+    pub fn list(&self) -> Vec<LogFileInfo> {
+        let mut files = Vec::new();
+        for target in &self.targets {
+            files.extend(LogFileInfo::of(&target.path, true));
+            let backups = backup_files(&target.path);
+            files.extend(
+                backups
+                    .iter()
+                    .rev()
+                    .filter_map(|(_, path)| LogFileInfo::of(path, false)),
+            );
+        }
+        files
+    }
+
+    /// Returns the path of the listed file called `name`.
+    pub fn path_of(&self, name: &str) -> Option<PathBuf> {
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return None;
+        }
+        self.targets.iter().find_map(|target| {
+            if target.path.file_name()? == name {
+                return Some(target.path.clone());
+            }
+            backup_files(&target.path)
+                .into_iter()
+                .map(|(_, path)| path)
+                .find(|path| path.file_name().is_some_and(|file| file == name))
+        })
+    }
+
+    /// Returns the last `limit` records of the files matching `query`, oldest
+    /// first.
+    ///
+    /// Each active file is read after its backups. Backups last modified
+    /// before `query.since` are skipped, and a file is left as soon as a
+    /// record is newer than `query.until`. Lines that don't start with a
+    /// timestamp continue the message of the previous record. With
+    /// `query.terms`, indexed targets only read the records the token index
+    /// points to.
+    pub fn search(&self, query: &RecordQuery, limit: usize) -> Vec<TraceRecord> {
+        let mut records = Vec::new();
+        for target in &self.targets {
+            let mut found = VecDeque::new();
+            for (modified, path) in backup_files(&target.path) {
+                if query
+                    .since
+                    .is_none_or(|since| DateTime::<Local>::from(modified) >= since)
+                {
+                    target.search(&path, query, limit, &mut found);
+                }
+            }
+            target.search(&target.path, query, limit, &mut found);
+            records.extend(found);
+        }
+
+        // Merge the targets
+        records.sort_by_key(|record| record.timestamp);
+        let excess = records.len().saturating_sub(limit);
+        records.split_off(excess)
+    }
+
+    /// Rotates every active file now, through their writer threads.
+    ///
+    /// Returns, for each file, the name of its backup (None when the file
+    /// was empty and left alone) or the error.
+    pub fn rotate(&self) -> Vec<(String, Result<Option<String>, Error>)> {
+        self.targets
+            .iter()
+            .map(|target| {
+                let result = target.rotate().map(|backup| {
+                    backup.map(|backup| {
+                        Path::new(&backup)
+                            .file_name()
+                            .map_or(backup.clone(), |name| name.to_string_lossy().into_owned())
+                    })
+                });
+                (target.path.display().to_string(), result)
+            })
+            .collect()
+    }
+}
+
+/// Adds the records of `path` matching `query` to `found`, keeping the last
+/// `limit` ones. Unreadable files are skipped.
+fn search_file(path: &Path, query: &RecordQuery, limit: usize, found: &mut VecDeque<TraceRecord>) {
+    if is_segment(path) {
+        return search_segment(path, query, limit, found);
+    }
+    let Ok(file) = File::open(path) else {
+        return;
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    let mut pending = String::new();
+
+    let flush = |text: &str, found: &mut VecDeque<TraceRecord>| -> bool {
+        let Ok(record) = strip_link(text).parse::<TraceRecord>() else {
+            return true;
+        };
+        if query.until.is_some_and(|until| record.timestamp > until) {
+            return false;
+        }
+        if query.matches(&record) {
+            if found.len() == limit {
+                found.pop_front();
+            }
+            if limit > 0 {
+                found.push_back(record);
+            }
+        }
+        true
+    };
+
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if starts_record(text) {
+            if !pending.is_empty() && !flush(&pending, found) {
+                return;
+            }
+            pending = text.to_string();
+        } else if !pending.is_empty() {
+            pending.push('\n');
+            pending.push_str(text);
+        }
+    }
+    if !pending.is_empty() {
+        flush(&pending, found);
+    }
+}
+
+/// [`search_file`] for a binary segment, read through its time index.
+fn search_segment(
+    path: &Path,
+    query: &RecordQuery,
+    limit: usize,
+    found: &mut VecDeque<TraceRecord>,
+) {
+    let Ok(mut reader) = SegmentReader::open(path) else {
+        return;
+    };
+    let _ = reader.read_range(query.since, query.until, |record| {
+        if query.matches(&record) {
+            keep_last(found, limit, record);
+        }
+    });
+}
+
+/// Adds the records starting at `offsets` in the text file `path` and
+/// matching `query` to `found`, keeping the last `limit` ones.
+fn read_records_at(
+    path: &Path,
+    offsets: &BTreeSet<u64>,
+    query: &RecordQuery,
+    limit: usize,
+    found: &mut VecDeque<TraceRecord>,
+) {
+    let Ok(file) = File::open(path) else {
+        return;
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    for &offset in offsets {
+        if reader.seek(SeekFrom::Start(offset)).is_err() {
+            return;
+        }
+        // First line, then the continuation lines
+        let mut text = String::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let read = String::from_utf8_lossy(&line);
+            let read = read.trim_end_matches(['\n', '\r']);
+            if !text.is_empty() {
+                if starts_record(read) {
+                    break;
+                }
+                text.push('\n');
+            }
+            text.push_str(read);
+        }
+        if let Ok(record) = strip_link(&text).parse::<TraceRecord>()
+            && query.matches(&record)
+        {
+            keep_last(found, limit, record);
+        }
+    }
+}
+
+/// Appends `record` to `found`, dropping the oldest one beyond `limit`.
+fn keep_last(found: &mut VecDeque<TraceRecord>, limit: usize, record: TraceRecord) {
+    if limit == 0 {
+        return;
+    }
+    if found.len() == limit {
+        found.pop_front();
+    }
+    found.push_back(record);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::file::FileTraceHandler;
+    use crate::trace::{TermQuery, Trace, TraceLevel};
+
+    #[test]
+    fn test_list_rotate_and_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let handler = FileTraceHandler::new(path.to_str().unwrap())
+            .unwrap()
+            .start()
+            .unwrap();
+        let files = LogFiles::new(vec![handler.control().unwrap()]);
+        fs::write(dir.path().join("secret.txt"), "not a log").unwrap();
+
+        // Empty files are not rotated
+        assert!(files.rotate()[0].1.as_ref().unwrap().is_none());
+
+        handler.log(TraceLevel::Info, "before rotation");
+        let rotated = files.rotate();
+        let backup = rotated[0].1.as_ref().unwrap().clone().unwrap();
+        assert!(backup.starts_with("app.log.1."));
+        handler.log(TraceLevel::Info, "after rotation");
+        drop(handler); // Joins the writer thread
+
+        let listed = files.list();
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].active && listed[0].name == "app.log");
+        assert!(!listed[1].active && listed[1].name == backup);
+
+        let content = fs::read_to_string(files.path_of(&backup).unwrap()).unwrap();
+        assert!(content.contains("before rotation"));
+        assert_eq!(files.path_of("app.log").unwrap(), path);
+        assert!(files.path_of("secret.txt").is_none());
+        assert!(files.path_of("../app.log").is_none());
+        assert!(files.path_of("..").is_none());
+    }
+
+    #[test]
+    fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(
+            dir.path().join("app.log.1.20251014_120000"),
+            "2025-10-14T11:59:00.000+00:00 [INFO] web - GET / {status=200}\n\
+             2025-10-14T11:59:30.000+00:00 [ERROR] web - GET /pay {status=500}\n",
+        )
+        .unwrap();
+        fs::write(
+            &path,
+            "2025-10-14T12:00:10.000+00:00 [ERROR] db - connection lost\n\
+             Traceback: pool exhausted\n\
+             2025-10-14T12:00:20.000+00:00 [INFO] web - GET /pay {status=200}\n",
+        )
+        .unwrap();
+        let handler = FileTraceHandler::new(path.to_str().unwrap())
+            .unwrap()
+            .start()
+            .unwrap();
+        let files = LogFiles::new(vec![handler.control().unwrap()]);
+
+        let all = files.search(&RecordQuery::default(), 10);
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[2].message, "connection lost\nTraceback: pool exhausted");
+        assert_eq!(files.search(&RecordQuery::default(), 1)[0].source, "web");
+
+        let errors = RecordQuery {
+            level: Some(TraceLevel::Error),
+            ..RecordQuery::default()
+        };
+        let found = files.search(&errors, 10);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].fields["status"], "500");
+
+        let window = RecordQuery {
+            since: Some("2025-10-14T11:59:10+00:00".parse().unwrap()),
+            until: Some("2025-10-14T12:00:10+00:00".parse().unwrap()),
+            pattern: Some(regex::Regex::new("GET|lost").unwrap()),
+            ..RecordQuery::default()
+        };
+        let found = files.search(&window, 10);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].message, "GET /pay");
+        assert!(found[1].message.starts_with("connection lost"));
+    }
+
+    #[test]
+    fn test_search_terms_with_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(
+            dir.path().join("app.log.1.20251014_120000"),
+            "2025-10-14T11:59:30.000+00:00 [ERROR] web - GET /pay {status=500}\n",
+        )
+        .unwrap();
+        fs::write(
+            &path,
+            "2025-10-14T12:00:10.000+00:00 [ERROR] db - connection lost\n\
+             Traceback: pool exhausted\n",
+        )
+        .unwrap();
+        let handler = FileTraceHandler::new(path.to_str().unwrap())
+            .unwrap()
+            .with_token_index()
+            .start()
+            .unwrap();
+        handler.log_record(&TraceRecord::new(TraceLevel::Info, "web", "GET /pay"));
+        let files = LogFiles::new(vec![handler.control().unwrap()]);
+        drop(handler); // Joins the writer thread, which indexed the file
+
+        let terms = |q: &str| RecordQuery {
+            terms: Some(TermQuery::parse(q).unwrap()),
+            ..RecordQuery::default()
+        };
+        let found = files.search(&terms("pay"), 10);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].fields["status"], "500");
+        assert_eq!(found[1].level, TraceLevel::Info);
+        let found = files.search(&terms("pool OR 500"), 10);
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[1].message,
+            "connection lost\nTraceback: pool exhausted"
+        );
+        assert!(files.search(&terms("pay lost"), 10).is_empty());
+    }
+}
@@ -1,11 +1,34 @@
+use super::chain::HashChain;
+use super::disk_guard::DiskGate;
+use super::files::FileControl;
 use super::rotation::RotationConfig;
+use super::segment::{SegmentRecord, segment_writer_thread};
+use super::token_index::SharedIndex;
 use super::writer::{TraceMessage, writer_thread};
-use crate::trace::{Trace, TraceLevel, handlers::TraceHandler};
+use crate::trace::record::DEFAULT_SOURCE;
+use crate::trace::{
+    DrainReport, QueueDepth, Trace, TraceLevel, TraceRecord, drain_queue, handlers::TraceHandler,
+};
+use serde::Deserialize;
 use std::fs::File;
+use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 use std::sync::atomic::AtomicU64;
 use std::sync::mpsc::{Sender, channel};
 use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// Storage format of a log file (`format` of a file target).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    /// One line of text per record
+    #[default]
+    Text,
+    /// Length-prefixed binary records with a CRC and a sparse time index,
+    /// see [`super::SegmentReader`]
+    Binary,
+}
 
 /// File trace handler with automatic rotation.
 ///
@@ -65,6 +88,16 @@ pub struct FileTraceHandler {
     config: RotationConfig,
     /// Shared atomic counter for log metrics
     log_count: Arc<AtomicU64>,
+    /// Write admission set by the disk guard (None when not guarded)
+    disk_gate: Option<Arc<DiskGate>>,
+    /// Hash chain handed to the writer thread (None for a plain file)
+    hash_chain: Option<HashChain>,
+    /// Storage format of the file
+    format: FileFormat,
+    /// Token index of the file, shared with the searches (None when disabled)
+    token_index: Option<SharedIndex>,
+    /// Number of records queued to the writer thread
+    queued: QueueDepth,
 }
 
 impl FileTraceHandler {
@@ -152,6 +185,11 @@ impl FileTraceHandler {
             file_path: file_path.to_string(),
             config,
             log_count: Arc::new(AtomicU64::new(0)),
+            disk_gate: None,
+            hash_chain: None,
+            format: FileFormat::Text,
+            token_index: None,
+            queued: QueueDepth::default(),
         })
     }
 
@@ -185,6 +223,98 @@ impl FileTraceHandler {
         self.log_count.clone()
     }
 
+    /// Replaces the log counter with a shared one (Builder pattern).
+    ///
+    /// Lets several handlers (e.g. the targets of a routing table) report
+    /// into a single metric. Must be called before `.start()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `log_count` - Counter to increment for each written message
+    pub fn with_log_counter(mut self, log_count: Arc<AtomicU64>) -> Self {
+        self.log_count = log_count;
+        self
+    }
+
+    /// Subjects the writes to a disk guard gate (Builder pattern).
+    ///
+    /// Records refused by the gate (low disk space) are dropped before
+    /// reaching the writer thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `gate` - Gate of the directory holding the file, see [`super::DiskGuard::gate`]
+    pub fn with_disk_gate(mut self, gate: Arc<DiskGate>) -> Self {
+        self.disk_gate = Some(gate);
+        self
+    }
+
+    /// Makes the file tamper-evident (Builder pattern).
+    ///
+    /// Each record carries a hash chained to the previous one, and the file
+    /// gets a checkpoint at every rotation, signed with `key` when given; see
+    /// [`super::verify_chain`]. Must be called before `.start()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the key is empty.
+    pub fn with_hash_chain(mut self, key: Option<&str>) -> Result<Self, std::io::Error> {
+        self.hash_chain = Some(HashChain::new(key)?);
+        Ok(self)
+    }
+
+    /// Selects the storage format of the file (Builder pattern).
+    ///
+    /// Must be called before `.start()`. The binary format stores records
+    /// in segments that can be read by time range, see
+    /// [`super::SegmentReader`]; it cannot be hash chained.
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Keeps a token index of the file (Builder pattern).
+    ///
+    /// The writer thread indexes each record it writes and saves the index
+    /// next to each backup, so [`super::LogFiles::search`] answers term
+    /// queries without scanning the files. Only text files are indexed. Must
+    /// be called before `.start()`.
+    pub fn with_token_index(mut self) -> Self {
+        self.token_index = Some(SharedIndex::default());
+        self
+    }
+
+    /// Returns the path of the active log file.
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Rotates the file now, whatever its size.
+    ///
+    /// The rotation goes through the writer thread, so records logged before
+    /// the call end up in the backup. An empty file is left alone.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - The path of the backup
+    /// * `Ok(None)` - If the file was empty
+    /// * `Err(std::io::Error)` - If the handler is not started or the rotation failed
+    pub fn rotate(&self) -> Result<Option<String>, std::io::Error> {
+        self.control()
+            .ok_or_else(|| std::io::Error::other("FileTraceHandler not started"))?
+            .rotate()
+    }
+
+    /// Returns the control of the writer thread (None until started).
+    pub(super) fn control(&self) -> Option<FileControl> {
+        let sender = self.sender.clone()?;
+        Some(FileControl::new(
+            &self.file_path,
+            sender,
+            self.token_index.clone(),
+        ))
+    }
+
     /// Starts the writer thread and returns self for method chaining (Builder pattern).
     ///
     /// This method initializes the background thread responsible for file I/O operations.
@@ -193,7 +323,8 @@ impl FileTraceHandler {
     /// # Returns
     ///
     /// * `Ok(Self)` - If the writer thread was started successfully
-    /// * `Err(std::io::Error)` - If the file cannot be opened for writing
+    /// * `Err(std::io::Error)` - If the file cannot be opened for writing, or
+    ///   `ErrorKind::InvalidInput` for a hash chained binary file
     ///
     /// # Examples
     ///
@@ -211,15 +342,44 @@ impl FileTraceHandler {
         if self.sender.is_some() {
             return Ok(self); // Already started
         }
+        if self.format == FileFormat::Binary
+            && let Some(option) = [
+                self.hash_chain.is_some().then_some("hash_chain"),
+                self.token_index.is_some().then_some("token_index"),
+            ]
+            .into_iter()
+            .flatten()
+            .next()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: {} requires format = \"text\"", self.file_path, option),
+            ));
+        }
 
         let (sender, receiver) = channel::<TraceMessage>();
         let file_path = self.file_path.clone();
         let config = self.config.clone();
         let log_count = self.log_count.clone();
+        let hash_chain = self.hash_chain.take();
+        let token_index = self.token_index.clone();
+        let queued = self.queued.clone();
 
         // Dedicated thread for writing with rotation
-        let thread_handle = thread::spawn(move || {
-            writer_thread(file_path, receiver, config, log_count);
+        let format = self.format;
+        let thread_handle = thread::spawn(move || match format {
+            FileFormat::Text => writer_thread(
+                file_path,
+                receiver,
+                config,
+                log_count,
+                hash_chain,
+                token_index,
+                queued,
+            ),
+            FileFormat::Binary => {
+                segment_writer_thread(file_path, receiver, config, log_count, queued)
+            }
         });
 
         self.sender = Some(sender);
@@ -231,17 +391,51 @@ impl FileTraceHandler {
 
 impl Trace for FileTraceHandler {
     fn log(&self, level: TraceLevel, message: &str) {
+        self.log_record(&TraceRecord::new(level, DEFAULT_SOURCE, message));
+    }
+
+    fn log_record(&self, record: &TraceRecord) {
+        if let Some(gate) = &self.disk_gate
+            && !gate.admits(record.level)
+        {
+            return;
+        }
         if let Some(sender) = &self.sender {
-            let formatted = format!("{} - {}\n", level, message);
+            let message = match self.format {
+                FileFormat::Text => TraceMessage::Log(format!("{}\n", record)),
+                FileFormat::Binary => TraceMessage::Segment(SegmentRecord::encode(record)),
+            };
             // Non-blocking send to writer thread
-            let _ = sender.send(TraceMessage::Log(formatted));
+            self.queued.push();
+            if sender.send(message).is_err() {
+                self.queued.pop();
+            }
         } else {
             eprintln!("Warning: FileTraceHandler not started, call start() first");
         }
     }
+
+    /// Waits until the writer thread has written the queued records and
+    /// synced the file.
+    fn drain(&self, deadline: Instant) -> DrainReport {
+        match &self.sender {
+            Some(sender) => drain_queue(
+                &self.file_path,
+                sender,
+                TraceMessage::Drain,
+                &self.queued,
+                deadline,
+            ),
+            None => DrainReport::default(),
+        }
+    }
 }
 
-impl TraceHandler for FileTraceHandler {}
+impl TraceHandler for FileTraceHandler {
+    fn describe(&self) -> String {
+        format!("file {}", self.file_path)
+    }
+}
 
 // FileTraceHandler is Send because Sender is Send and JoinHandle is Send
 // FileTraceHandler is Sync because we only use a Sender (which implements Sync)
@@ -7,8 +7,14 @@
 //! # Architecture
 //!
 //! - `handler.rs` : Public facade (FileTraceHandler)
+//! - `routing.rs` : Routing table dispatching records to several files (RoutingTraceHandler)
 //! - `writer.rs` : Asynchronous writer thread
 //! - `rotation.rs` : File rotation logic
+//! - `disk_guard.rs` : Free space watchdog of the log directories (DiskGuard)
+//! - `files.rs` : Active files and backups, for the admin endpoints (LogFiles)
+//! - `chain.rs` : Tamper-evident hash chain of the records (verify_chain)
+//! - `segment.rs` : Binary segments with a sparse time index (SegmentReader)
+//! - `token_index.rs` : Inverted index of the tokens of text files, for term queries
 //! - `file_opener.rs` : Cross-platform file opening (Unix/Windows)
 //!
 //! # Features
@@ -45,6 +51,21 @@
 //! 3. Archives the current file with a timestamp
 //! 4. Creates a new log file for continued logging
 //!
+//! # Line Format
+//!
+//! Each record is written on one line with its timestamp and source:
+//! ```text
+//! 2025-10-14T17:45:32.123+02:00 [INFO] loggerd - Application started
+//! ```
+//!
+//! A target with `hash_chain = true` appends to each line the hash linking it
+//! to the previous one (`#chain=<sha256>`), and seals each file with a
+//! checkpoint record before rotating it.
+//!
+//! A target with `format = "binary"` writes length-prefixed records with a
+//! CRC-32 instead, and seals each segment with a sparse timestamp index at
+//! rotation, so time ranges are read without scanning whole files.
+//!
 //! Example rotation sequence:
 //! ```text
 //! Before rotation:
@@ -57,12 +78,23 @@
 //! app.log.2.20231014_120000 (previous backup)
 //! ```
 
+mod chain;
+mod disk_guard;
 mod file_opener;
+mod files;
 mod handler;
 mod rotation;
+mod routing;
+mod segment;
+mod token_index;
 mod writer;
 
 // Public re-exports
-pub use handler::FileTraceHandler;
+pub use chain::{BrokenLink, ChainReport, verify_chain};
+pub use disk_guard::{DiskGate, DiskGuard, DiskGuardConfig, DiskSpace, DiskState, DiskStatus};
+pub use files::{LogFileInfo, LogFiles};
+pub use handler::{FileFormat, FileTraceHandler};
 #[allow(unused_imports)] // Public API for custom config (future use)
 pub use rotation::RotationConfig;
+pub use routing::{FileTargetConfig, RouteConfig, RoutingConfig, RoutingTraceHandler};
+pub use segment::{SegmentReader, is_segment};
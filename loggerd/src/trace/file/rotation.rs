@@ -1,6 +1,8 @@
 use chrono::Local;
 use std::fs;
 use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 /// Configuration for log file rotation.
 ///
@@ -91,7 +93,7 @@ impl RotationConfig {
 ///
 /// # Returns
 ///
-/// * `Ok(())` - If rotation completed successfully
+/// * `Ok(String)` - The name of the backup, if rotation completed successfully
 /// * `Err(std::io::Error)` - If any file operation failed
 ///
 /// # Examples
@@ -115,7 +117,9 @@ impl RotationConfig {
 /// - Missing backup files are ignored (not an error)
 /// - The main log file rename is the critical operation
 /// - Partial rotation is acceptable if some backup shifts fail
-pub fn rotate_log_files(file_path: &str, max_backups: usize) -> Result<()> {
+/// - A second rotation within the same second gets a `_2`, `_3`... suffix
+///   instead of overwriting the first backup
+pub fn rotate_log_files(file_path: &str, max_backups: usize) -> Result<String> {
     // Remove the oldest backup if we've reached the limit
     if max_backups > 0 {
         let oldest = format!("{}.{}", file_path, max_backups);
@@ -131,13 +135,50 @@ pub fn rotate_log_files(file_path: &str, max_backups: usize) -> Result<()> {
 
     // Archive the current file with timestamp
     let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let backup_name = format!("{}.1.{}", file_path, timestamp);
+    let mut backup_name = format!("{}.1.{}", file_path, timestamp);
+    let mut suffix = 1;
+    while Path::new(&backup_name).exists() {
+        suffix += 1;
+        backup_name = format!("{}.1.{}_{}", file_path, timestamp, suffix);
+    }
 
     // Rename file.log -> file.log.1.YYYYMMDD_HHMMSS
     fs::rename(file_path, &backup_name)?;
 
-    eprintln!("Log rotated: {} (max_size exceeded)", backup_name);
-    Ok(())
+    eprintln!("Log rotated: {}", backup_name);
+    Ok(backup_name)
+}
+
+/// Returns `true` if `name` is the name of a rotation backup of the file
+/// named `file_name`: `<file>.<n>` or `<file>.<n>.<timestamp>`.
+///
+/// Other files sharing the prefix (`loggerd.log.billing`) are not backups.
+pub fn is_backup_name(name: &str, file_name: &str) -> bool {
+    name.strip_prefix(file_name)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.split('.').next())
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Returns the rotation backups of a log file with their modification
+/// times, oldest first.
+pub fn backup_files(file_path: &Path) -> Vec<(SystemTime, PathBuf)> {
+    let dir = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let (Some(file_name), Ok(entries)) = (file_path.file_name(), fs::read_dir(dir)) else {
+        return Vec::new();
+    };
+    let file_name = file_name.to_string_lossy();
+
+    let mut backups: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| is_backup_name(&entry.file_name().to_string_lossy(), &file_name))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    backups.sort();
+    backups
 }
 
 #[cfg(test)]
@@ -151,6 +192,32 @@ mod tests {
         assert_eq!(config.max_backups, 5);
     }
 
+    #[test]
+    fn test_backup_names() {
+        assert!(is_backup_name("app.log.1", "app.log"));
+        assert!(is_backup_name("app.log.2.20251014_120000", "app.log"));
+        assert!(!is_backup_name("app.log", "app.log"));
+        assert!(!is_backup_name("app.log.billing", "app.log"));
+        assert!(!is_backup_name("app.log.", "app.log"));
+        assert!(!is_backup_name("other.log.1", "app.log"));
+    }
+
+    #[test]
+    fn test_rotations_in_the_same_second_keep_both_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let file_path = path.to_str().unwrap();
+
+        fs::write(&path, "first\n").unwrap();
+        let first = rotate_log_files(file_path, 5).unwrap();
+        fs::write(&path, "second\n").unwrap();
+        let second = rotate_log_files(file_path, 5).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(backup_files(&path).len(), 2);
+        assert!(!path.exists());
+    }
+
     #[test]
     fn test_custom_config() {
         let config = RotationConfig::new(5 * 1024 * 1024, 3);
@@ -0,0 +1,507 @@
+use super::disk_guard::DiskGuard;
+use super::files::LogFiles;
+use super::handler::{FileFormat, FileTraceHandler};
+use super::rotation::RotationConfig;
+use crate::trace::record::DEFAULT_SOURCE;
+use crate::trace::{
+    DrainReport, FieldFilter, Trace, TraceLevel, TraceRecord, handlers::TraceHandler,
+};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::time::Instant;
+
+/// A log file target with its own rotation settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FileTargetConfig {
+    /// Path to the log file
+    pub path: String,
+    /// Maximum file size before rotation (in bytes)
+    pub max_size_bytes: u64,
+    /// Maximum number of backup files to keep
+    pub max_backups: usize,
+    /// Storage format: `text` lines or `binary` segments
+    pub format: FileFormat,
+    /// Chains every record to the previous one by a SHA-256 hash
+    pub hash_chain: bool,
+    /// Secret signing the checkpoint written at each rotation (HMAC-SHA256);
+    /// requires `hash_chain`
+    pub chain_key: Option<String>,
+    /// Keeps a token index of the file, for term queries (`/logs?q=`);
+    /// requires `format = "text"`
+    pub token_index: bool,
+}
+
+impl Default for FileTargetConfig {
+    fn default() -> Self {
+        let rotation = RotationConfig::default();
+        Self {
+            path: "loggerd.log".to_string(),
+            max_size_bytes: rotation.max_size_bytes,
+            max_backups: rotation.max_backups,
+            format: FileFormat::Text,
+            hash_chain: false,
+            chain_key: None,
+            token_index: false,
+        }
+    }
+}
+
+impl FileTargetConfig {
+    /// Creates the (not yet started) file handler for this target.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created, or
+    /// `ErrorKind::InvalidInput` if `chain_key` is empty or set without
+    /// `hash_chain`.
+    fn handler(&self) -> Result<FileTraceHandler, Error> {
+        let mut handler = FileTraceHandler::with_config(
+            &self.path,
+            RotationConfig::new(self.max_size_bytes, self.max_backups),
+        )?
+        .with_format(self.format);
+        if self.token_index {
+            handler = handler.with_token_index();
+        }
+        match (self.hash_chain, &self.chain_key) {
+            (true, key) => handler.with_hash_chain(key.as_deref()),
+            (false, Some(_)) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: chain_key requires hash_chain = true", self.path),
+            )),
+            (false, None) => Ok(handler),
+        }
+    }
+}
+
+/// A routing rule (`[[routing.routes]]` entry).
+///
+/// Every criterion that is set must match; an empty rule matches everything.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteConfig {
+    /// Route name, used in error messages
+    pub name: String,
+    /// Sources sent to this route (any source when empty)
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Minimum level sent to this route
+    #[serde(default)]
+    pub min_level: Option<TraceLevel>,
+    /// Regular expression the message must match
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Conditions on the record fields (field name → regular expression)
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    /// File written by this route
+    #[serde(flatten)]
+    pub target: FileTargetConfig,
+}
+
+/// Routing table of the file output (`[routing]` section).
+///
+/// ```toml
+/// [routing]
+/// enabled = true             # false: no log file at all
+///
+/// [routing.default]          # catch-all route
+/// path = "loggerd.log"
+/// token_index = true         # term queries, see `/logs?q=`
+///
+/// [[routing.routes]]
+/// name = "billing-errors"
+/// sources = ["billing"]
+/// min_level = "error"
+/// path = "billing-errors.log"
+/// max_size_bytes = 5242880
+/// max_backups = 3
+///
+/// [[routing.routes]]
+/// name = "audit"
+/// sources = ["audit"]
+/// path = "audit.log"
+/// hash_chain = true          # tamper-evident, see `loggerctl verify`
+/// chain_key = "change-me"    # signs the rotation checkpoints
+///
+/// [[routing.routes]]
+/// name = "access"
+/// sources = ["http"]
+/// path = "access.seg"
+/// format = "binary"          # segments read by time range, see `loggerctl export`
+///
+/// [[routing.routes]]
+/// name = "http-errors"
+/// fields = { status = "^5\\d\\d$" }   # fields extracted by a parser
+/// path = "http-errors.log"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// Writes records to files (the other outputs keep working when false)
+    pub enabled: bool,
+    /// Catch-all target for records no route matched
+    pub default: FileTargetConfig,
+    /// Routes, evaluated in order
+    pub routes: Vec<RouteConfig>,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default: FileTargetConfig::default(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+/// Returns `path` made absolute, with its directory resolved when it exists,
+/// so that two spellings of the same file compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let resolved = absolute
+        .parent()
+        .zip(absolute.file_name())
+        .and_then(|(dir, name)| Some(dir.canonicalize().ok()?.join(name)));
+    resolved.unwrap_or(absolute)
+}
+
+/// A compiled route and its file handler.
+struct Route {
+    sources: Vec<String>,
+    min_level: Option<TraceLevel>,
+    pattern: Option<Regex>,
+    fields: FieldFilter,
+    handler: FileTraceHandler,
+}
+
+impl Route {
+    /// Returns `true` if the record satisfies every criterion of the route.
+    fn matches(&self, record: &TraceRecord) -> bool {
+        (self.sources.is_empty() || self.sources.contains(&record.source))
+            && self.min_level.is_none_or(|level| record.level >= level)
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&record.message))
+            && self.fields.matches(record)
+    }
+}
+
+/// Trace handler dispatching records to different files.
+///
+/// Routes are evaluated in configuration order and the first matching one
+/// receives the record; records matched by no route go to the default
+/// (catch-all) file. Each target is a regular [`FileTraceHandler`] with its
+/// own writer thread and rotation settings. All targets share one log
+/// counter, so `log_count` in the metrics keeps counting every written line.
+///
+/// # Examples
+///
+/// ```no_run
+/// use loggerd::trace::file::{RoutingConfig, RoutingTraceHandler};
+/// use loggerd::trace::{Trace, TraceLevel};
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let handler = RoutingTraceHandler::new(&RoutingConfig::default())?.start()?;
+/// handler.log(TraceLevel::Info, "Goes to loggerd.log");
+/// # Ok(())
+/// # }
+/// ```
+pub struct RoutingTraceHandler {
+    /// Routes, in evaluation order
+    routes: Vec<Route>,
+    /// Catch-all target
+    default: FileTraceHandler,
+    /// Counter shared by every target
+    log_count: Arc<AtomicU64>,
+}
+
+impl RoutingTraceHandler {
+    /// Creates the routing table and its file handlers.
+    ///
+    /// **Note**: Call `.start()` to begin the writer threads
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a target file cannot be created, or
+    /// `ErrorKind::InvalidInput` if a route pattern or field condition is not
+    /// a valid regex, or if two targets write to the same file.
+    pub fn new(config: &RoutingConfig) -> Result<Self, Error> {
+        let log_count = Arc::new(AtomicU64::new(0));
+
+        // One writer per file: two would race on its size and rotation
+        let mut paths: Vec<(PathBuf, String)> = Vec::new();
+        let targets = config
+            .routes
+            .iter()
+            .map(|route| (&route.target, format!("route '{}'", route.name)))
+            .chain([(&config.default, "the default target".to_string())]);
+        for (target, name) in targets {
+            let path = normalize(Path::new(&target.path));
+            if let Some((_, other)) = paths.iter().find(|(known, _)| *known == path) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} writes to {}, like {}", name, target.path, other),
+                ));
+            }
+            paths.push((path, name));
+        }
+
+        let mut routes = Vec::with_capacity(config.routes.len());
+        for route in &config.routes {
+            let pattern = route
+                .pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("route '{}': invalid pattern: {}", route.name, e),
+                    )
+                })?;
+            let fields = FieldFilter::new(&route.fields).map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("route '{}': invalid field condition: {}", route.name, e),
+                )
+            })?;
+
+            routes.push(Route {
+                sources: route.sources.clone(),
+                min_level: route.min_level,
+                pattern,
+                fields,
+                handler: route.target.handler()?.with_log_counter(log_count.clone()),
+            });
+        }
+
+        let default = config
+            .default
+            .handler()?
+            .with_log_counter(log_count.clone());
+
+        Ok(Self {
+            routes,
+            default,
+            log_count,
+        })
+    }
+
+    /// Returns the paths of every target, the default one last.
+    pub fn file_paths(&self) -> Vec<&str> {
+        self.routes
+            .iter()
+            .map(|route| route.handler.file_path())
+            .chain([self.default.file_path()])
+            .collect()
+    }
+
+    /// Subjects every target to the gate of its directory (Builder pattern).
+    ///
+    /// Must be called before `.start()`; targets the guard doesn't watch are
+    /// left unguarded.
+    pub fn with_disk_guard(mut self, guard: &DiskGuard) -> Self {
+        let guarded = |handler: FileTraceHandler| match guard.gate(handler.file_path()) {
+            Some(gate) => handler.with_disk_gate(gate),
+            None => handler,
+        };
+        self.routes = self
+            .routes
+            .into_iter()
+            .map(|mut route| {
+                route.handler = guarded(route.handler);
+                route
+            })
+            .collect();
+        self.default = guarded(self.default);
+        self
+    }
+
+    /// Starts the writer thread of every target.
+    pub fn start(mut self) -> Result<Self, Error> {
+        self.routes = self
+            .routes
+            .into_iter()
+            .map(|mut route| {
+                route.handler = route.handler.start()?;
+                Ok(route)
+            })
+            .collect::<Result<_, Error>>()?;
+        self.default = self.default.start()?;
+        Ok(self)
+    }
+
+    /// Returns the files of every target, for listing and forced rotation
+    /// (empty until started).
+    pub fn log_files(&self) -> LogFiles {
+        LogFiles::new(
+            self.routes
+                .iter()
+                .map(|route| &route.handler)
+                .chain([&self.default])
+                .filter_map(FileTraceHandler::control)
+                .collect(),
+        )
+    }
+
+    /// Returns the counter of lines written across all targets.
+    pub fn log_counter(&self) -> Arc<AtomicU64> {
+        self.log_count.clone()
+    }
+}
+
+impl Trace for RoutingTraceHandler {
+    fn log(&self, level: TraceLevel, message: &str) {
+        self.log_record(&TraceRecord::new(level, DEFAULT_SOURCE, message));
+    }
+
+    fn log_record(&self, record: &TraceRecord) {
+        let handler = self
+            .routes
+            .iter()
+            .find(|route| route.matches(record))
+            .map_or(&self.default, |route| &route.handler);
+        handler.log_record(record);
+    }
+
+    fn drain(&self, deadline: Instant) -> DrainReport {
+        let mut report = DrainReport::default();
+        for route in &self.routes {
+            report.merge(route.handler.drain(deadline));
+        }
+        report.merge(self.default.drain(deadline));
+        report
+    }
+}
+
+impl TraceHandler for RoutingTraceHandler {
+    fn describe(&self) -> String {
+        let paths: Vec<_> = self
+            .routes
+            .iter()
+            .map(|route| route.handler.describe())
+            .chain([self.default.describe()])
+            .collect();
+        format!("routing to {}", paths.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn route(name: &str, path: &str) -> RouteConfig {
+        RouteConfig {
+            name: name.to_string(),
+            sources: Vec::new(),
+            min_level: None,
+            pattern: None,
+            fields: BTreeMap::new(),
+            target: FileTargetConfig {
+                path: path.to_string(),
+                ..FileTargetConfig::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_records_follow_first_matching_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+
+        let mut billing = route("billing", &path("billing.log"));
+        billing.sources = vec!["billing".to_string()];
+        let mut errors = route("errors", &path("errors.log"));
+        errors.min_level = Some(TraceLevel::Error);
+        let mut timeouts = route("timeouts", &path("timeouts.log"));
+        timeouts.pattern = Some("time(d )?out".to_string());
+        let mut server_errors = route("server-errors", &path("server-errors.log"));
+        server_errors.fields = BTreeMap::from([("status".to_string(), "^5".to_string())]);
+
+        let config = RoutingConfig {
+            default: FileTargetConfig {
+                path: path("all.log"),
+                ..FileTargetConfig::default()
+            },
+            routes: vec![billing, errors, timeouts, server_errors],
+            ..RoutingConfig::default()
+        };
+
+        let handler = RoutingTraceHandler::new(&config).unwrap().start().unwrap();
+        handler.log_record(&TraceRecord::new(TraceLevel::Error, "billing", "declined"));
+        handler.log_record(&TraceRecord::new(TraceLevel::Error, "web", "crashed"));
+        handler.log_record(&TraceRecord::new(TraceLevel::Info, "web", "timed out"));
+        handler.log_record(&TraceRecord::new(TraceLevel::Info, "web", "ok"));
+        let mut request = TraceRecord::new(TraceLevel::Info, "web", "request");
+        request
+            .fields
+            .insert("status".to_string(), "502".to_string());
+        handler.log_record(&request);
+        let counter = handler.log_counter();
+        drop(handler); // Joins the writer threads
+
+        let read = |name: &str| fs::read_to_string(path(name)).unwrap();
+        assert!(read("billing.log").contains("[ERROR] billing - declined"));
+        assert!(read("errors.log").contains("[ERROR] web - crashed"));
+        assert!(read("timeouts.log").contains("[INFO] web - timed out"));
+        assert!(read("all.log").contains("[INFO] web - ok"));
+        assert!(read("server-errors.log").contains("[INFO] web - request {status=502}"));
+        assert_eq!(read("all.log").lines().count(), 1);
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bad = route("bad", &dir.path().join("bad.log").to_string_lossy());
+        bad.pattern = Some("(".to_string());
+        let config = RoutingConfig {
+            default: FileTargetConfig {
+                path: dir.path().join("all.log").to_string_lossy().into_owned(),
+                ..FileTargetConfig::default()
+            },
+            routes: vec![bad],
+            ..RoutingConfig::default()
+        };
+
+        let error = RoutingTraceHandler::new(&config).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_shared_path_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let same = dir.path().join("same.log").to_string_lossy().into_owned();
+        let config = RoutingConfig {
+            default: FileTargetConfig {
+                path: same.clone(),
+                ..FileTargetConfig::default()
+            },
+            routes: vec![route(
+                "audit",
+                &format!("{}/./same.log", dir.path().display()),
+            )],
+            ..RoutingConfig::default()
+        };
+
+        let error = RoutingTraceHandler::new(&config).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("route 'audit'"), "{}", error);
+
+        let config = RoutingConfig {
+            routes: vec![route("a", &same), route("b", &same)],
+            ..RoutingConfig::default()
+        };
+        let error = RoutingTraceHandler::new(&config).err().unwrap();
+        assert!(error.to_string().contains("like route 'a'"), "{}", error);
+    }
+}
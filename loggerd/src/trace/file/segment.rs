@@ -0,0 +1,663 @@
+use super::file_opener::open_log_file;
+use super::rotation::{RotationConfig, rotate_log_files};
+use super::writer::{TraceMessage, should_rotate};
+use crate::trace::{Fields, QueueDepth, TraceLevel, TraceRecord};
+use chrono::{DateTime, Local, TimeZone};
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+
+/// First bytes of every segment file.
+const SEGMENT_MAGIC: &[u8; 8] = b"LGDSEG01";
+
+/// Last bytes of a sealed segment, after the offset of its index.
+const INDEX_MAGIC: &[u8; 8] = b"LGDIDX01";
+
+/// Size of the segment header (the magic).
+const HEADER_LEN: u64 = SEGMENT_MAGIC.len() as u64;
+
+/// Size of a record frame header: payload length and CRC-32.
+const FRAME_HEADER_LEN: u64 = 8;
+
+/// Size of an index entry: block offset, oldest and newest timestamps.
+const INDEX_ENTRY_LEN: u64 = 24;
+
+/// Size of the trailer: index offset and magic.
+const TRAILER_LEN: u64 = 16;
+
+/// Bytes of records covered by one entry of the sparse index.
+const INDEX_INTERVAL: u64 = 16 * 1024;
+
+/// Largest payload accepted when reading; a larger length is corruption.
+const MAX_PAYLOAD_LEN: u32 = 64 * 1024 * 1024;
+
+/// A record encoded for a segment, ready to be appended.
+///
+/// Encoding happens in the logging thread, like the formatting of text
+/// lines, so the writer thread only copies bytes.
+pub struct SegmentRecord {
+    /// Emission time, in microseconds since the Unix epoch
+    timestamp: i64,
+    /// Frame: payload length, CRC-32 of the payload, payload
+    frame: Vec<u8>,
+}
+
+impl SegmentRecord {
+    /// Encodes a record into its frame.
+    pub(super) fn encode(record: &TraceRecord) -> Self {
+        let timestamp = record.timestamp.timestamp_micros();
+        let mut payload = Vec::with_capacity(32 + record.source.len() + record.message.len());
+        payload.extend_from_slice(&timestamp.to_le_bytes());
+        payload.push(record.level as u8);
+        put_str(&mut payload, &record.source);
+        put_str(&mut payload, &record.message);
+        payload.extend_from_slice(&(record.fields.len() as u32).to_le_bytes());
+        for (name, value) in &record.fields {
+            put_str(&mut payload, name);
+            put_str(&mut payload, value);
+        }
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN as usize + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        Self { timestamp, frame }
+    }
+}
+
+fn put_str(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+/// Decodes the payload of a frame (None if it is malformed).
+fn decode(payload: &[u8]) -> Option<TraceRecord> {
+    let mut cursor = Cursor { bytes: payload };
+    let timestamp = Local
+        .timestamp_micros(i64::from_le_bytes(cursor.take(8)?.try_into().ok()?))
+        .single()?;
+    let level = *TraceLevel::ALL.get(cursor.take(1)?[0] as usize)?;
+    let source = cursor.string()?;
+    let message = cursor.string()?;
+    let mut fields = Fields::new();
+    for _ in 0..cursor.u32()? {
+        let name = cursor.string()?;
+        fields.insert(name, cursor.string()?);
+    }
+    cursor.bytes.is_empty().then_some(TraceRecord {
+        timestamp,
+        level,
+        source,
+        message,
+        fields,
+    })
+}
+
+/// Reads the fields of a payload in order.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
+
+/// Entry of the sparse index: the records of a block of the segment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IndexEntry {
+    /// Offset of the first record of the block
+    offset: u64,
+    /// Oldest timestamp of the block (microseconds)
+    oldest: i64,
+    /// Newest timestamp of the block (microseconds)
+    newest: i64,
+}
+
+/// Sparse index of a segment, built as records are appended.
+///
+/// Records are not assumed to be in time order (clients send their own
+/// timestamps), so each block keeps its oldest and newest timestamps.
+#[derive(Debug, Default)]
+struct SparseIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl SparseIndex {
+    fn add(&mut self, offset: u64, timestamp: i64) {
+        match self.entries.last_mut() {
+            Some(block) if offset - block.offset < INDEX_INTERVAL => {
+                block.oldest = block.oldest.min(timestamp);
+                block.newest = block.newest.max(timestamp);
+            }
+            _ => self.entries.push(IndexEntry {
+                offset,
+                oldest: timestamp,
+                newest: timestamp,
+            }),
+        }
+    }
+}
+
+/// A frame read from a segment.
+enum Frame {
+    /// A record payload whose CRC matches
+    Valid(Vec<u8>),
+    /// A complete frame whose CRC doesn't match
+    Corrupted,
+    /// End of the data, or a truncated or impossible frame
+    End,
+}
+
+/// Reads the frame at `position`, which must end before `end`; returns it
+/// with the position of the next frame.
+fn read_frame(reader: &mut BufReader<File>, position: u64, end: u64) -> (Frame, u64) {
+    let mut header = [0u8; FRAME_HEADER_LEN as usize];
+    if position + FRAME_HEADER_LEN > end || reader.read_exact(&mut header).is_err() {
+        return (Frame::End, position);
+    }
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let next = position + FRAME_HEADER_LEN + u64::from(len);
+    if len > MAX_PAYLOAD_LEN || next > end {
+        return (Frame::End, position);
+    }
+    let mut payload = vec![0u8; len as usize];
+    if reader.read_exact(&mut payload).is_err() {
+        return (Frame::End, position);
+    }
+    if crc32fast::hash(&payload) != crc {
+        return (Frame::Corrupted, next);
+    }
+    (Frame::Valid(payload), next)
+}
+
+/// Returns the timestamp at the start of a payload.
+fn payload_timestamp(payload: &[u8]) -> Option<i64> {
+    Some(i64::from_le_bytes(payload.get(..8)?.try_into().ok()?))
+}
+
+/// Returns `true` if the file at `path` starts like a segment.
+pub fn is_segment(path: &Path) -> bool {
+    let mut magic = [0u8; SEGMENT_MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| &magic == SEGMENT_MAGIC)
+}
+
+/// Reader of a binary segment written by a target with `format = "binary"`.
+///
+/// A segment is a header followed by length-prefixed records, each with the
+/// CRC-32 of its payload. A sealed segment (a rotation backup) ends with a
+/// sparse index giving the oldest and newest timestamp of every 16 KiB block
+/// of records, so [`SegmentReader::read_range`] only reads the blocks that
+/// can hold the requested time range. The active segment has no index yet
+/// and is read entirely.
+///
+/// # Examples
+///
+/// ```no_run
+/// use loggerd::trace::file::SegmentReader;
+/// use std::path::Path;
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let mut reader = SegmentReader::open(Path::new("app.log.1.20251014_120000"))?;
+/// let since = chrono::Local::now() - chrono::Duration::hours(1);
+/// reader.read_range(Some(since), None, |record| println!("{}", record))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SegmentReader {
+    reader: BufReader<File>,
+    /// End of the records (start of the index for a sealed segment)
+    data_end: u64,
+    /// Sparse index (None for a segment being written)
+    index: Option<Vec<IndexEntry>>,
+}
+
+impl SegmentReader {
+    /// Opens a segment and loads its index.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidData` if the file is not a segment.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut magic = [0u8; SEGMENT_MAGIC.len()];
+        if file.read_exact(&mut magic).is_err() || &magic != SEGMENT_MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a log segment", path.display()),
+            ));
+        }
+        let (data_end, index) = match read_index(&mut file, len)? {
+            Some((data_end, index)) => (data_end, Some(index)),
+            None => (len, None),
+        };
+        file.seek(SeekFrom::Start(HEADER_LEN))?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            data_end,
+            index,
+        })
+    }
+
+    /// Returns `true` if the segment was sealed with its index at rotation.
+    pub fn is_sealed(&self) -> bool {
+        self.index.is_some()
+    }
+
+    /// Calls `visit` with every record emitted between `since` and `until`
+    /// (inclusive), in file order.
+    ///
+    /// Returns the number of corrupted records skipped. A frame with a wrong
+    /// CRC is skipped; an impossible length ends the reading, as for a
+    /// segment truncated by a crash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read.
+    pub fn read_range(
+        &mut self,
+        since: Option<DateTime<Local>>,
+        until: Option<DateTime<Local>>,
+        mut visit: impl FnMut(TraceRecord),
+    ) -> Result<u64> {
+        let since = since.map_or(i64::MIN, |since| since.timestamp_micros());
+        let until = until.map_or(i64::MAX, |until| until.timestamp_micros());
+
+        // Byte ranges holding candidate records
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        match &self.index {
+            Some(entries) => {
+                for (i, entry) in entries.iter().enumerate() {
+                    if entry.newest < since || entry.oldest > until {
+                        continue;
+                    }
+                    let end = entries.get(i + 1).map_or(self.data_end, |next| next.offset);
+                    match ranges.last_mut() {
+                        Some(range) if range.1 == entry.offset => range.1 = end,
+                        _ => ranges.push((entry.offset, end)),
+                    }
+                }
+            }
+            None => ranges.push((HEADER_LEN, self.data_end)),
+        }
+
+        let mut corrupted = 0;
+        for (start, end) in ranges {
+            self.reader.seek(SeekFrom::Start(start))?;
+            let mut position = start;
+            loop {
+                let (frame, next) = read_frame(&mut self.reader, position, end);
+                position = next;
+                let payload = match frame {
+                    Frame::Valid(payload) => payload,
+                    Frame::Corrupted => {
+                        corrupted += 1;
+                        continue;
+                    }
+                    Frame::End => {
+                        if position < end {
+                            corrupted += 1;
+                        }
+                        break;
+                    }
+                };
+                let Some(record) = decode(&payload) else {
+                    corrupted += 1;
+                    continue;
+                };
+                let timestamp = record.timestamp.timestamp_micros();
+                if (since..=until).contains(&timestamp) {
+                    visit(record);
+                }
+            }
+        }
+        Ok(corrupted)
+    }
+}
+
+/// Reads the index of a sealed segment: the end of its records and its
+/// entries (None when the segment has no valid trailer).
+fn read_index(file: &mut File, len: u64) -> Result<Option<(u64, Vec<IndexEntry>)>> {
+    if len < HEADER_LEN + TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::Start(len - TRAILER_LEN))?;
+    file.read_exact(&mut trailer)?;
+    let index_start = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let index_end = len - TRAILER_LEN;
+    if &trailer[8..] != INDEX_MAGIC
+        || !(HEADER_LEN..=index_end).contains(&index_start)
+        || !(index_end - index_start).is_multiple_of(INDEX_ENTRY_LEN)
+    {
+        return Ok(None);
+    }
+
+    let mut bytes = vec![0u8; (index_end - index_start) as usize];
+    file.seek(SeekFrom::Start(index_start))?;
+    file.read_exact(&mut bytes)?;
+    let entries = bytes
+        .chunks_exact(INDEX_ENTRY_LEN as usize)
+        .map(|entry| IndexEntry {
+            offset: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+            oldest: i64::from_le_bytes(entry[8..16].try_into().unwrap()),
+            newest: i64::from_le_bytes(entry[16..].try_into().unwrap()),
+        })
+        .collect();
+    Ok(Some((index_start, entries)))
+}
+
+/// The segment being written by the writer thread.
+struct ActiveSegment {
+    file: File,
+    /// Current size of the file
+    size: u64,
+    /// Records of the file
+    records: u64,
+    index: SparseIndex,
+}
+
+impl ActiveSegment {
+    /// Opens the segment at `path`, creating it or continuing it.
+    ///
+    /// The records of an existing segment are scanned to rebuild its index;
+    /// an index left by a failed rotation and a record torn by a crash are
+    /// cut off. A file that is not a segment (written in text format) is
+    /// rotated away first.
+    fn open(path: &Path, file_path: &str, max_backups: usize) -> Result<Self> {
+        let mut index = SparseIndex::default();
+        let mut records = 0;
+        let mut valid_end = 0;
+        match SegmentReader::open(path) {
+            Ok(mut reader) => {
+                let data_end = reader.data_end;
+                let mut position = HEADER_LEN;
+                loop {
+                    let (frame, next) = read_frame(&mut reader.reader, position, data_end);
+                    match frame {
+                        Frame::Valid(payload) => {
+                            if let Some(timestamp) = payload_timestamp(&payload) {
+                                index.add(position, timestamp);
+                                records += 1;
+                            }
+                        }
+                        Frame::Corrupted => {}
+                        Frame::End => break,
+                    }
+                    position = next;
+                }
+                valid_end = position;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                if std::fs::metadata(path)?.len() > 0 {
+                    rotate_log_files(file_path, max_backups)?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        let mut file = open_log_file(path)?;
+        if file.metadata()?.len() != valid_end {
+            file.set_len(valid_end)?;
+        }
+        if valid_end == 0 {
+            file.write_all(SEGMENT_MAGIC)?;
+            file.flush()?;
+            valid_end = HEADER_LEN;
+        }
+        Ok(Self {
+            file,
+            size: valid_end,
+            records,
+            index,
+        })
+    }
+
+    /// Appends a record.
+    fn append(&mut self, record: &SegmentRecord) -> Result<()> {
+        self.file.write_all(&record.frame)?;
+        self.file.flush()?;
+        self.index.add(self.size, record.timestamp);
+        self.size += record.frame.len() as u64;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Writes the index and the trailer.
+    fn seal(&mut self) -> Result<()> {
+        let mut footer = Vec::with_capacity(
+            self.index.entries.len() * INDEX_ENTRY_LEN as usize + TRAILER_LEN as usize,
+        );
+        for entry in &self.index.entries {
+            footer.extend_from_slice(&entry.offset.to_le_bytes());
+            footer.extend_from_slice(&entry.oldest.to_le_bytes());
+            footer.extend_from_slice(&entry.newest.to_le_bytes());
+        }
+        footer.extend_from_slice(&self.size.to_le_bytes());
+        footer.extend_from_slice(INDEX_MAGIC);
+        self.file.write_all(&footer)?;
+        self.file.flush()
+    }
+
+    /// Seals the segment, rotates it and starts a new one.
+    ///
+    /// When the rotation fails, the segment is reopened (which cuts its
+    /// index off) and writing continues in it.
+    fn rotate(&mut self, path: &Path, file_path: &str, max_backups: usize) -> Result<String> {
+        let rotated = self.seal().and_then(|()| {
+            // Close the file before renaming it
+            drop(std::mem::replace(&mut self.file, File::open(path)?));
+            rotate_log_files(file_path, max_backups)
+        });
+        *self = Self::open(path, file_path, max_backups)?;
+        rotated
+    }
+}
+
+/// Writer thread of a target with `format = "binary"`.
+///
+/// Same protocol and rotation settings as the text writer thread; segments
+/// are sealed with their index before being rotated.
+pub fn segment_writer_thread(
+    file_path: String,
+    receiver: Receiver<TraceMessage>,
+    config: RotationConfig,
+    log_count: Arc<AtomicU64>,
+    queued: QueueDepth,
+) {
+    let path = Path::new(&file_path);
+    let mut segment = match ActiveSegment::open(path, &file_path, config.max_backups) {
+        Ok(segment) => segment,
+        Err(e) => {
+            eprintln!("Failed to open log segment '{}': {}", file_path, e);
+            return;
+        }
+    };
+
+    loop {
+        match receiver.recv() {
+            Ok(TraceMessage::Segment(record)) => {
+                queued.pop();
+                if segment.records > 0
+                    && should_rotate(
+                        segment.size,
+                        record.frame.len() as u64,
+                        config.max_size_bytes,
+                    )
+                    && let Err(e) = segment.rotate(path, &file_path, config.max_backups)
+                {
+                    eprintln!("Rotation failed, continuing with current segment: {}", e);
+                }
+                match segment.append(&record) {
+                    Ok(()) => {
+                        log_count.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => eprintln!("Failed to write log: {}", e),
+                }
+            }
+            // Text lines are not sent to binary targets
+            Ok(TraceMessage::Log(_)) => queued.pop(),
+            Ok(TraceMessage::Rotate(reply)) => {
+                let result = if segment.records == 0 {
+                    Ok(None)
+                } else {
+                    segment
+                        .rotate(path, &file_path, config.max_backups)
+                        .map(Some)
+                };
+                let _ = reply.send(result);
+            }
+            Ok(TraceMessage::Drain(reply)) => {
+                let _ = reply.send(segment.file.flush().and_then(|()| segment.file.sync_all()));
+            }
+            Ok(TraceMessage::Shutdown) | Err(_) => {
+                let _ = segment.file.flush();
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::Trace;
+    use crate::trace::file::{FileFormat, FileTraceHandler};
+    use chrono::Duration;
+    use std::fs;
+
+    fn records(path: &Path) -> Vec<TraceRecord> {
+        let mut found = Vec::new();
+        SegmentReader::open(path)
+            .unwrap()
+            .read_range(None, None, |record| found.push(record))
+            .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut record = TraceRecord::new(TraceLevel::Error, "billing", "payment\ndeclined");
+        record
+            .fields
+            .insert("order".to_string(), "A-42 é".to_string());
+        let encoded = SegmentRecord::encode(&record);
+        let decoded = decode(&encoded.frame[FRAME_HEADER_LEN as usize..]).unwrap();
+        assert_eq!(
+            decoded.timestamp.timestamp_micros(),
+            record.timestamp.timestamp_micros()
+        );
+        assert_eq!(
+            (decoded.level, decoded.message),
+            (record.level, record.message)
+        );
+        assert_eq!(decoded.fields, record.fields);
+        assert!(decode(&encoded.frame[FRAME_HEADER_LEN as usize + 1..]).is_none());
+    }
+
+    #[test]
+    fn test_rotation_index_and_time_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.seg");
+        let handler = FileTraceHandler::with_rotation(path.to_str().unwrap(), 1024 * 1024, 5)
+            .unwrap()
+            .with_format(FileFormat::Binary)
+            .start()
+            .unwrap();
+        let start = Local::now() - Duration::hours(10);
+        for i in 0..2000 {
+            let mut record = TraceRecord::new(TraceLevel::Info, "load", &format!("record {}", i));
+            record.timestamp = start + Duration::seconds(i);
+            handler.log_record(&record);
+        }
+        handler.rotate().unwrap().unwrap();
+        handler.log(TraceLevel::Info, "in the active segment");
+        drop(handler);
+
+        let backup = super::super::rotation::backup_files(&path).pop().unwrap().1;
+        let mut reader = SegmentReader::open(&backup).unwrap();
+        assert!(reader.is_sealed());
+        assert!(reader.index.as_ref().unwrap().len() > 2);
+        let mut found = Vec::new();
+        let corrupted = reader
+            .read_range(
+                Some(start + Duration::seconds(1000)),
+                Some(start + Duration::seconds(1009)),
+                |record| found.push(record.message),
+            )
+            .unwrap();
+        assert_eq!(corrupted, 0);
+        assert_eq!(found.len(), 10);
+        assert_eq!(found[0], "record 1000");
+        assert_eq!(records(&backup).len(), 2000);
+
+        let active = records(&path);
+        assert!(!SegmentReader::open(&path).unwrap().is_sealed());
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].message, "in the active segment");
+    }
+
+    #[test]
+    fn test_torn_and_corrupted_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.seg");
+        let start = || {
+            FileTraceHandler::new(path.to_str().unwrap())
+                .unwrap()
+                .with_format(FileFormat::Binary)
+                .start()
+                .unwrap()
+        };
+        let handler = start();
+        for message in ["one", "two", "three"] {
+            handler.log(TraceLevel::Info, message);
+        }
+        drop(handler);
+
+        // Flip a byte of the second record and tear the last one
+        let mut bytes = fs::read(&path).unwrap();
+        let second = bytes.windows(3).position(|w| w == b"two").unwrap();
+        bytes[second] = b'T';
+        bytes.truncate(bytes.len() - 2);
+        fs::write(&path, &bytes).unwrap();
+
+        let mut found = Vec::new();
+        let corrupted = SegmentReader::open(&path)
+            .unwrap()
+            .read_range(None, None, |record| found.push(record.message))
+            .unwrap();
+        assert_eq!(found, ["one"]);
+        assert_eq!(corrupted, 2);
+
+        // The writer cuts the torn record off and continues
+        let handler = start();
+        handler.log(TraceLevel::Info, "four");
+        drop(handler);
+        let messages: Vec<String> = records(&path).into_iter().map(|r| r.message).collect();
+        assert_eq!(messages, ["one", "four"]);
+        assert!(is_segment(&path));
+    }
+}
@@ -0,0 +1,314 @@
+use super::chain::strip_link;
+use super::rotation::backup_files;
+use crate::trace::{TermQuery, tokenize};
+use chrono::DateTime;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// First word of an index file, followed by the size of the indexed file.
+const INDEX_MAGIC: &str = "LGDTOK01";
+
+/// Inverted index of a text log file: token → offsets of the records
+/// containing it.
+///
+/// The writer thread keeps the index of the active file in memory and saves
+/// it next to each backup at rotation, as the hidden file
+/// `.<backup name>.tokens`. The index of a backup that has none, or whose
+/// size changed since, is rebuilt from the backup when it is searched.
+/// Tokens are taken from the source, the message and the fields, as
+/// [`TermQuery`] matches them; the timestamp and level are not indexed.
+#[derive(Debug, Default)]
+pub(super) struct TokenIndex {
+    postings: HashMap<String, Vec<u64>>,
+}
+
+/// Token index of an active file, shared by its writer thread and the
+/// searches.
+pub(super) type SharedIndex = Arc<Mutex<TokenIndex>>;
+
+impl TokenIndex {
+    /// Indexes the record starting at `offset`, given as written (with its
+    /// timestamp and level, possibly on several lines).
+    pub(super) fn add(&mut self, offset: u64, line: &str) {
+        let text = strip_link(line.trim_end_matches('\n'));
+        // Skip the timestamp and level
+        let text = text.split_once("] ").map_or(text, |(_, rest)| rest);
+        let tokens: HashSet<String> = tokenize(text).collect();
+        for token in tokens {
+            self.postings.entry(token).or_default().push(offset);
+        }
+    }
+
+    /// Forgets every record (the file was rotated).
+    pub(super) fn clear(&mut self) {
+        self.postings.clear();
+    }
+
+    /// Builds the index of an existing file.
+    pub(super) fn build(path: &Path) -> Result<Self> {
+        let mut index = Self::default();
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut line = Vec::new();
+        let mut offset = 0;
+        // Record being read: its offset and its text so far
+        let mut pending: Option<(u64, String)> = None;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            let text = String::from_utf8_lossy(&line);
+            if starts_record(&text) {
+                if let Some((start, record)) = pending.take() {
+                    index.add(start, &record);
+                }
+                pending = Some((offset, text.into_owned()));
+            } else if let Some((_, record)) = &mut pending {
+                record.push_str(&text);
+            }
+            offset += read as u64;
+        }
+        if let Some((start, record)) = pending {
+            index.add(start, &record);
+        }
+        Ok(index)
+    }
+
+    /// Returns the offsets of the records matching `terms`, in file order.
+    pub(super) fn candidates(&self, terms: &TermQuery) -> BTreeSet<u64> {
+        let mut found = BTreeSet::new();
+        for group in terms.groups() {
+            let mut lists: Vec<&Vec<u64>> = Vec::with_capacity(group.len());
+            for token in group {
+                match self.postings.get(token) {
+                    Some(offsets) => lists.push(offsets),
+                    None => {
+                        lists.clear();
+                        break;
+                    }
+                }
+            }
+            // Intersect, starting from the rarest token
+            lists.sort_by_key(|offsets| offsets.len());
+            let Some((rarest, others)) = lists.split_first() else {
+                continue;
+            };
+            let others: Vec<HashSet<u64>> = others
+                .iter()
+                .map(|offsets| offsets.iter().copied().collect())
+                .collect();
+            found.extend(
+                rarest
+                    .iter()
+                    .filter(|offset| others.iter().all(|set| set.contains(offset))),
+            );
+        }
+        found
+    }
+
+    /// Saves the index of the log file `log`, which is `log_len` bytes long.
+    fn save(&self, log: &Path, log_len: u64) -> Result<()> {
+        let path = index_path(log);
+        let partial = path.with_extension("partial");
+        let mut out = BufWriter::new(File::create(&partial)?);
+        writeln!(out, "{} {}", INDEX_MAGIC, log_len)?;
+        let mut tokens: Vec<&String> = self.postings.keys().collect();
+        tokens.sort();
+        for token in tokens {
+            write!(out, "{}", token)?;
+            for offset in &self.postings[token] {
+                write!(out, " {}", offset)?;
+            }
+            writeln!(out)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(partial, path)
+    }
+
+    /// Loads the postings of `tokens` from the index file of `log`; fails if
+    /// the index is missing or doesn't cover the current size of `log`.
+    fn load(log: &Path, tokens: &HashSet<&str>) -> Result<Self> {
+        let mut lines = BufReader::new(File::open(index_path(log))?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let expected = format!("{} {}", INDEX_MAGIC, fs::metadata(log)?.len());
+        if header != expected {
+            return Err(Error::new(ErrorKind::InvalidData, "stale token index"));
+        }
+
+        let mut index = Self::default();
+        for line in lines {
+            let line = line?;
+            let mut words = line.split(' ');
+            let Some(token) = words.next().filter(|token| tokens.contains(token)) else {
+                continue;
+            };
+            let offsets = words
+                .map(str::parse)
+                .collect::<std::result::Result<Vec<u64>, _>>()
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "corrupted token index"))?;
+            index.postings.insert(token.to_string(), offsets);
+        }
+        Ok(index)
+    }
+}
+
+/// Saves the index of a backup just rotated, and removes the index files of
+/// the backups deleted since.
+pub(super) fn save_backup_index(index: &TokenIndex, backup: &Path, file_path: &Path) {
+    let saved = fs::metadata(backup).and_then(|metadata| index.save(backup, metadata.len()));
+    if let Err(e) = saved {
+        eprintln!(
+            "Failed to save the token index of '{}': {}",
+            backup.display(),
+            e
+        );
+    }
+    prune(file_path);
+}
+
+/// Returns the offsets of the records of a backup matching `terms`, from its
+/// index file, rebuilt first when missing or stale.
+pub(super) fn backup_candidates(backup: &Path, terms: &TermQuery) -> Result<BTreeSet<u64>> {
+    let tokens: HashSet<&str> = terms
+        .groups()
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    let index = match TokenIndex::load(backup, &tokens) {
+        Ok(index) => index,
+        Err(_) => {
+            let len = fs::metadata(backup)?.len();
+            let index = TokenIndex::build(backup)?;
+            if let Err(e) = index.save(backup, len) {
+                eprintln!(
+                    "Failed to save the token index of '{}': {}",
+                    backup.display(),
+                    e
+                );
+            }
+            index
+        }
+    };
+    Ok(index.candidates(terms))
+}
+
+/// Path of the index file of a log file: `.<name>.tokens` in its directory.
+fn index_path(log: &Path) -> PathBuf {
+    let name = log.file_name().unwrap_or_default().to_string_lossy();
+    log.with_file_name(format!(".{}.tokens", name))
+}
+
+/// Removes the index files whose backup no longer exists.
+fn prune(file_path: &Path) {
+    let backups: HashSet<PathBuf> = backup_files(file_path)
+        .into_iter()
+        .map(|(_, backup)| index_path(&backup))
+        .collect();
+    let dir = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let (Some(name), Ok(entries)) = (file_path.file_name(), fs::read_dir(dir)) else {
+        return;
+    };
+    let prefix = format!(".{}.", name.to_string_lossy());
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with(&prefix)
+            && file_name.ends_with(".tokens")
+            && !backups.contains(&entry.path())
+        {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Returns `true` if `line` starts with a timestamp, as records do.
+pub(super) fn starts_record(line: &str) -> bool {
+    line.split_once(' ')
+        .is_some_and(|(timestamp, _)| DateTime::parse_from_rfc3339(timestamp).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::file::FileTraceHandler;
+    use crate::trace::{Trace, TraceLevel, TraceRecord};
+
+    fn offsets(index: &TokenIndex, terms: &str) -> Vec<u64> {
+        index
+            .candidates(&TermQuery::parse(terms).unwrap())
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_build_and_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let lines = [
+            "2025-10-14T12:00:00.000+00:00 [ERROR] db - query timeout {host=db-01}\n",
+            "2025-10-14T12:00:01.000+00:00 [INFO] web - GET / {status=200}\n",
+            "2025-10-14T12:00:02.000+00:00 [ERROR] db - connection refused\n",
+            "Caused by: timeout\n",
+        ];
+        fs::write(&path, lines.concat()).unwrap();
+        let second = lines[0].len() as u64;
+        let third = second + lines[1].len() as u64;
+
+        let index = TokenIndex::build(&path).unwrap();
+        assert_eq!(offsets(&index, "timeout"), [0, third]);
+        assert_eq!(offsets(&index, "timeout db01"), Vec::<u64>::new());
+        assert_eq!(offsets(&index, "timeout db-01"), [0]);
+        assert_eq!(offsets(&index, "200 OR refused"), [second, third]);
+        assert_eq!(offsets(&index, "DB AND missing"), Vec::<u64>::new());
+        // The level and timestamp are not indexed
+        assert!(offsets(&index, "error").is_empty());
+    }
+
+    #[test]
+    fn test_backup_index_saved_and_rebuilt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let handler = FileTraceHandler::new(path.to_str().unwrap())
+            .unwrap()
+            .with_token_index()
+            .start()
+            .unwrap();
+        handler.log_record(&TraceRecord::new(
+            TraceLevel::Info,
+            "web",
+            "payment accepted",
+        ));
+        handler.log_record(&TraceRecord::new(
+            TraceLevel::Info,
+            "web",
+            "payment refused",
+        ));
+        let backup = PathBuf::from(handler.rotate().unwrap().unwrap());
+        drop(handler);
+
+        // Saved at rotation, hidden from the backup listing
+        assert!(index_path(&backup).exists());
+        assert_eq!(backup_files(&path).len(), 1);
+        let terms = TermQuery::parse("payment refused").unwrap();
+        let found = backup_candidates(&backup, &terms).unwrap();
+        assert_eq!(found.len(), 1);
+
+        // Rebuilt once stale
+        let mut content = fs::read_to_string(&backup).unwrap();
+        content.push_str("2025-10-14T12:00:00.000+00:00 [INFO] web - refused again payment\n");
+        fs::write(&backup, content).unwrap();
+        assert_eq!(backup_candidates(&backup, &terms).unwrap().len(), 2);
+
+        // Removed with its backup
+        fs::remove_file(&backup).unwrap();
+        prune(&path);
+        assert!(!index_path(&backup).exists());
+    }
+}
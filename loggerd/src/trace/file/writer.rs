@@ -1,10 +1,14 @@
+use super::chain::{HashChain, LINK_LEN};
 use super::file_opener::open_log_file;
 use super::rotation::{RotationConfig, rotate_log_files};
+use super::segment::SegmentRecord;
+use super::token_index::{SharedIndex, TokenIndex, save_backup_index};
+use crate::trace::{QueueDepth, lock};
 use std::io::Write;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 
 /// Messages sent to the writer thread.
 ///
@@ -13,6 +17,14 @@ use std::sync::mpsc::Receiver;
 pub enum TraceMessage {
     /// Log a message to the file
     Log(String),
+    /// Append a record to a binary segment
+    Segment(SegmentRecord),
+    /// Rotate the file now; the name of the backup (None for an empty file,
+    /// which is not rotated) or the error is sent back
+    Rotate(Sender<std::io::Result<Option<String>>>),
+    /// Sync the file to storage; the outcome is sent back once every record
+    /// queued before is written
+    Drain(Sender<std::io::Result<()>>),
     /// Signal the writer thread to shutdown gracefully
     Shutdown,
 }
@@ -43,6 +55,9 @@ pub enum TraceMessage {
 /// * `receiver` - MPSC receiver for trace messages
 /// * `config` - Rotation configuration
 /// * `log_count` - Shared atomic counter for metrics
+/// * `chain` - Hash chain linking the records (None for a plain file)
+/// * `index` - Token index of the file, kept up to date (None when disabled)
+/// * `queued` - Number of records queued to the thread, for the drain
 ///
 /// # Error Handling
 ///
@@ -56,6 +71,9 @@ pub fn writer_thread(
     receiver: Receiver<TraceMessage>,
     config: RotationConfig,
     log_count: Arc<AtomicU64>,
+    mut chain: Option<HashChain>,
+    index: Option<SharedIndex>,
+    queued: QueueDepth,
 ) {
     let path = Path::new(&file_path);
 
@@ -70,32 +88,97 @@ pub fn writer_thread(
 
     let mut current_size = file.metadata().map(|m| m.len()).unwrap_or(0);
 
+    if let Some(chain) = &mut chain
+        && let Err(e) = start_chain(
+            chain,
+            &mut file,
+            &mut current_size,
+            path,
+            &file_path,
+            &config,
+        )
+    {
+        eprintln!("Failed to start the hash chain of '{}': {}", file_path, e);
+        return;
+    }
+
+    // Index the records written before this start
+    if let Some(index) = &index {
+        match TokenIndex::build(path) {
+            Ok(built) => *lock(index) = built,
+            Err(e) => eprintln!("Failed to index log file '{}': {}", file_path, e),
+        }
+    }
+
     loop {
         match receiver.recv() {
             Ok(TraceMessage::Log(message)) => {
-                let message_len = message.len() as u64;
+                queued.pop();
+                let mut message_len = message.len() as u64;
+                if chain.is_some() {
+                    message_len += LINK_LEN as u64;
+                }
 
                 // Check if rotation is needed and attempt rotation
-                if should_rotate(current_size, message_len, config.max_size_bytes) {
-                    perform_rotation(
+                if should_rotate(current_size, message_len, config.max_size_bytes)
+                    && let Err(e) = perform_rotation(
                         &mut file,
                         &mut current_size,
                         path,
                         &file_path,
                         config.max_backups,
+                        chain.as_mut(),
+                        index.as_ref(),
                     )
-                    .unwrap_or_else(|e| {
-                        eprintln!("Rotation failed, continuing with current file: {}", e);
-                        // Continue with current file even if rotation fails
-                    });
+                {
+                    // Continue with current file even if rotation fails
+                    eprintln!("Rotation failed, continuing with current file: {}", e);
                 }
 
+                // Link the message once the file it goes to is known
+                let message = match &mut chain {
+                    Some(chain) => chain.link(&message),
+                    None => message,
+                };
+
                 // Write the message
                 match write_message(&mut file, &message, message_len, &log_count) {
-                    Ok(()) => current_size += message_len,
+                    Ok(()) => {
+                        if let Some(index) = &index {
+                            lock(index).add(current_size, &message);
+                        }
+                        current_size += message_len;
+                    }
                     Err(e) => eprintln!("Failed to write log: {}", e),
                 }
             }
+            // Binary records are not sent to text targets
+            Ok(TraceMessage::Segment(_)) => queued.pop(),
+            Ok(TraceMessage::Rotate(reply)) => {
+                // A chained file holding only its anchor is empty
+                let empty = match &chain {
+                    Some(chain) => chain.records() <= 1,
+                    None => current_size == 0,
+                };
+                let result = if empty {
+                    Ok(None)
+                } else {
+                    perform_rotation(
+                        &mut file,
+                        &mut current_size,
+                        path,
+                        &file_path,
+                        config.max_backups,
+                        chain.as_mut(),
+                        index.as_ref(),
+                    )
+                    .map(Some)
+                };
+                let _ = reply.send(result);
+            }
+            Ok(TraceMessage::Drain(reply)) => {
+                let _ = reply.send(file.flush().and_then(|()| file.sync_all()));
+            }
             Ok(TraceMessage::Shutdown) | Err(_) => {
                 // Final flush and clean shutdown
                 let _ = file.flush();
@@ -106,6 +189,38 @@ pub fn writer_thread(
     // File will be automatically closed here (drop)
 }
 
+/// Continues the hash chain of the opened file.
+///
+/// A file written before the chain was enabled is rotated away first, and a
+/// new file gets its anchor record.
+fn start_chain(
+    chain: &mut HashChain,
+    file: &mut std::fs::File,
+    current_size: &mut u64,
+    path: &Path,
+    file_path: &str,
+    config: &RotationConfig,
+) -> std::io::Result<()> {
+    if !chain.resume(path)? {
+        perform_rotation(
+            file,
+            current_size,
+            path,
+            file_path,
+            config.max_backups,
+            None,
+            None,
+        )?;
+    }
+    if *current_size == 0 {
+        let anchor = chain.anchor();
+        file.write_all(anchor.as_bytes())?;
+        file.flush()?;
+        *current_size = anchor.len() as u64;
+    }
+    Ok(())
+}
+
 /// Checks if rotation is needed based on current and incoming message size.
 ///
 /// # Arguments
@@ -118,17 +233,19 @@ pub fn writer_thread(
 ///
 /// `true` if the file should be rotated, `false` otherwise
 #[inline]
-fn should_rotate(current_size: u64, message_len: u64, max_size: u64) -> bool {
+pub(super) fn should_rotate(current_size: u64, message_len: u64, max_size: u64) -> bool {
     current_size + message_len > max_size
 }
 
 /// Performs file rotation: flush, close, rotate, reopen.
 ///
 /// This function handles the complete rotation process:
-/// 1. Flushes and closes the current file
-/// 2. Calls the rotation logic to move files
-/// 3. Opens a new file for continued logging
-/// 4. Resets the size counter
+/// 1. Seals a chained file with a checkpoint record
+/// 2. Flushes and closes the current file
+/// 3. Calls the rotation logic to move files
+/// 4. Saves the token index of the backup
+/// 5. Opens a new file for continued logging, anchored to the chain
+/// 6. Resets the size counter
 ///
 /// # Arguments
 ///
@@ -137,10 +254,12 @@ fn should_rotate(current_size: u64, message_len: u64, max_size: u64) -> bool {
 /// * `path` - Path to the log file
 /// * `file_path` - String path for rotation operations
 /// * `max_backups` - Maximum number of backup files to keep
+/// * `chain` - Hash chain of the file (None for a plain file)
+/// * `index` - Token index of the file (None when disabled)
 ///
 /// # Returns
 ///
-/// * `Ok(())` - If rotation completed successfully
+/// * `Ok(String)` - The backup name, if rotation completed successfully
 /// * `Err(std::io::Error)` - If any step of rotation failed
 fn perform_rotation(
     file: &mut std::fs::File,
@@ -148,7 +267,17 @@ fn perform_rotation(
     path: &Path,
     file_path: &str,
     max_backups: usize,
-) -> std::io::Result<()> {
+    mut chain: Option<&mut HashChain>,
+    index: Option<&SharedIndex>,
+) -> std::io::Result<String> {
+    if let Some(chain) = &mut chain {
+        let checkpoint = chain.checkpoint();
+        file.write_all(checkpoint.as_bytes())?;
+        if let Some(index) = index {
+            lock(index).add(*current_size, &checkpoint);
+        }
+    }
+
     // Flush and close the current file
     file.flush()?;
     drop(std::mem::replace(
@@ -157,13 +286,27 @@ fn perform_rotation(
     ));
 
     // Rotate files
-    rotate_log_files(file_path, max_backups)?;
+    let backup = rotate_log_files(file_path, max_backups)?;
+    if let Some(index) = index {
+        let mut index = lock(index);
+        save_backup_index(&index, Path::new(&backup), path);
+        index.clear();
+    }
 
     // Reopen a new file
     *file = open_log_file(path)?;
     *current_size = 0;
+    if let Some(chain) = chain {
+        let anchor = chain.anchor();
+        file.write_all(anchor.as_bytes())?;
+        file.flush()?;
+        if let Some(index) = index {
+            lock(index).add(0, &anchor);
+        }
+        *current_size = anchor.len() as u64;
+    }
 
-    Ok(())
+    Ok(backup)
 }
 
 /// Writes a message to the file and increments the atomic counter.
@@ -0,0 +1,300 @@
+use super::ForwardConfig;
+use super::spool::Spool;
+use crate::http_client::HttpEndpoint;
+use crate::metrics::MetricsRegistry;
+use crate::trace::{QueueDepth, TraceRecord};
+use std::io::{Error, Result};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// Content type of forwarded batches (one JSON record per line).
+const NDJSON: &str = "application/x-ndjson";
+
+/// Messages sent to the forwarder thread.
+pub enum ForwardMessage {
+    /// Spool a record for delivery
+    Record(TraceRecord),
+    /// Sync the spool; the outcome is sent back once every record queued
+    /// before is spooled
+    Drain(Sender<Result<()>>),
+    /// Signal the forwarder thread to sync the spool and stop
+    Shutdown,
+}
+
+/// Forwarding counters, registered in the shared metrics registry.
+#[derive(Clone)]
+pub struct ForwardStats {
+    /// Records written to the spool
+    pub spooled: Arc<AtomicU64>,
+    /// Records acknowledged by the upstream
+    pub sent: Arc<AtomicU64>,
+    /// Records discarded because the spool was full
+    pub dropped: Arc<AtomicU64>,
+    /// Bytes waiting in the spool
+    pub pending_bytes: Arc<AtomicU64>,
+    /// 1 while the upstream accepts batches, 0 during an outage
+    pub upstream_up: Arc<AtomicU64>,
+}
+
+impl ForwardStats {
+    /// Registers the forwarding metrics for `endpoint`.
+    pub fn register(metrics: &MetricsRegistry, endpoint: &HttpEndpoint) -> Self {
+        let upstream = format!("{}:{}", endpoint.host, endpoint.port);
+        let labels = [("upstream", upstream.as_str())];
+        Self {
+            spooled: metrics.counter("forward_records_spooled_total", &labels),
+            sent: metrics.counter("forward_records_sent_total", &labels),
+            dropped: metrics.counter("forward_records_dropped_total", &labels),
+            pending_bytes: metrics.gauge("forward_spool_pending_bytes", &labels),
+            upstream_up: metrics.gauge("forward_upstream_up", &labels),
+        }
+    }
+}
+
+/// Dedicated forwarder thread.
+///
+/// The thread owns the spool, so appends and deliveries never race:
+///
+/// 1. Receive records from the handler and append them to the spool
+/// 2. Fsync the spool once the channel is drained
+/// 3. When the upstream is due for an attempt, send batches in spool order
+///    and advance the checkpoint after each acknowledged batch
+/// 4. On failure, retry with exponential backoff (`retry_interval_ms` up to
+///    `max_retry_interval_ms`) while records keep accumulating on disk
+///
+/// On shutdown the spool is synced and left in place: anything not yet
+/// acknowledged is replayed by the next daemon start.
+///
+/// # Arguments
+///
+/// * `spool` - Opened spool
+/// * `receiver` - MPSC receiver for forward messages
+/// * `endpoint` - Upstream collector
+/// * `config` - Forwarding configuration
+/// * `stats` - Shared forwarding counters
+/// * `queued` - Number of records queued to the thread, for the drain
+pub fn forwarder_thread(
+    mut spool: Spool,
+    receiver: Receiver<ForwardMessage>,
+    endpoint: HttpEndpoint,
+    config: ForwardConfig,
+    stats: ForwardStats,
+    queued: QueueDepth,
+) {
+    let retry_interval = Duration::from_millis(config.retry_interval_ms);
+    let max_retry_interval =
+        Duration::from_millis(config.max_retry_interval_ms).max(retry_interval);
+    let timeout = Duration::from_millis(config.timeout_ms);
+
+    let mut backoff = retry_interval;
+    let mut next_attempt = Instant::now();
+    let mut upstream_up = true;
+
+    stats.upstream_up.store(1, Ordering::Relaxed);
+    stats
+        .pending_bytes
+        .store(spool.pending_bytes(), Ordering::Relaxed);
+
+    loop {
+        let wait = if spool.is_empty() {
+            retry_interval
+        } else {
+            next_attempt.saturating_duration_since(Instant::now())
+        };
+
+        let mut shutdown = false;
+        match receiver.recv_timeout(wait) {
+            Ok(ForwardMessage::Record(record)) => {
+                queued.pop();
+                spool_record(&mut spool, &record, &stats);
+                // Drain whatever else is queued before paying for an fsync
+                while let Ok(message) = receiver.try_recv() {
+                    match message {
+                        ForwardMessage::Record(record) => {
+                            queued.pop();
+                            spool_record(&mut spool, &record, &stats);
+                        }
+                        ForwardMessage::Drain(reply) => {
+                            let _ = reply.send(spool.sync());
+                        }
+                        ForwardMessage::Shutdown => {
+                            shutdown = true;
+                            break;
+                        }
+                    }
+                }
+                if let Err(e) = spool.sync() {
+                    eprintln!("Failed to sync forward spool: {}", e);
+                }
+            }
+            Ok(ForwardMessage::Drain(reply)) => {
+                let _ = reply.send(spool.sync());
+            }
+            Ok(ForwardMessage::Shutdown) | Err(RecvTimeoutError::Disconnected) => shutdown = true,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        if shutdown {
+            let _ = spool.sync();
+            break;
+        }
+
+        if spool.is_empty() || Instant::now() < next_attempt {
+            continue;
+        }
+
+        match deliver_pending(&mut spool, &endpoint, config.batch_size, timeout, &stats) {
+            Ok(()) => {
+                if !upstream_up {
+                    eprintln!(
+                        "Upstream {}:{} reachable again, spool replayed",
+                        endpoint.host, endpoint.port
+                    );
+                }
+                upstream_up = true;
+                backoff = retry_interval;
+            }
+            Err(e) => {
+                if upstream_up {
+                    eprintln!(
+                        "Upstream {}:{} unavailable, spooling records: {}",
+                        endpoint.host, endpoint.port, e
+                    );
+                }
+                upstream_up = false;
+                next_attempt = Instant::now() + backoff;
+                backoff = (backoff * 2).min(max_retry_interval);
+            }
+        }
+        stats
+            .upstream_up
+            .store(upstream_up as u64, Ordering::Relaxed);
+        stats
+            .pending_bytes
+            .store(spool.pending_bytes(), Ordering::Relaxed);
+    }
+}
+
+/// Appends a record to the spool and updates the counters.
+fn spool_record(spool: &mut Spool, record: &TraceRecord, stats: &ForwardStats) {
+    let line = match serde_json::to_string(record) {
+        Ok(line) => line,
+        Err(e) => {
+            eprintln!("Failed to serialize record for forwarding: {}", e);
+            return;
+        }
+    };
+
+    match spool.append(&line) {
+        Ok(dropped) => {
+            stats.spooled.fetch_add(1, Ordering::Relaxed);
+            if dropped > 0 {
+                stats.dropped.fetch_add(dropped, Ordering::Relaxed);
+                eprintln!("Forward spool full, dropped {} oldest records", dropped);
+            }
+        }
+        Err(e) => eprintln!("Failed to spool record: {}", e),
+    }
+    stats
+        .pending_bytes
+        .store(spool.pending_bytes(), Ordering::Relaxed);
+}
+
+/// Sends every pending batch, committing the checkpoint after each one.
+///
+/// Stops at the first failure; the failed batch stays in the spool and is
+/// sent again, in order, on the next attempt.
+fn deliver_pending(
+    spool: &mut Spool,
+    endpoint: &HttpEndpoint,
+    batch_size: usize,
+    timeout: Duration,
+    stats: &ForwardStats,
+) -> Result<()> {
+    loop {
+        let (records, position) = spool.read_batch(batch_size.max(1))?;
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut body = records.join("\n");
+        body.push('\n');
+
+        let status = endpoint.post(NDJSON, body.as_bytes(), timeout)?;
+        if !(200..300).contains(&status) {
+            return Err(Error::other(format!("upstream answered HTTP {}", status)));
+        }
+
+        spool.commit(position)?;
+        stats
+            .sent
+            .fetch_add(records.len() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Accepts one request and answers with `status`, returning the body.
+    fn serve_once(listener: &TcpListener, status: u16) -> String {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 {} X\r\nContent-Length: 0\r\n\r\n",
+            status
+        )
+        .unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    fn test_failed_batch_is_kept_and_resent() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let endpoint = HttpEndpoint::parse(&format!("http://127.0.0.1:{}/ingest", port)).unwrap();
+        let stats = ForwardStats::register(&MetricsRegistry::new(), &endpoint);
+
+        let mut spool = Spool::open(dir.path(), 1024, 1024 * 1024).unwrap();
+        for message in ["one", "two"] {
+            let record = TraceRecord::new(crate::trace::TraceLevel::Info, "test", message);
+            spool_record(&mut spool, &record, &stats);
+        }
+
+        let server = std::thread::spawn(move || {
+            let rejected = serve_once(&listener, 503);
+            let accepted = serve_once(&listener, 200);
+            (rejected, accepted)
+        });
+
+        let timeout = Duration::from_secs(5);
+        assert!(deliver_pending(&mut spool, &endpoint, 10, timeout, &stats).is_err());
+        assert!(!spool.is_empty());
+        deliver_pending(&mut spool, &endpoint, 10, timeout, &stats).unwrap();
+        assert!(spool.is_empty());
+
+        let (rejected, accepted) = server.join().unwrap();
+        assert_eq!(rejected, accepted);
+        assert_eq!(accepted.lines().count(), 2);
+        assert_eq!(stats.sent.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,171 @@
+use super::ForwardConfig;
+use super::forwarder::{ForwardMessage, ForwardStats, forwarder_thread};
+use super::spool::Spool;
+use crate::http_client::HttpEndpoint;
+use crate::metrics::MetricsRegistry;
+use crate::trace::record::DEFAULT_SOURCE;
+use crate::trace::{
+    DrainReport, QueueDepth, Trace, TraceLevel, TraceRecord, drain_queue, handlers::TraceHandler,
+};
+use std::sync::mpsc::{Sender, channel};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// Trace handler forwarding records to an upstream collector.
+///
+/// Every record is first written to a durable on-disk [`Spool`], then sent
+/// to the upstream in batches by a dedicated thread. While the upstream is
+/// unreachable records keep accumulating on disk (up to `max_spool_bytes`)
+/// and are replayed in order once it comes back, including across daemon
+/// restarts.
+///
+/// # Protocol
+///
+/// Batches are POSTed to `url` as newline-delimited JSON
+/// (`application/x-ndjson`), one [`TraceRecord`] per line. Any 2xx status
+/// acknowledges the whole batch.
+///
+/// # Examples
+///
+/// ```no_run
+/// use loggerd::metrics::MetricsRegistry;
+/// use loggerd::trace::forward::{ForwardConfig, ForwardTraceHandler};
+/// use loggerd::trace::{Trace, TraceLevel};
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let config = ForwardConfig {
+///     url: "http://collector:8080/logs".to_string(),
+///     ..ForwardConfig::default()
+/// };
+/// let handler = ForwardTraceHandler::new(config, &MetricsRegistry::new())?.start()?;
+/// handler.log(TraceLevel::Info, "Forwarded to the collector");
+/// # Ok(())
+/// # }
+/// ```
+pub struct ForwardTraceHandler {
+    /// Channel sender for communicating with the forwarder thread
+    sender: Option<Sender<ForwardMessage>>,
+    /// Handle to the background forwarder thread
+    thread_handle: Option<JoinHandle<()>>,
+    /// Spool, moved into the forwarder thread by `start()`
+    spool: Option<Spool>,
+    /// Parsed upstream URL
+    endpoint: HttpEndpoint,
+    /// Forwarding configuration
+    config: ForwardConfig,
+    /// Shared forwarding counters
+    stats: ForwardStats,
+    /// Number of records queued to the forwarder thread
+    queued: QueueDepth,
+}
+
+impl ForwardTraceHandler {
+    /// Creates a forwarding handler and opens its spool.
+    ///
+    /// **Note**: Call `.start()` to begin the forwarder thread
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Forwarding configuration
+    /// * `metrics` - Registry receiving the forwarding counters
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ForwardTraceHandler)` - If the URL is valid and the spool can be opened
+    /// * `Err(std::io::Error)` - If the URL is invalid or the spool directory is unusable
+    pub fn new(config: ForwardConfig, metrics: &MetricsRegistry) -> Result<Self, std::io::Error> {
+        let endpoint = HttpEndpoint::parse(&config.url)?;
+        let spool = Spool::open(
+            &config.spool_dir,
+            config.segment_max_bytes,
+            config.max_spool_bytes,
+        )?;
+        let stats = ForwardStats::register(metrics, &endpoint);
+
+        Ok(Self {
+            sender: None,
+            thread_handle: None,
+            spool: Some(spool),
+            endpoint,
+            config,
+            stats,
+            queued: QueueDepth::default(),
+        })
+    }
+
+    /// Starts the forwarder thread and returns self for method chaining.
+    ///
+    /// Records left in the spool by a previous run are replayed as soon as
+    /// the upstream is reachable.
+    pub fn start(mut self) -> Result<Self, std::io::Error> {
+        let Some(spool) = self.spool.take() else {
+            return Ok(self); // Already started
+        };
+
+        let (sender, receiver) = channel::<ForwardMessage>();
+        let endpoint = self.endpoint.clone();
+        let config = self.config.clone();
+        let stats = self.stats.clone();
+        let queued = self.queued.clone();
+
+        let thread_handle = thread::Builder::new()
+            .name("loggerd-forward".to_string())
+            .spawn(move || forwarder_thread(spool, receiver, endpoint, config, stats, queued))?;
+
+        self.sender = Some(sender);
+        self.thread_handle = Some(thread_handle);
+
+        Ok(self)
+    }
+}
+
+impl Trace for ForwardTraceHandler {
+    fn log(&self, level: TraceLevel, message: &str) {
+        self.log_record(&TraceRecord::new(level, DEFAULT_SOURCE, message));
+    }
+
+    fn log_record(&self, record: &TraceRecord) {
+        if let Some(sender) = &self.sender {
+            self.queued.push();
+            if sender.send(ForwardMessage::Record(record.clone())).is_err() {
+                self.queued.pop();
+            }
+        } else {
+            eprintln!("Warning: ForwardTraceHandler not started, call start() first");
+        }
+    }
+
+    /// Waits until the forwarder thread has spooled the queued records and
+    /// synced the spool; they are sent upstream by the next daemon start if
+    /// not before.
+    fn drain(&self, deadline: Instant) -> DrainReport {
+        match &self.sender {
+            Some(sender) => drain_queue(
+                &self.describe(),
+                sender,
+                ForwardMessage::Drain,
+                &self.queued,
+                deadline,
+            ),
+            None => DrainReport::default(),
+        }
+    }
+}
+
+impl TraceHandler for ForwardTraceHandler {
+    fn describe(&self) -> String {
+        format!("forward to {}:{}", self.endpoint.host, self.endpoint.port)
+    }
+}
+
+impl Drop for ForwardTraceHandler {
+    fn drop(&mut self) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(ForwardMessage::Shutdown);
+        }
+
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
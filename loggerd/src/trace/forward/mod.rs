@@ -0,0 +1,86 @@
+//! Forwarding of log records to an upstream collector.
+//!
+//! Remote sites regularly lose connectivity for hours, so records are never
+//! sent straight from memory: they go through a durable on-disk spool first
+//! and are delivered from there.
+//!
+//! # Architecture
+//!
+//! - `handler.rs` : Public facade (ForwardTraceHandler)
+//! - `forwarder.rs` : Background thread owning the spool and the upstream connection
+//! - `spool.rs` : Segment files + checkpoint, with a maximum size
+//!
+//! # Delivery Semantics
+//!
+//! ```text
+//! log_record() ──▶ channel ──▶ spool.append() ──▶ fsync
+//!                                   │
+//!                     upstream up?  ▼
+//!                       read_batch() ──▶ POST ──▶ 2xx ──▶ commit(checkpoint)
+//! ```
+//!
+//! A batch is only removed from the spool once the upstream acknowledged it,
+//! and the checkpoint is persisted atomically right after the acknowledgement.
+//! After a restart delivery resumes from the checkpoint, in the original order.
+//!
+//! # Configuration
+//!
+//! ```toml
+//! [forward]
+//! url = "http://collector.example:8080/logs"
+//! spool_dir = "/var/spool/loggerd"
+//! segment_max_bytes = 1048576     # 1 MB per segment
+//! max_spool_bytes = 104857600     # 100 MB on disk, oldest segments dropped beyond
+//! batch_size = 500
+//! retry_interval_ms = 1000        # first retry delay, doubled up to...
+//! max_retry_interval_ms = 60000   # ...this ceiling
+//! timeout_ms = 5000
+//! ```
+
+mod forwarder;
+mod handler;
+mod spool;
+
+use serde::Deserialize;
+use std::path::PathBuf;
+
+pub use handler::ForwardTraceHandler;
+#[allow(unused_imports)] // Public API for tooling that inspects a spool
+pub use spool::{Spool, SpoolPosition};
+
+/// Configuration of the forwarding handler (`[forward]` section).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForwardConfig {
+    /// Upstream collector URL (`http://host:port/path`)
+    pub url: String,
+    /// Directory holding the spool segments and checkpoint
+    pub spool_dir: PathBuf,
+    /// Size at which a spool segment is sealed
+    pub segment_max_bytes: u64,
+    /// Maximum spool size on disk; the oldest segments are dropped beyond
+    pub max_spool_bytes: u64,
+    /// Maximum number of records per upstream request
+    pub batch_size: usize,
+    /// Delay before the first retry after a failed delivery
+    pub retry_interval_ms: u64,
+    /// Upper bound of the exponential retry delay
+    pub max_retry_interval_ms: u64,
+    /// Connect/read/write timeout for upstream requests
+    pub timeout_ms: u64,
+}
+
+impl Default for ForwardConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            spool_dir: PathBuf::from("spool"),
+            segment_max_bytes: 1024 * 1024,     // 1 MB
+            max_spool_bytes: 100 * 1024 * 1024, // 100 MB
+            batch_size: 500,
+            retry_interval_ms: 1000,
+            max_retry_interval_ms: 60_000,
+            timeout_ms: 5000,
+        }
+    }
+}
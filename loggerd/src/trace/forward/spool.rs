@@ -0,0 +1,481 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension of spool segment files (`00000000000000000001.seg`).
+const SEGMENT_EXTENSION: &str = "seg";
+/// Name of the checkpoint file inside the spool directory.
+const CHECKPOINT_FILE: &str = "checkpoint";
+
+/// Position in the spool: a segment id and a byte offset inside it.
+///
+/// The checkpoint stores the position of the first record that has *not*
+/// been acknowledged by the upstream yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpoolPosition {
+    /// Segment id
+    pub segment: u64,
+    /// Byte offset inside the segment
+    pub offset: u64,
+}
+
+/// A segment file known to the spool.
+#[derive(Debug)]
+struct Segment {
+    id: u64,
+    size: u64,
+}
+
+/// Durable on-disk queue of outbound records.
+///
+/// Records are stored one per line in append-only segment files. A separate
+/// checkpoint file remembers how far the upstream has acknowledged, so the
+/// spool can be reopened after a restart and resume exactly where delivery
+/// stopped.
+///
+/// # Layout
+///
+/// ```text
+/// spool/
+/// ├── 00000000000000000003.seg   (oldest, partially acknowledged)
+/// ├── 00000000000000000004.seg   (active segment, appended to)
+/// └── checkpoint                  ("3 18234")
+/// ```
+///
+/// # Guarantees
+///
+/// - Records are read back in the order they were appended
+/// - The checkpoint is replaced atomically (write + fsync + rename), so an
+///   acknowledged batch is never read again after a restart
+/// - A torn last line left by a crash is truncated on open
+/// - When the spool exceeds `max_spool_bytes`, the oldest segment is dropped
+///   and the number of lost records is reported to the caller
+pub struct Spool {
+    /// Directory containing segments and checkpoint
+    dir: PathBuf,
+    /// Size at which the active segment is sealed and a new one started
+    segment_max_bytes: u64,
+    /// Total size above which the oldest segments are discarded
+    max_spool_bytes: u64,
+    /// Known segments, oldest first (never empty)
+    segments: VecDeque<Segment>,
+    /// Append handle on the last segment
+    writer: File,
+    /// First unacknowledged position
+    checkpoint: SpoolPosition,
+}
+
+impl Spool {
+    /// Opens (or creates) a spool in `dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Spool directory, created if missing
+    /// * `segment_max_bytes` - Size at which segments are rolled over
+    /// * `max_spool_bytes` - Maximum total size of the spool on disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or read, or if the
+    /// checkpoint file is corrupted.
+    pub fn open(dir: &Path, segment_max_bytes: u64, max_spool_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        let mut checkpoint = read_checkpoint(dir)?.unwrap_or(SpoolPosition {
+            segment: ids.first().copied().unwrap_or(1),
+            offset: 0,
+        });
+
+        // Segments older than the checkpoint were fully acknowledged before a
+        // crash interrupted their removal.
+        for id in ids.iter().filter(|id| **id < checkpoint.segment) {
+            let _ = fs::remove_file(segment_path(dir, *id));
+        }
+        ids.retain(|id| *id >= checkpoint.segment);
+
+        let mut segments = VecDeque::new();
+        for id in &ids {
+            let size = fs::metadata(segment_path(dir, *id))?.len();
+            segments.push_back(Segment { id: *id, size });
+        }
+
+        match segments.front() {
+            Some(first) if first.id > checkpoint.segment => {
+                checkpoint = SpoolPosition {
+                    segment: first.id,
+                    offset: 0,
+                };
+            }
+            Some(_) => {}
+            None => {
+                let id = checkpoint.segment.max(1);
+                File::create(segment_path(dir, id))?;
+                segments.push_back(Segment { id, size: 0 });
+                checkpoint = SpoolPosition {
+                    segment: id,
+                    offset: 0,
+                };
+            }
+        }
+
+        // Drop a partially written record at the end of the active segment
+        let last = segments.back_mut().expect("spool has at least one segment");
+        last.size = truncate_torn_tail(&segment_path(dir, last.id))?;
+        if checkpoint.segment == last.id {
+            checkpoint.offset = checkpoint.offset.min(last.size);
+        }
+
+        let writer = OpenOptions::new()
+            .append(true)
+            .open(segment_path(dir, last.id))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segment_max_bytes,
+            max_spool_bytes,
+            segments,
+            writer,
+            checkpoint,
+        })
+    }
+
+    /// Appends one record (a single line, without the trailing newline).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(dropped)` - Number of unacknowledged records discarded to keep
+    ///   the spool under `max_spool_bytes` (usually 0)
+    /// * `Err(std::io::Error)` - If the record cannot be written
+    pub fn append(&mut self, line: &str) -> Result<u64> {
+        if line.contains('\n') {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "spool records must be single lines",
+            ));
+        }
+        let len = line.len() as u64 + 1;
+
+        let last = self
+            .segments
+            .back()
+            .expect("spool has at least one segment");
+        if last.size > 0 && last.size + len > self.segment_max_bytes {
+            self.roll_segment()?;
+        }
+
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.segments.back_mut().unwrap().size += len;
+
+        self.enforce_size_limit()
+    }
+
+    /// Forces appended records to stable storage.
+    pub fn sync(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.sync_data()
+    }
+
+    /// Reads up to `max_records` unacknowledged records, oldest first.
+    ///
+    /// Reading does not consume anything: the records stay in the spool until
+    /// [`Spool::commit`] is called with the returned position.
+    ///
+    /// # Returns
+    ///
+    /// The records and the position just after the last one.
+    pub fn read_batch(&self, max_records: usize) -> Result<(Vec<String>, SpoolPosition)> {
+        let mut records = Vec::new();
+        let mut position = self.checkpoint;
+
+        for segment in self
+            .segments
+            .iter()
+            .filter(|s| s.id >= self.checkpoint.segment)
+        {
+            if records.len() >= max_records {
+                break;
+            }
+            if segment.id > position.segment {
+                position = SpoolPosition {
+                    segment: segment.id,
+                    offset: 0,
+                };
+            }
+            if position.offset >= segment.size {
+                continue;
+            }
+
+            let mut file = File::open(segment_path(&self.dir, segment.id))?;
+            file.seek(SeekFrom::Start(position.offset))?;
+            let mut reader = BufReader::new(file.take(segment.size - position.offset));
+
+            let mut line = String::new();
+            while records.len() < max_records {
+                line.clear();
+                let read = reader.read_line(&mut line)?;
+                if read == 0 || !line.ends_with('\n') {
+                    break;
+                }
+                position.offset += read as u64;
+                records.push(line.trim_end_matches('\n').to_string());
+            }
+        }
+
+        Ok((records, position))
+    }
+
+    /// Marks every record before `position` as acknowledged.
+    ///
+    /// The checkpoint is persisted before fully acknowledged segments are
+    /// deleted, so a crash in between only leaves files that the next
+    /// [`Spool::open`] removes.
+    pub fn commit(&mut self, position: SpoolPosition) -> Result<()> {
+        let mut checkpoint = position;
+        let mut obsolete = Vec::new();
+
+        while self.segments.len() > 1 {
+            let front = self.segments.front().unwrap();
+            let fully_acked = front.id < checkpoint.segment
+                || (front.id == checkpoint.segment && checkpoint.offset >= front.size);
+            if !fully_acked {
+                break;
+            }
+            let front = self.segments.pop_front().unwrap();
+            if front.id == checkpoint.segment {
+                checkpoint = SpoolPosition {
+                    segment: self.segments.front().unwrap().id,
+                    offset: 0,
+                };
+            }
+            obsolete.push(front.id);
+        }
+
+        write_checkpoint(&self.dir, checkpoint)?;
+        self.checkpoint = checkpoint;
+
+        for id in obsolete {
+            let _ = fs::remove_file(segment_path(&self.dir, id));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes waiting for acknowledgement.
+    pub fn pending_bytes(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter(|s| s.id >= self.checkpoint.segment)
+            .map(|s| {
+                if s.id == self.checkpoint.segment {
+                    s.size.saturating_sub(self.checkpoint.offset)
+                } else {
+                    s.size
+                }
+            })
+            .sum()
+    }
+
+    /// Returns `true` when every record has been acknowledged.
+    pub fn is_empty(&self) -> bool {
+        self.pending_bytes() == 0
+    }
+
+    /// Seals the active segment and starts a new one.
+    fn roll_segment(&mut self) -> Result<()> {
+        self.sync()?;
+        let id = self.segments.back().unwrap().id + 1;
+        self.writer = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(segment_path(&self.dir, id))?;
+        self.segments.push_back(Segment { id, size: 0 });
+        Ok(())
+    }
+
+    /// Discards the oldest segments while the spool is over its size limit.
+    fn enforce_size_limit(&mut self) -> Result<u64> {
+        let mut dropped = 0;
+
+        while self.segments.len() > 1
+            && self.segments.iter().map(|s| s.size).sum::<u64>() > self.max_spool_bytes
+        {
+            let oldest = self.segments.pop_front().unwrap();
+            if oldest.id >= self.checkpoint.segment {
+                let from = if oldest.id == self.checkpoint.segment {
+                    self.checkpoint.offset
+                } else {
+                    0
+                };
+                dropped += count_records(&segment_path(&self.dir, oldest.id), from)?;
+
+                self.checkpoint = SpoolPosition {
+                    segment: self.segments.front().unwrap().id,
+                    offset: 0,
+                };
+                write_checkpoint(&self.dir, self.checkpoint)?;
+            }
+            fs::remove_file(segment_path(&self.dir, oldest.id))?;
+        }
+
+        Ok(dropped)
+    }
+}
+
+/// Returns the path of segment `id` in `dir`.
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+}
+
+/// Reads the checkpoint file, if any.
+fn read_checkpoint(dir: &Path) -> Result<Option<SpoolPosition>> {
+    let content = match fs::read_to_string(dir.join(CHECKPOINT_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut parts = content.split_whitespace().map(str::parse::<u64>);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(segment)), Some(Ok(offset))) => Ok(Some(SpoolPosition { segment, offset })),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("corrupted spool checkpoint in {}", dir.display()),
+        )),
+    }
+}
+
+/// Atomically replaces the checkpoint file.
+fn write_checkpoint(dir: &Path, position: SpoolPosition) -> Result<()> {
+    let tmp = dir.join(format!("{}.tmp", CHECKPOINT_FILE));
+    {
+        let mut file = File::create(&tmp)?;
+        writeln!(file, "{} {}", position.segment, position.offset)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, dir.join(CHECKPOINT_FILE))?;
+
+    // Persist the rename itself (not supported on every platform)
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Truncates everything after the last newline and returns the new size.
+fn truncate_torn_tail(path: &Path) -> Result<u64> {
+    let content = fs::read(path)?;
+    let valid = content
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map(|i| i as u64 + 1)
+        .unwrap_or(0);
+
+    if valid < content.len() as u64 {
+        OpenOptions::new().write(true).open(path)?.set_len(valid)?;
+    }
+    Ok(valid)
+}
+
+/// Counts the complete records stored in a segment after `offset`.
+fn count_records(path: &Path, offset: u64) -> Result<u64> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    Ok(content.iter().filter(|b| **b == b'\n').count() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::open(dir.path(), 1024, 1024 * 1024).unwrap();
+        for i in 0..5 {
+            spool.append(&format!("record {}", i)).unwrap();
+        }
+
+        let (batch, position) = spool.read_batch(3).unwrap();
+        assert_eq!(batch, vec!["record 0", "record 1", "record 2"]);
+
+        // Nothing is consumed until commit
+        assert_eq!(spool.read_batch(3).unwrap().0, batch);
+
+        spool.commit(position).unwrap();
+        let (batch, _) = spool.read_batch(10).unwrap();
+        assert_eq!(batch, vec!["record 3", "record 4"]);
+    }
+
+    #[test]
+    fn test_resume_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut spool = Spool::open(dir.path(), 32, 1024 * 1024).unwrap();
+            for i in 0..10 {
+                spool.append(&format!("record {}", i)).unwrap();
+            }
+            spool.sync().unwrap();
+            let (_, position) = spool.read_batch(4).unwrap();
+            spool.commit(position).unwrap();
+        }
+
+        let spool = Spool::open(dir.path(), 32, 1024 * 1024).unwrap();
+        let (batch, _) = spool.read_batch(100).unwrap();
+        let expected: Vec<String> = (4..10).map(|i| format!("record {}", i)).collect();
+        assert_eq!(batch, expected);
+    }
+
+    #[test]
+    fn test_torn_tail_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut spool = Spool::open(dir.path(), 1024, 1024 * 1024).unwrap();
+            spool.append("complete").unwrap();
+            spool.sync().unwrap();
+        }
+        let mut segment = OpenOptions::new()
+            .append(true)
+            .open(segment_path(dir.path(), 1))
+            .unwrap();
+        segment.write_all(b"{\"partial").unwrap();
+
+        let mut spool = Spool::open(dir.path(), 1024, 1024 * 1024).unwrap();
+        spool.append("next").unwrap();
+        assert_eq!(spool.read_batch(10).unwrap().0, vec!["complete", "next"]);
+    }
+
+    #[test]
+    fn test_size_limit_drops_oldest_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        // 10-byte records, 2 per segment, at most 4 records on disk
+        let mut spool = Spool::open(dir.path(), 20, 40).unwrap();
+        let mut dropped = 0;
+        for i in 0..6 {
+            dropped += spool.append(&format!("record-{:02}", i)).unwrap();
+        }
+
+        assert_eq!(dropped, 2);
+        let (batch, _) = spool.read_batch(10).unwrap();
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch[0], "record-02");
+    }
+}
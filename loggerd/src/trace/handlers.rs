@@ -1,4 +1,13 @@
 use crate::trace::Trace;
 
 /// Trait for handling trace logs.
-pub trait TraceHandler: Trace + Send + Sync {}
+pub trait TraceHandler: Trace + Send + Sync {
+    /// Describes the handler in the listing of [`super::HandlerRegister::list`]
+    /// (its output: file path, upstream, ...).
+    ///
+    /// The default implementation gives the name of the type.
+    fn describe(&self) -> String {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name).to_string()
+    }
+}
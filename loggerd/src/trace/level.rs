@@ -1,4 +1,8 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 /// Enumeration of trace levels for logging.
 ///
@@ -9,7 +13,10 @@ use std::fmt::Display;
 /// - Copy is more performant: no dereferencing, direct access to value
 /// - Copy is more idiomatic in Rust for primitive/simple types
 /// - Simplifies code: no & everywhere, no lifetime management
-#[derive(Debug, Copy, Clone)]
+///
+/// Levels are ordered by severity (`Verbose < ... < Critical < None`), so a
+/// minimum level can be expressed with a simple comparison.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[allow(dead_code)] // All levels are part of the public API
 pub enum TraceLevel {
     /// Verbose logging - most detailed
@@ -28,9 +35,21 @@ pub enum TraceLevel {
     None,
 }
 
-impl Display for TraceLevel {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let level_str = match self {
+impl TraceLevel {
+    /// Every level, from the least to the most severe.
+    pub const ALL: [TraceLevel; 7] = [
+        TraceLevel::Verbose,
+        TraceLevel::Debug,
+        TraceLevel::Info,
+        TraceLevel::Warning,
+        TraceLevel::Error,
+        TraceLevel::Critical,
+        TraceLevel::None,
+    ];
+
+    /// Returns the upper-case name of the level, without brackets.
+    pub fn as_str(&self) -> &'static str {
+        match self {
             TraceLevel::Verbose => "VERBOSE",
             TraceLevel::Debug => "DEBUG",
             TraceLevel::Info => "INFO",
@@ -38,7 +57,127 @@ impl Display for TraceLevel {
             TraceLevel::Error => "ERROR",
             TraceLevel::Critical => "CRITICAL",
             TraceLevel::None => "NONE",
-        };
-        write!(f, "[{}]", level_str)
+        }
+    }
+}
+
+impl Display for TraceLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]", self.as_str())
+    }
+}
+
+impl FromStr for TraceLevel {
+    type Err = String;
+
+    /// Parses a level name case-insensitively (`"info"`, `"INFO"`, `"[INFO]"`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().trim_start_matches('[').trim_end_matches(']');
+        match name.to_ascii_uppercase().as_str() {
+            "VERBOSE" | "TRACE" => Ok(TraceLevel::Verbose),
+            "DEBUG" => Ok(TraceLevel::Debug),
+            "INFO" => Ok(TraceLevel::Info),
+            "WARNING" | "WARN" => Ok(TraceLevel::Warning),
+            "ERROR" => Ok(TraceLevel::Error),
+            "CRITICAL" | "FATAL" => Ok(TraceLevel::Critical),
+            "NONE" => Ok(TraceLevel::None),
+            _ => Err(format!("unknown trace level '{}'", s)),
+        }
+    }
+}
+
+impl Serialize for TraceLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TraceLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Minimum level of a trace system, changeable while it runs.
+///
+/// Clones share the same level: the trace system keeps one to drop the
+/// records below it, the admin API another one to read and change it.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::trace::{LevelFilter, TraceLevel};
+///
+/// let filter = LevelFilter::new(TraceLevel::Info);
+/// assert!(!filter.allows(TraceLevel::Debug));
+/// filter.clone().set(TraceLevel::Debug);
+/// assert!(filter.allows(TraceLevel::Debug));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LevelFilter {
+    level: Arc<AtomicU8>,
+}
+
+impl LevelFilter {
+    /// Creates a filter letting through `level` and above.
+    pub fn new(level: TraceLevel) -> Self {
+        Self {
+            level: Arc::new(AtomicU8::new(level as u8)),
+        }
+    }
+
+    /// Returns the current minimum level.
+    pub fn get(&self) -> TraceLevel {
+        TraceLevel::ALL[self.level.load(Ordering::Relaxed) as usize]
+    }
+
+    /// Changes the minimum level and returns the previous one.
+    pub fn set(&self, level: TraceLevel) -> TraceLevel {
+        TraceLevel::ALL[self.level.swap(level as u8, Ordering::Relaxed) as usize]
+    }
+
+    /// Returns `true` if records of `level` pass the filter.
+    pub fn allows(&self, level: TraceLevel) -> bool {
+        level as u8 >= self.level.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for LevelFilter {
+    /// Lets every record through.
+    fn default() -> Self {
+        Self::new(TraceLevel::Verbose)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_ordering() {
+        assert!(TraceLevel::Verbose < TraceLevel::Info);
+        assert!(TraceLevel::Error < TraceLevel::Critical);
+        assert!(TraceLevel::Critical < TraceLevel::None);
+    }
+
+    #[test]
+    fn test_level_parsing() {
+        assert_eq!("info".parse::<TraceLevel>(), Ok(TraceLevel::Info));
+        assert_eq!("[WARNING]".parse::<TraceLevel>(), Ok(TraceLevel::Warning));
+        assert_eq!("warn".parse::<TraceLevel>(), Ok(TraceLevel::Warning));
+        assert!("loud".parse::<TraceLevel>().is_err());
+    }
+
+    #[test]
+    fn test_level_filter() {
+        let filter = LevelFilter::default();
+        assert!(filter.allows(TraceLevel::Verbose));
+
+        let shared = filter.clone();
+        assert_eq!(shared.set(TraceLevel::Warning), TraceLevel::Verbose);
+        assert_eq!(filter.get(), TraceLevel::Warning);
+        assert!(!filter.allows(TraceLevel::Info));
+        assert!(filter.allows(TraceLevel::Critical));
     }
 }
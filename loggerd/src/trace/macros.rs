@@ -0,0 +1,166 @@
+//! Logging macros capturing the call site.
+
+/// Logs a record at the given level, with a formatted message and optional
+/// fields.
+///
+/// ```text
+/// trace_log!(trace, level, "format {}", args...; key = value, ...)
+/// ```
+///
+/// The record has the [`DEFAULT_SOURCE`](crate::trace::DEFAULT_SOURCE)
+/// source and carries the call site in its `module` and `location` fields
+/// (see [`TraceRecord::with_call_site`](crate::trace::TraceRecord::with_call_site)),
+/// followed by the fields after `;`, whose values are formatted with
+/// `Display`. When [`Trace::enabled`](crate::trace::Trace::enabled) rejects
+/// the level, neither the message nor the field values are formatted or
+/// even evaluated.
+///
+/// `trace` is anything that derefs to a [`Trace`](crate::trace::Trace):
+/// a trace, a reference or an `Arc`. The per-level macros
+/// ([`trace_info!`](crate::trace_info), [`trace_error!`](crate::trace_error),
+/// ...) are shorthands for this one.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::trace::{ConcreteTrace, HandlerRegister, RingBufferTraceHandler, TraceLevel};
+/// use loggerd::trace_log;
+///
+/// let trace = ConcreteTrace::new();
+/// let recent = RingBufferTraceHandler::new(10);
+/// trace.register(recent.clone());
+///
+/// let user = "bob";
+/// trace_log!(trace, TraceLevel::Info, "{user} logged in"; user = user, attempts = 2);
+///
+/// let record = &recent.recent(None, 1)[0];
+/// assert_eq!(record.message, "bob logged in");
+/// assert_eq!(record.fields["attempts"], "2");
+/// assert_eq!(record.fields["module"], module_path!());
+/// ```
+#[macro_export]
+macro_rules! trace_log {
+    ($trace:expr, $level:expr, $fmt:literal $(, $arg:expr)* $(,)? $(; $($key:ident = $value:expr),+ $(,)?)?) => {{
+        #[allow(unused_imports)]
+        use $crate::trace::Trace as _;
+        let trace = &$trace;
+        let level: $crate::trace::TraceLevel = $level;
+        if trace.enabled(level) {
+            #[allow(unused_mut)]
+            let mut record = $crate::trace::TraceRecord::new(
+                level,
+                $crate::trace::DEFAULT_SOURCE,
+                &::std::format!($fmt $(, $arg)*),
+            )
+            .with_call_site(::std::module_path!(), ::std::file!(), ::std::line!());
+            $($(
+                record.fields.insert(
+                    ::std::stringify!($key).to_string(),
+                    ::std::string::ToString::to_string(&$value),
+                );
+            )+)?
+            trace.log_record(&record);
+        }
+    }};
+}
+
+/// Logs a Verbose record, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_verbose {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Verbose, $($rest)+)
+    };
+}
+
+/// Logs a Debug record, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_debug {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Debug, $($rest)+)
+    };
+}
+
+/// Logs an Info record, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_info {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Info, $($rest)+)
+    };
+}
+
+/// Logs a Warning record, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_warning {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Warning, $($rest)+)
+    };
+}
+
+/// Logs an Error record, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_error {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Error, $($rest)+)
+    };
+}
+
+/// Logs a Critical record, see [`trace_log!`](crate::trace_log).
+#[macro_export]
+macro_rules! trace_critical {
+    ($trace:expr, $($rest:tt)+) => {
+        $crate::trace_log!($trace, $crate::trace::TraceLevel::Critical, $($rest)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trace::{
+        ConcreteTrace, HandlerRegister, LevelFilter, RingBufferTraceHandler, Trace, TraceLevel,
+    };
+    use std::fmt;
+    use std::sync::Arc;
+
+    /// Panics when formatted.
+    struct Expensive;
+    impl fmt::Display for Expensive {
+        fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            panic!("formatted a filtered record");
+        }
+    }
+
+    #[test]
+    fn test_macros() {
+        let trace = Arc::new(ConcreteTrace::new());
+        let recent = RingBufferTraceHandler::new(10);
+        trace.register(recent.clone());
+        let filter: LevelFilter = trace.level_filter();
+        filter.set(TraceLevel::Info);
+
+        let line = line!() + 1;
+        crate::trace_warning!(trace, "disk {}% full", 93; mount = "/var", free_mb = 512);
+        crate::trace_error!(trace, "plain");
+        let dyn_trace: Arc<dyn Trace + Send + Sync> = trace.clone();
+        crate::trace_info!(dyn_trace, "{} arg, trailing comma", 1,);
+
+        // Filtered out: nothing is formatted nor evaluated
+        let mut evaluated = false;
+        crate::trace_debug!(trace, "{}", Expensive; value = {
+            evaluated = true;
+            Expensive
+        });
+        assert!(!evaluated);
+
+        let records = recent.recent(None, 10);
+        assert_eq!(records.len(), 3);
+        let record = &records[0];
+        assert_eq!(record.level, TraceLevel::Warning);
+        assert_eq!(record.message, "disk 93% full");
+        assert_eq!(record.fields["mount"], "/var");
+        assert_eq!(record.fields["free_mb"], "512");
+        assert_eq!(record.fields["module"], module_path!());
+        assert_eq!(record.fields["location"], format!("{}:{}", file!(), line));
+        assert_eq!(records[1].message, "plain");
+        assert_eq!(records[1].fields.len(), 2);
+        assert_eq!(records[2].level, TraceLevel::Info);
+    }
+}
@@ -1,8 +1,17 @@
 mod concrete_trace;
+mod drain;
+mod fields;
 pub mod file; // New structured module
+pub mod forward;
 mod handlers;
 mod level;
+mod macros;
+mod panic_hook;
 mod print_trace_handlers;
+mod query;
+mod record;
+mod ring_buffer;
+pub mod stage;
 #[allow(clippy::module_inception)]
 mod trace;
 
@@ -10,12 +19,25 @@ use std::io::Error;
 use std::sync::Arc;
 use std::sync::atomic::AtomicU64;
 
-use concrete_trace::ConcreteTrace;
-use print_trace_handlers::PrintTraceHandler;
-use trace::HandlerRegister;
+use crate::alert::AlertManager;
+use crate::config::LoggerdConfig;
+use crate::metrics::MetricsRegistry;
 
-pub use level::TraceLevel;
-pub use trace::Trace;
+pub use concrete_trace::ConcreteTrace;
+pub use drain::DrainReport;
+pub(crate) use drain::{QueueDepth, drain_queue};
+pub(crate) use fields::parse_logfmt;
+pub use fields::{FieldFilter, Fields, fields_from_json};
+pub use handlers::TraceHandler;
+pub use level::{LevelFilter, TraceLevel};
+pub(crate) use panic_hook::lock;
+pub use panic_hook::{PANIC_SOURCE, install_panic_hook};
+pub use print_trace_handlers::PrintTraceHandler;
+pub(crate) use query::tokenize;
+pub use query::{RecordQuery, TermQuery};
+pub use record::{DEFAULT_SOURCE, TraceRecord};
+pub use ring_buffer::{RingBufferConfig, RingBufferTraceHandler};
+pub use trace::{HandlerId, HandlerInfo, HandlerRegister, Trace};
 
 /// Creates a preconfigured trace system for the loggerd daemon.
 ///
@@ -52,14 +74,137 @@ pub use trace::Trace;
 /// - There are insufficient permissions to write to the current directory
 // TODO: Add builder pattern for more flexible configuration
 pub fn create_trace() -> Result<(impl Trace + Send + Sync, Arc<AtomicU64>), Error> {
+    let (trace, handles) =
+        create_trace_with_config(&LoggerdConfig::default(), &Arc::new(MetricsRegistry::new()))?;
+    Ok((trace, handles.log_counter))
+}
+
+/// Parts of a trace system the daemon keeps a hand on.
+#[derive(Clone)]
+pub struct TraceHandles {
+    /// Number of lines written to the log files
+    pub log_counter: Arc<AtomicU64>,
+    /// Most recent records, served by `/logs/recent`
+    pub recent: RingBufferTraceHandler,
+    /// Alert rules, served by `/alerts` (None without `[alerts]` section)
+    pub alerts: Option<AlertManager>,
+    /// Free space watchdog of the log directories, reported by `/health`
+    /// (None without `[disk_guard]` section or file output)
+    pub disk_guard: Option<Arc<file::DiskGuard>>,
+    /// Active log files and backups, served by `/admin/files` and searched
+    /// by `GET /logs` (None when the file output is disabled)
+    pub log_files: Option<file::LogFiles>,
+    /// Minimum level of the trace system, changed by `/admin/level`
+    pub level: LevelFilter,
+}
+
+/// Creates the trace system described by a loggerd configuration.
+///
+/// Registers a console handler, a [`RingBufferTraceHandler`] sized by the
+/// `[ring_buffer]` section, a [`file::RoutingTraceHandler`] built from the
+/// `[routing]` section (defaulting to the single `loggerd.log` file of
+/// [`create_trace`]; skipped when `enabled = false`) guarded by a
+/// [`file::DiskGuard`] when the `[disk_guard]` section is present, plus a
+/// [`forward::ForwardTraceHandler`] when the `[forward]` section is present.
+///
+/// Before the handlers, records go through a [`stage::ParserStage`] when
+/// `[[parsers]]` are defined, a [`stage::RedactionStage`] when the
+/// `[redaction]` section is present, a [`stage::LogMetricsStage`] when
+/// `[[log_metrics]]` rules are defined, an [`AlertManager`] when the
+/// `[alerts]` section is present, a [`stage::DedupStage`] when the
+/// `[dedup]` section is present, then a [`stage::RateLimitStage`] when the
+/// `[rate_limit]` section is present.
+///
+/// # Arguments
+///
+/// * `config` - Daemon configuration
+/// * `metrics` - Registry receiving the handlers' counters
+///
+/// # Errors
+///
+/// Same as [`create_trace`], and additionally if a parser, a redaction
+/// pattern, a log metric rule, an alert rule, the rate limit or a forwarding or webhook URL
+/// is invalid, or if the spool directory cannot be opened.
+pub fn create_trace_with_config(
+    config: &LoggerdConfig,
+    metrics: &Arc<MetricsRegistry>,
+) -> Result<(impl Trace + Send + Sync + use<>, TraceHandles), Error> {
     let trace = ConcreteTrace::new();
+    let level = trace.level_filter();
+
+    // Parse first, so that redaction also applies to the extracted fields
+    if !config.parsers.is_empty() {
+        trace.add_stage(stage::ParserStage::new(&config.parsers, metrics)?);
+    }
+    // Redact before any other stage or handler may see the secrets
+    if let Some(redaction) = &config.redaction {
+        trace.add_stage(stage::RedactionStage::new(redaction, metrics)?);
+    }
+    // Count before dedup and rate limiting, which drop records
+    if !config.log_metrics.is_empty() {
+        trace.add_stage(stage::LogMetricsStage::new(
+            &config.log_metrics,
+            metrics.clone(),
+        )?);
+    }
+    // Alert on every record, before repeats are collapsed
+    let alerts = config
+        .alerts
+        .as_ref()
+        .map(|alerts| AlertManager::new(alerts, metrics))
+        .transpose()?;
+    if let Some(alerts) = &alerts {
+        trace.add_stage(alerts.clone());
+    }
+    // Collapse repeats so that they don't use up the rate limit
+    if let Some(dedup) = &config.dedup {
+        trace.add_stage(stage::DedupStage::new(dedup.clone(), metrics.clone()));
+    }
+    if let Some(rate_limit) = &config.rate_limit {
+        trace.add_stage(stage::RateLimitStage::new(
+            rate_limit.clone(),
+            metrics.clone(),
+        )?);
+    }
+
+    let recent = RingBufferTraceHandler::new(config.ring_buffer.capacity);
+    trace.register(PrintTraceHandler::new());
+    trace.register(recent.clone());
 
-    let print_handler = PrintTraceHandler::new();
-    let file_handler = file::FileTraceHandler::new("loggerd.log")?.start()?;
-    let log_counter = file_handler.log_counter();
+    let mut disk_guard = None;
+    let mut log_files = None;
+    let log_counter = if config.routing.enabled {
+        let mut file_handler = file::RoutingTraceHandler::new(&config.routing)?;
+        if let Some(guard_config) = &config.disk_guard {
+            let guard =
+                file::DiskGuard::new(guard_config, &file_handler.file_paths(), metrics)?.start()?;
+            file_handler = file_handler.with_disk_guard(&guard);
+            disk_guard = Some(Arc::new(guard));
+        }
+        let file_handler = file_handler.start()?;
+        log_files = Some(file_handler.log_files());
+        let log_counter = file_handler.log_counter();
+        trace.register(file_handler);
+        log_counter
+    } else {
+        Arc::new(AtomicU64::new(0))
+    };
 
-    trace.register(print_handler);
-    trace.register(file_handler);
+    if let Some(forward_config) = &config.forward {
+        let forward_handler =
+            forward::ForwardTraceHandler::new(forward_config.clone(), metrics)?.start()?;
+        trace.register(forward_handler);
+    }
 
-    Ok((trace, log_counter))
+    Ok((
+        trace,
+        TraceHandles {
+            log_counter,
+            recent,
+            alerts,
+            disk_guard,
+            log_files,
+            level,
+        },
+    ))
 }
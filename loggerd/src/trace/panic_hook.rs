@@ -0,0 +1,118 @@
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::panic::{self, Location};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use super::level::TraceLevel;
+use super::record::TraceRecord;
+use super::trace::Trace;
+
+/// Source of the records reporting a panic.
+pub const PANIC_SOURCE: &str = "panic";
+
+/// Reports the panics of every thread through `trace`.
+///
+/// Replaces the process panic hook: each panic becomes a Critical record
+/// with the [`PANIC_SOURCE`] source, the `thread` and `location` fields, and
+/// the backtrace on continuation lines when `RUST_BACKTRACE` enables it. The
+/// previous hook still runs first, so the panic is printed to stderr even if
+/// the trace cannot deliver it (a handler panicking while the same thread
+/// dispatches a record, see [`super::ConcreteTrace`]).
+///
+/// The hook only keeps a weak reference: once the trace is dropped, panics
+/// go to stderr only.
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// let config = loggerd::config::LoggerdConfig::default();
+/// let server = loggerd::server::start(config).await?;
+/// loggerd::trace::install_panic_hook(server.trace());
+/// # Ok(())
+/// # }
+/// ```
+pub fn install_panic_hook(trace: Arc<dyn Trace + Send + Sync>) {
+    let trace = Arc::downgrade(&trace);
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if let Some(trace) = trace.upgrade() {
+            let thread = thread::current();
+            trace.log_record(&panic_record(
+                thread.name().unwrap_or("<unnamed>"),
+                info.location(),
+                info.payload(),
+                &Backtrace::capture(),
+            ));
+        }
+    }));
+}
+
+/// Locks `mutex`, even if a thread panicked while holding it: a handler or
+/// stage that panics must not take logging down for every later record.
+pub(crate) fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Builds the record reporting a panic.
+fn panic_record(
+    thread: &str,
+    location: Option<&Location<'_>>,
+    payload: &(dyn Any + Send),
+    backtrace: &Backtrace,
+) -> TraceRecord {
+    let payload = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    let location = location.map_or_else(|| "<unknown>".to_string(), ToString::to_string);
+
+    let mut message = format!("thread '{}' panicked at {}: {}", thread, location, payload);
+    if backtrace.status() == BacktraceStatus::Captured {
+        message.push_str(&format!("\nstack backtrace:\n{}", backtrace));
+    }
+    let mut record = TraceRecord::new(TraceLevel::Critical, PANIC_SOURCE, &message);
+    record
+        .fields
+        .insert("thread".to_string(), thread.to_string());
+    record.fields.insert("location".to_string(), location);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_record() {
+        let location = Location::caller();
+        let payload: Box<dyn Any + Send> = Box::new(format!("index {} out of range", 7));
+        let record = panic_record(
+            "loggerd-forward",
+            Some(location),
+            payload.as_ref(),
+            &Backtrace::disabled(),
+        );
+
+        assert_eq!(record.level, TraceLevel::Critical);
+        assert_eq!(record.source, PANIC_SOURCE);
+        assert_eq!(
+            record.message,
+            format!(
+                "thread 'loggerd-forward' panicked at {}: index 7 out of range",
+                location
+            )
+        );
+        assert_eq!(record.fields["thread"], "loggerd-forward");
+        assert_eq!(record.fields["location"], location.to_string());
+
+        let record = panic_record("main", None, &42, &Backtrace::force_capture());
+        assert!(
+            record
+                .message
+                .starts_with("thread 'main' panicked at <unknown>: Box<dyn Any>")
+        );
+        assert!(record.message.contains("\nstack backtrace:\n"));
+    }
+}
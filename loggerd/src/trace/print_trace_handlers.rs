@@ -33,6 +33,12 @@ impl PrintTraceHandler {
     }
 }
 
+impl Default for PrintTraceHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Trace for PrintTraceHandler {
     fn log(&self, level: super::TraceLevel, message: &str) {
         let message = format!("{} - {}", level, message);
@@ -40,7 +46,11 @@ impl Trace for PrintTraceHandler {
     }
 }
 
-impl TraceHandler for PrintTraceHandler {}
+impl TraceHandler for PrintTraceHandler {
+    fn describe(&self) -> String {
+        "console".to_string()
+    }
+}
 
 // PrintTraceHandler is Send + Sync because it has no shared state
 unsafe impl Send for PrintTraceHandler {}
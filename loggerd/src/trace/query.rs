@@ -0,0 +1,229 @@
+use chrono::{DateTime, Local};
+use regex::Regex;
+use std::collections::HashSet;
+
+use super::level::TraceLevel;
+use super::record::TraceRecord;
+
+/// Criteria selecting records, shared by the log endpoints and the search
+/// of the log files.
+///
+/// Every criterion is optional; a default query matches every record.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::trace::{RecordQuery, TraceLevel, TraceRecord};
+///
+/// let query = RecordQuery {
+///     level: Some(TraceLevel::Warning),
+///     pattern: Some(regex::Regex::new("timeout").unwrap()),
+///     ..RecordQuery::default()
+/// };
+/// assert!(query.matches(&TraceRecord::new(TraceLevel::Error, "db", "query timeout")));
+/// assert!(!query.matches(&TraceRecord::new(TraceLevel::Info, "db", "query timeout")));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RecordQuery {
+    /// Records emitted at or after this time
+    pub since: Option<DateTime<Local>>,
+    /// Records emitted at or before this time
+    pub until: Option<DateTime<Local>>,
+    /// Minimum level
+    pub level: Option<TraceLevel>,
+    /// Exact source
+    pub source: Option<String>,
+    /// Regular expression searched in the message
+    pub pattern: Option<Regex>,
+    /// Fields that must have exactly these values
+    pub fields: Vec<(String, String)>,
+    /// Words the record must contain, see [`TermQuery`]
+    pub terms: Option<TermQuery>,
+}
+
+impl RecordQuery {
+    /// Returns `true` if `record` meets every criterion.
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp <= until)
+            && self.level.is_none_or(|level| record.level >= level)
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| record.source == *source)
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&record.message))
+            && self
+                .fields
+                .iter()
+                .all(|(name, value)| record.fields.get(name) == Some(value))
+            && self
+                .terms
+                .as_ref()
+                .is_none_or(|terms| terms.matches(record))
+    }
+}
+
+/// Longest token indexed and searched; longer words are ignored.
+const MAX_TOKEN_LEN: usize = 64;
+
+/// Splits a text into lowercase tokens: runs of letters and digits.
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && token.len() <= MAX_TOKEN_LEN)
+        .map(str::to_lowercase)
+}
+
+/// Returns the tokens of a record: source, message, field names and values.
+fn record_tokens(record: &TraceRecord) -> HashSet<String> {
+    let fields = record
+        .fields
+        .iter()
+        .flat_map(|(name, value)| [name.as_str(), value.as_str()]);
+    [record.source.as_str(), record.message.as_str()]
+        .into_iter()
+        .chain(fields)
+        .flat_map(tokenize)
+        .collect()
+}
+
+/// Words searched in the records (`?q=` of `GET /logs`).
+///
+/// Words separated by spaces must all appear (AND); `OR` separates
+/// alternatives. Words are matched whole and case-insensitively against the
+/// source, the message and the fields; a word with punctuation (`db-01`)
+/// stands for all its parts. This is what the token index of the log files
+/// answers without scanning them.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::trace::{TermQuery, TraceLevel, TraceRecord};
+///
+/// let query = TermQuery::parse("timeout db OR refused").unwrap();
+/// assert!(query.matches(&TraceRecord::new(TraceLevel::Error, "db", "Query Timeout")));
+/// assert!(query.matches(&TraceRecord::new(TraceLevel::Error, "web", "connection refused")));
+/// assert!(!query.matches(&TraceRecord::new(TraceLevel::Error, "web", "timeout")));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermQuery {
+    /// Alternatives, each a set of tokens that must all be present
+    groups: Vec<Vec<String>>,
+}
+
+impl TermQuery {
+    /// Parses `word word OR word`; `AND` between words is accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if an alternative is empty or a word has no letter
+    /// or digit.
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let mut groups = vec![Vec::new()];
+        for word in query.split_whitespace() {
+            match word {
+                "OR" => groups.push(Vec::new()),
+                "AND" => {}
+                _ => {
+                    let tokens: Vec<String> = tokenize(word).collect();
+                    if tokens.is_empty() {
+                        return Err(format!("invalid term '{}': no letter or digit", word));
+                    }
+                    groups.last_mut().unwrap().extend(tokens);
+                }
+            }
+        }
+        if groups.iter().any(Vec::is_empty) {
+            return Err(format!("invalid terms '{}': empty alternative", query));
+        }
+        Ok(Self { groups })
+    }
+
+    /// Returns the alternatives, each a set of tokens.
+    pub fn groups(&self) -> &[Vec<String>] {
+        &self.groups
+    }
+
+    /// Returns `true` if the record contains every token of an alternative.
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        let tokens = record_tokens(record);
+        self.groups
+            .iter()
+            .any(|group| group.iter().all(|token| tokens.contains(token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_criteria() {
+        let mut record = TraceRecord::new(TraceLevel::Warning, "billing", "payment retry");
+        record
+            .fields
+            .insert("order".to_string(), "A-42".to_string());
+        assert!(RecordQuery::default().matches(&record));
+
+        let matching = RecordQuery {
+            since: Some(record.timestamp - Duration::minutes(1)),
+            until: Some(record.timestamp),
+            level: Some(TraceLevel::Info),
+            source: Some("billing".to_string()),
+            pattern: Some(Regex::new("^pay").unwrap()),
+            fields: vec![("order".to_string(), "A-42".to_string())],
+            terms: Some(TermQuery::parse("payment a OR refund").unwrap()),
+        };
+        assert!(matching.matches(&record));
+
+        let rejecting = [
+            RecordQuery {
+                since: Some(record.timestamp + Duration::milliseconds(1)),
+                ..matching.clone()
+            },
+            RecordQuery {
+                level: Some(TraceLevel::Error),
+                ..matching.clone()
+            },
+            RecordQuery {
+                source: Some("web".to_string()),
+                ..matching.clone()
+            },
+            RecordQuery {
+                pattern: Some(Regex::new("refund").unwrap()),
+                ..matching.clone()
+            },
+            RecordQuery {
+                fields: vec![("order".to_string(), "A-43".to_string())],
+                ..matching.clone()
+            },
+            RecordQuery {
+                terms: Some(TermQuery::parse("billing refund").unwrap()),
+                ..matching.clone()
+            },
+        ];
+        assert!(rejecting.iter().all(|query| !query.matches(&record)));
+    }
+
+    #[test]
+    fn test_term_query() {
+        let query = TermQuery::parse("Timeout AND db-01 OR 502").unwrap();
+        assert_eq!(query.groups(), [vec!["timeout", "db", "01"], vec!["502"]]);
+
+        let mut record = TraceRecord::new(TraceLevel::Error, "web", "upstream failed");
+        assert!(!query.matches(&record));
+        record
+            .fields
+            .insert("status".to_string(), "502".to_string());
+        assert!(query.matches(&record));
+        // Whole words only
+        assert!(!query.matches(&TraceRecord::new(TraceLevel::Info, "db", "01 timeouts")));
+
+        assert!(TermQuery::parse("").is_err());
+        assert!(TermQuery::parse("a OR").is_err());
+        assert!(TermQuery::parse("--").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::fields::{Fields, format_logfmt, parse_logfmt};
+use super::level::TraceLevel;
+
+/// Source name used for records emitted by the daemon itself.
+pub const DEFAULT_SOURCE: &str = "loggerd";
+
+/// A single log record flowing through the trace system.
+///
+/// `Trace::log` only carries a level and a message; a `TraceRecord` adds the
+/// information needed once records leave the process or come from other
+/// applications: when it was emitted and by whom.
+///
+/// Records serialize to a flat JSON object, which is the format used by the
+/// forwarding spool and by the upstream HTTP protocol:
+///
+/// ```text
+/// {"timestamp":"2025-10-14T17:45:32.123+02:00","level":"INFO","source":"loggerd","message":"started"}
+/// ```
+///
+/// Records may also carry structured `fields`, extracted by the parsers or
+/// sent by clients (`"fields":{"status":"500"}`); the key is omitted when
+/// there are none.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::trace::{TraceLevel, TraceRecord};
+///
+/// let record = TraceRecord::new(TraceLevel::Info, "billing", "invoice sent");
+/// assert_eq!(record.source, "billing");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    /// Time at which the record was emitted
+    pub timestamp: DateTime<Local>,
+    /// Severity of the record
+    pub level: TraceLevel,
+    /// Name of the application or component that emitted the record
+    pub source: String,
+    /// Message content
+    pub message: String,
+    /// Structured fields (status code, user, duration, ...)
+    #[serde(default, skip_serializing_if = "Fields::is_empty")]
+    pub fields: Fields,
+}
+
+impl TraceRecord {
+    /// Creates a record timestamped with the current local time.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Severity of the record
+    /// * `source` - Emitting application or component
+    /// * `message` - Message content
+    pub fn new(level: TraceLevel, source: &str, message: &str) -> Self {
+        Self {
+            timestamp: Local::now(),
+            level,
+            source: source.to_string(),
+            message: message.to_string(),
+            fields: Fields::new(),
+        }
+    }
+
+    /// Adds the place that emitted the record, as the `module` and
+    /// `location` (`file:line`) fields; used by the logging macros such as
+    /// [`trace_info!`](crate::trace_info).
+    pub fn with_call_site(mut self, module: &str, file: &str, line: u32) -> Self {
+        self.fields.insert("module".to_string(), module.to_string());
+        self.fields
+            .insert("location".to_string(), format!("{}:{}", file, line));
+        self
+    }
+}
+
+/// Formats the record as a log file line (without the trailing newline).
+///
+/// ```text
+/// 2025-10-14T17:45:32.123+02:00 [INFO] loggerd - message
+/// ```
+///
+/// Fields, if any, follow the message in logfmt: `- message {status=500 user=bob}`.
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} - {}",
+            self.timestamp.format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+            self.level,
+            self.source,
+            self.message
+        )?;
+        if !self.fields.is_empty() {
+            write!(f, " {{{}}}", format_logfmt(&self.fields))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a log file line back into a record (see the [`Display`] format).
+///
+/// The message may span several lines. A trailing `{...}` is read as the
+/// fields when it is valid logfmt, and left in the message otherwise. The
+/// timestamp keeps the millisecond precision of the file.
+impl FromStr for TraceRecord {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("not a log line: '{}'", line);
+        let (timestamp, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        let timestamp = DateTime::parse_from_rfc3339(timestamp).map_err(|_| invalid())?;
+        let (level, rest) = rest.split_once(' ').ok_or_else(invalid)?;
+        if !level.starts_with('[') {
+            return Err(invalid());
+        }
+        let level = level.parse()?;
+        let (source, message) = rest.split_once(" - ").ok_or_else(invalid)?;
+
+        let (message, fields) = message
+            .strip_suffix('}')
+            .and_then(|head| head.rsplit_once(" {"))
+            .and_then(|(message, fields)| Some((message, parse_logfmt(fields)?)))
+            .unwrap_or((message, Fields::new()));
+
+        Ok(Self {
+            timestamp: timestamp.with_timezone(&Local),
+            level,
+            source: source.to_string(),
+            message: message.to_string(),
+            fields,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let record = TraceRecord::new(TraceLevel::Warning, "app", "disk almost full");
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains("\"level\":\"WARNING\""));
+
+        let decoded: TraceRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, record);
+    }
+
+    #[test]
+    fn test_line_format() {
+        let record = TraceRecord::new(TraceLevel::Info, "app", "started");
+        let line = record.to_string();
+        assert!(line.ends_with(" [INFO] app - started"));
+        assert!(line.starts_with(&record.timestamp.format("%Y-%m-%dT%H:%M:%S").to_string()));
+
+        let mut record = record;
+        record
+            .fields
+            .insert("status".to_string(), "500".to_string());
+        assert!(record.to_string().ends_with(" - started {status=500}"));
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let mut record = TraceRecord::new(TraceLevel::Error, "web", "request failed\nretrying");
+        record
+            .fields
+            .insert("user".to_string(), "jane doe".to_string());
+        let parsed: TraceRecord = record.to_string().parse().unwrap();
+        assert_eq!(parsed.level, TraceLevel::Error);
+        assert_eq!(parsed.source, "web");
+        assert_eq!(parsed.message, "request failed\nretrying");
+        assert_eq!(parsed.fields, record.fields);
+        assert_eq!(
+            parsed.timestamp.timestamp_millis(),
+            record.timestamp.timestamp_millis()
+        );
+
+        // Braces that are not logfmt stay in the message
+        let line = "2025-10-14T17:45:32.123+02:00 [INFO] app - got {not logfmt}";
+        let parsed: TraceRecord = line.parse().unwrap();
+        assert_eq!(parsed.message, "got {not logfmt}");
+        assert!(parsed.fields.is_empty());
+
+        assert!("continued line".parse::<TraceRecord>().is_err());
+        assert!(
+            "2025-10-14T17:45:32.123+02:00 INFO app - m"
+                .parse::<TraceRecord>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_fields_are_optional_in_json() {
+        let json = serde_json::to_string(&TraceRecord::new(TraceLevel::Info, "app", "m")).unwrap();
+        assert!(!json.contains("fields"));
+
+        let mut record = TraceRecord::new(TraceLevel::Info, "app", "m");
+        record.fields.insert("user".to_string(), "bob".to_string());
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(r#""fields":{"user":"bob"}"#));
+        assert_eq!(serde_json::from_str::<TraceRecord>(&json).unwrap(), record);
+    }
+}
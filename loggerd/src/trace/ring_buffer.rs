@@ -0,0 +1,344 @@
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::handlers::TraceHandler;
+use super::level::TraceLevel;
+use super::panic_hook::lock;
+use super::record::{DEFAULT_SOURCE, TraceRecord};
+use super::trace::Trace;
+
+/// Configuration of the in-memory buffer of recent records (`[ring_buffer]` section).
+///
+/// ```toml
+/// [ring_buffer]
+/// capacity = 1000
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RingBufferConfig {
+    /// Number of records kept
+    pub capacity: usize,
+}
+
+impl Default for RingBufferConfig {
+    fn default() -> Self {
+        Self { capacity: 1000 }
+    }
+}
+
+/// One slot of the buffer: the record and its sequence number.
+type Slot = Mutex<Option<(u64, TraceRecord)>>;
+
+/// Storage shared by the clones of a [`RingBufferTraceHandler`].
+struct RingBuffer {
+    /// Sequence number of the next record
+    next_seq: AtomicU64,
+    /// Record `seq` lives in slot `seq % slots.len()`
+    slots: Box<[Slot]>,
+}
+
+/// Trace handler keeping the most recent records in memory.
+///
+/// The buffer has a fixed capacity: once full, each new record replaces the
+/// oldest one. Writers reserve a sequence number with an atomic increment
+/// and only lock the slot they write, so concurrent loggers rarely contend
+/// and readers never block the whole buffer.
+///
+/// Clones share the same buffer: one clone is registered in the trace
+/// system, another one serves `/logs/recent`.
+///
+/// # Examples
+///
+/// ```
+/// use loggerd::trace::{RingBufferTraceHandler, Trace, TraceLevel};
+///
+/// let recent = RingBufferTraceHandler::new(100);
+/// recent.log(TraceLevel::Info, "kept in memory");
+/// assert_eq!(recent.recent(None, 10).len(), 1);
+/// ```
+#[derive(Clone)]
+pub struct RingBufferTraceHandler {
+    buffer: Arc<RingBuffer>,
+}
+
+impl RingBufferTraceHandler {
+    /// Creates an empty buffer holding up to `capacity` records (at least one).
+    pub fn new(capacity: usize) -> Self {
+        let slots = (0..capacity.max(1)).map(|_| Mutex::new(None)).collect();
+        Self {
+            buffer: Arc::new(RingBuffer {
+                next_seq: AtomicU64::new(0),
+                slots,
+            }),
+        }
+    }
+
+    /// Returns the number of records the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.slots.len()
+    }
+
+    /// Returns up to `limit` of the most recent records, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_level` - Only return records at least this severe
+    /// * `limit` - Maximum number of records returned
+    pub fn recent(&self, min_level: Option<TraceLevel>, limit: usize) -> Vec<TraceRecord> {
+        self.recent_matching(limit, |record| {
+            min_level.is_none_or(|level| record.level >= level)
+        })
+    }
+
+    /// Returns up to `limit` of the most recent records accepted by
+    /// `predicate`, oldest first.
+    pub fn recent_matching(
+        &self,
+        limit: usize,
+        predicate: impl Fn(&TraceRecord) -> bool,
+    ) -> Vec<TraceRecord> {
+        self.recent_after(None, limit, predicate).0
+    }
+
+    /// Same as [`recent_matching`](Self::recent_matching), restricted to the
+    /// records logged since `cursor` when given.
+    ///
+    /// Also returns the cursor to pass next time to only get newer records,
+    /// which is how `loggerctl tail -f` follows the daemon. From a cursor,
+    /// records are read oldest first and the returned cursor stops at the
+    /// first one not returned, whether because `limit` was reached or
+    /// because its writer has not filled its slot yet: the next call picks
+    /// up from there, so records are only missed when the buffer overwrote
+    /// them. Without a cursor, the newest records are returned, minus those
+    /// logged after a record still being written.
+    pub fn recent_after(
+        &self,
+        cursor: Option<u64>,
+        limit: usize,
+        predicate: impl Fn(&TraceRecord) -> bool,
+    ) -> (Vec<TraceRecord>, u64) {
+        let capacity = self.capacity() as u64;
+        let end = self.buffer.next_seq.load(Ordering::Acquire);
+        let oldest = end.saturating_sub(capacity);
+
+        let mut records = Vec::new();
+        let Some(cursor) = cursor else {
+            let mut end = end;
+            for seq in (oldest..end).rev() {
+                if records.len() >= limit {
+                    break;
+                }
+                match self.read(seq) {
+                    SlotState::Written(record) if predicate(&record) => records.push(record),
+                    SlotState::Written(_) | SlotState::Overwritten => {}
+                    // Newer records come back with it, from the returned cursor
+                    SlotState::Pending => {
+                        records.clear();
+                        end = seq;
+                    }
+                }
+            }
+            records.reverse();
+            return (records, end);
+        };
+
+        for seq in cursor.clamp(oldest, end)..end {
+            if records.len() >= limit {
+                return (records, seq);
+            }
+            match self.read(seq) {
+                SlotState::Written(record) if predicate(&record) => records.push(record),
+                SlotState::Written(_) | SlotState::Overwritten => {}
+                SlotState::Pending => return (records, seq),
+            }
+        }
+        (records, end)
+    }
+
+    /// Reads the record `seq` from its slot.
+    fn read(&self, seq: u64) -> SlotState {
+        let index = (seq % self.capacity() as u64) as usize;
+        let slot = lock(&self.buffer.slots[index]);
+        match slot.as_ref() {
+            Some((slot_seq, record)) if *slot_seq == seq => SlotState::Written(record.clone()),
+            Some((slot_seq, _)) if *slot_seq > seq => SlotState::Overwritten,
+            _ => SlotState::Pending,
+        }
+    }
+}
+
+/// What the slot of a sequence number holds, see [`RingBufferTraceHandler::read`].
+enum SlotState {
+    /// The record
+    Written(TraceRecord),
+    /// A newer record: this one was dropped
+    Overwritten,
+    /// An older record or nothing: the writer has reserved the sequence
+    /// number but not filled the slot yet
+    Pending,
+}
+
+impl Trace for RingBufferTraceHandler {
+    fn log(&self, level: TraceLevel, message: &str) {
+        self.log_record(&TraceRecord::new(level, DEFAULT_SOURCE, message));
+    }
+
+    fn log_record(&self, record: &TraceRecord) {
+        let seq = self.buffer.next_seq.fetch_add(1, Ordering::AcqRel);
+        let index = (seq % self.capacity() as u64) as usize;
+        let mut slot = lock(&self.buffer.slots[index]);
+        // A slower writer of an older lap must not overwrite a newer record
+        if slot.as_ref().is_none_or(|(slot_seq, _)| *slot_seq < seq) {
+            *slot = Some((seq, record.clone()));
+        }
+    }
+}
+
+impl TraceHandler for RingBufferTraceHandler {
+    fn describe(&self) -> String {
+        format!("recent records ({})", self.capacity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn messages(records: &[TraceRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_keeps_last_records_in_order() {
+        let buffer = RingBufferTraceHandler::new(3);
+        for i in 0..5 {
+            buffer.log(TraceLevel::Info, &format!("m{}", i));
+        }
+
+        assert_eq!(messages(&buffer.recent(None, 10)), vec!["m2", "m3", "m4"]);
+        assert_eq!(messages(&buffer.recent(None, 2)), vec!["m3", "m4"]);
+    }
+
+    #[test]
+    fn test_level_filter() {
+        let buffer = RingBufferTraceHandler::new(10);
+        buffer.log(TraceLevel::Debug, "debug");
+        buffer.log(TraceLevel::Error, "error");
+        buffer.log(TraceLevel::Warning, "warning");
+        buffer.log(TraceLevel::Info, "info");
+
+        let records = buffer.recent(Some(TraceLevel::Warning), 10);
+        assert_eq!(messages(&records), vec!["error", "warning"]);
+    }
+
+    #[test]
+    fn test_predicate() {
+        let buffer = RingBufferTraceHandler::new(10);
+        for i in 0..6 {
+            buffer.log(TraceLevel::Info, &format!("m{}", i));
+        }
+
+        let even = buffer.recent_matching(2, |r| r.message.ends_with(['0', '2', '4']));
+        assert_eq!(messages(&even), vec!["m2", "m4"]);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let buffer = RingBufferTraceHandler::new(10);
+        buffer.log(TraceLevel::Info, "m0");
+        let (records, cursor) = buffer.recent_after(None, 10, |_| true);
+        assert_eq!(messages(&records), vec!["m0"]);
+
+        buffer.log(TraceLevel::Info, "m1");
+        buffer.log(TraceLevel::Info, "m2");
+        let (records, cursor) = buffer.recent_after(Some(cursor), 10, |_| true);
+        assert_eq!(messages(&records), vec!["m1", "m2"]);
+        let (records, _) = buffer.recent_after(Some(cursor), 10, |_| true);
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn test_cursor_waits_for_pending_records() {
+        let buffer = RingBufferTraceHandler::new(10);
+        buffer.log(TraceLevel::Info, "m0");
+        // A writer reserved seq 1 but has not filled its slot yet
+        let pending = buffer.buffer.next_seq.fetch_add(1, Ordering::AcqRel);
+        buffer.log(TraceLevel::Info, "m2");
+
+        let (records, cursor) = buffer.recent_after(Some(0), 10, |_| true);
+        assert_eq!(messages(&records), vec!["m0"]);
+        assert_eq!(cursor, pending);
+        let (records, first) = buffer.recent_after(None, 10, |_| true);
+        assert_eq!(messages(&records), vec!["m0"]);
+        assert_eq!(first, pending);
+
+        *buffer.buffer.slots[pending as usize].lock().unwrap() =
+            Some((pending, TraceRecord::new(TraceLevel::Info, "app", "m1")));
+        let (records, cursor) = buffer.recent_after(Some(cursor), 10, |_| true);
+        assert_eq!(messages(&records), vec!["m1", "m2"]);
+        assert_eq!(cursor, 3);
+    }
+
+    #[test]
+    fn test_cursor_limit() {
+        let buffer = RingBufferTraceHandler::new(10);
+        for i in 0..5 {
+            buffer.log(TraceLevel::Info, &format!("m{}", i));
+        }
+
+        // The records beyond the limit come with the next call
+        let (records, cursor) = buffer.recent_after(Some(0), 3, |_| true);
+        assert_eq!(messages(&records), vec!["m0", "m1", "m2"]);
+        let (records, cursor) = buffer.recent_after(Some(cursor), 3, |_| true);
+        assert_eq!(messages(&records), vec!["m3", "m4"]);
+        assert_eq!(cursor, 5);
+    }
+
+    #[test]
+    fn test_poisoned_slot() {
+        let buffer = RingBufferTraceHandler::new(2);
+        let poisoner = buffer.clone();
+        let _ = thread::spawn(move || {
+            let _slot = poisoner.buffer.slots[0].lock().unwrap();
+            panic!("poisoning the slot");
+        })
+        .join();
+        assert!(buffer.buffer.slots[0].is_poisoned());
+
+        // Records still go through the slot a panic left locked
+        buffer.log(TraceLevel::Info, "m0");
+        buffer.log(TraceLevel::Info, "m1");
+        buffer.log(TraceLevel::Info, "m2");
+        assert_eq!(messages(&buffer.recent(None, 10)), vec!["m1", "m2"]);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let buffer = RingBufferTraceHandler::new(64);
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let buffer = buffer.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        buffer.log(TraceLevel::Info, &format!("{}-{}", t, i));
+                    }
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|w| w.join().unwrap());
+
+        let records = buffer.recent(None, 1000);
+        assert_eq!(records.len(), 64);
+        // Records of each writer come out in the order they were logged
+        for t in 0..4 {
+            let prefix = format!("{}-", t);
+            let indexes: Vec<u32> = records
+                .iter()
+                .filter_map(|r| r.message.strip_prefix(&prefix)?.parse().ok())
+                .collect();
+            assert!(indexes.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use super::TraceStage;
+use super::log_metrics::{MAX_SERIES, OVERFLOW_LABEL};
+use crate::metrics::MetricsRegistry;
+use crate::trace::{TraceLevel, TraceRecord, lock};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Configuration of duplicate collapsing (`[dedup]` section).
+///
+/// ```toml
+/// [dedup]
+/// window_secs = 30
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedupConfig {
+    /// Longest time a run of identical records is held before its summary
+    pub window_secs: u64,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self { window_secs: 30 }
+    }
+}
+
+/// Run of identical records of one source.
+struct Run {
+    /// Level of the repeated record
+    level: TraceLevel,
+    /// Message of the repeated record
+    message: String,
+    /// Copies collapsed since the record was let through
+    repeated: u64,
+    /// When the record was let through
+    started: Instant,
+    /// `dedup_collapsed_total{source=...}`, created on the first collapse
+    collapsed_total: Option<Arc<AtomicU64>>,
+}
+
+impl Run {
+    /// Returns true if `record` repeats this run.
+    fn matches(&self, record: &TraceRecord) -> bool {
+        self.level == record.level && self.message == record.message
+    }
+
+    /// Builds the "last message repeated N times" record, if anything was collapsed.
+    fn summary(&self, source: &str) -> Option<TraceRecord> {
+        (self.repeated > 0).then(|| {
+            let message = format!("last message repeated {} times", self.repeated);
+            TraceRecord::new(self.level, source, &message)
+        })
+    }
+}
+
+/// Stage collapsing identical consecutive records, as syslogd does.
+///
+/// The first record of a run goes through; the following copies from the
+/// same source (same level and message) are counted instead. When the run
+/// ends (the source logs something else) or `window_secs` have elapsed since
+/// its first record, a single summary is emitted with the source and level
+/// of the repeated record:
+///
+/// ```text
+/// [ERROR] payments - connection refused
+/// [ERROR] payments - last message repeated 2841 times
+/// ```
+///
+/// Collapsed records are also counted in the
+/// `dedup_collapsed_total{source="..."}` metric. Only the sources that had
+/// something collapsed get a series, and past `MAX_SERIES` sources the
+/// counts go to the `source="_other"` series.
+pub struct DedupStage {
+    window: Duration,
+    metrics: Arc<MetricsRegistry>,
+    /// Current run of each source
+    runs: Mutex<HashMap<String, Run>>,
+    /// Collapsed counter of each source, by source
+    counters: Mutex<HashMap<String, Arc<AtomicU64>>>,
+}
+
+impl DedupStage {
+    /// Creates the stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Collapsing window
+    /// * `metrics` - Registry receiving the collapsed counters
+    pub fn new(config: DedupConfig, metrics: Arc<MetricsRegistry>) -> Self {
+        Self {
+            window: Duration::from_secs(config.window_secs),
+            metrics,
+            runs: Mutex::new(HashMap::new()),
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the collapsed counter of `source`, registering it on first use.
+    fn collapsed_total(&self, source: &str) -> Arc<AtomicU64> {
+        let mut counters = lock(&self.counters);
+        if let Some(found) = counters.get(source) {
+            return found.clone();
+        }
+        let label = if counters.len() < MAX_SERIES {
+            source
+        } else {
+            OVERFLOW_LABEL
+        };
+        let created = self
+            .metrics
+            .counter("dedup_collapsed_total", &[("source", label)]);
+        counters.insert(source.to_string(), created.clone());
+        created
+    }
+}
+
+impl TraceStage for DedupStage {
+    fn process(&self, record: TraceRecord, emit: &mut dyn FnMut(TraceRecord)) {
+        let now = Instant::now();
+        let summary = {
+            let mut runs = lock(&self.runs);
+            if let Some(run) = runs.get_mut(&record.source)
+                && run.matches(&record)
+                && now.saturating_duration_since(run.started) < self.window
+            {
+                run.repeated += 1;
+                run.collapsed_total
+                    .get_or_insert_with(|| self.collapsed_total(&record.source))
+                    .fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+
+            let previous = runs.insert(
+                record.source.clone(),
+                Run {
+                    level: record.level,
+                    message: record.message.clone(),
+                    repeated: 0,
+                    started: now,
+                    collapsed_total: None,
+                },
+            );
+            previous.and_then(|run| run.summary(&record.source))
+        };
+
+        // The summary closes the previous run, so it comes first
+        if let Some(summary) = summary {
+            emit(summary);
+        }
+        emit(record);
+    }
+
+    fn tick(&self, now: Instant, emit: &mut dyn FnMut(TraceRecord)) {
+        let mut summaries = Vec::new();
+        lock(&self.runs).retain(|source, run| {
+            if now.saturating_duration_since(run.started) < self.window {
+                return true;
+            }
+            summaries.extend(run.summary(source));
+            false
+        });
+        // Emit outside the lock: the next stages may take their own locks
+        summaries.into_iter().for_each(emit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(window_secs: u64) -> (DedupStage, Arc<MetricsRegistry>) {
+        let metrics = Arc::new(MetricsRegistry::new());
+        let stage = DedupStage::new(DedupConfig { window_secs }, metrics.clone());
+        (stage, metrics)
+    }
+
+    fn send(stage: &DedupStage, out: &mut Vec<String>, source: &str, message: &str) {
+        stage.process(
+            TraceRecord::new(TraceLevel::Error, source, message),
+            &mut |r| out.push(format!("{} - {}", r.source, r.message)),
+        );
+    }
+
+    #[test]
+    fn test_run_collapsed_until_message_changes() {
+        let (stage, metrics) = stage(60);
+        let mut out = Vec::new();
+
+        for _ in 0..5 {
+            send(&stage, &mut out, "db", "connection refused");
+        }
+        // Another source doesn't end the run
+        send(&stage, &mut out, "web", "connection refused");
+        send(&stage, &mut out, "db", "connected");
+
+        assert_eq!(
+            out,
+            vec![
+                "db - connection refused",
+                "web - connection refused",
+                "db - last message repeated 4 times",
+                "db - connected",
+            ]
+        );
+        let collapsed = metrics.counter("dedup_collapsed_total", &[("source", "db")]);
+        assert_eq!(collapsed.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_summary_on_window_expiry() {
+        let (stage, _) = stage(30);
+        let mut out = Vec::new();
+        send(&stage, &mut out, "db", "timeout");
+        send(&stage, &mut out, "db", "timeout");
+        send(&stage, &mut out, "web", "single");
+
+        // Window not elapsed yet
+        stage.tick(Instant::now(), &mut |r| out.push(r.message));
+        assert_eq!(out.len(), 2);
+
+        let mut summaries = Vec::new();
+        stage.tick(Instant::now() + Duration::from_secs(31), &mut |r| {
+            summaries.push(r)
+        });
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].level, TraceLevel::Error);
+        assert_eq!(summaries[0].message, "last message repeated 1 times");
+
+        // The next copy starts a new run and goes through
+        send(&stage, &mut out, "db", "timeout");
+        assert_eq!(out.last().unwrap(), "db - timeout");
+    }
+
+    #[test]
+    fn test_collapsed_series_are_capped() {
+        let (stage, metrics) = stage(60);
+        let mut out = Vec::new();
+
+        // A source without repeats gets no series
+        send(&stage, &mut out, "single", "once");
+        assert!(!metrics.render_prometheus().contains("source=\"single\""));
+
+        for id in 0..MAX_SERIES + 5 {
+            let source = format!("job-{}", id);
+            send(&stage, &mut out, &source, "failed");
+            send(&stage, &mut out, &source, "failed");
+        }
+        let other = metrics.counter("dedup_collapsed_total", &[("source", OVERFLOW_LABEL)]);
+        assert_eq!(other.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,469 @@
+use super::TraceStage;
+use crate::metrics::{Histogram, MetricsRegistry};
+use crate::trace::{FieldFilter, TraceLevel, TraceRecord, lock};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Label value used once a rule reached [`MAX_SERIES`].
+pub(super) const OVERFLOW_LABEL: &str = "_other";
+
+/// Maximum number of label combinations per rule: labels taken from capture
+/// groups must not let a noisy message create an unbounded number of series.
+pub(super) const MAX_SERIES: usize = 1000;
+
+/// Kind of metric fed by a [`LogMetricConfig`] rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogMetricKind {
+    /// Counts the matching records
+    Counter,
+    /// Observes a number extracted from the matching records
+    Histogram,
+}
+
+/// A metric derived from the records (`[[log_metrics]]` entry).
+///
+/// Every criterion that is set must match, as for routing rules.
+///
+/// ```toml
+/// [[log_metrics]]
+/// name = "connection_refused_total"
+/// type = "counter"
+/// pattern = "connection refused"
+/// sources = ["billing"]
+///
+/// [[log_metrics]]
+/// name = "query_duration_ms"
+/// type = "histogram"
+/// pattern = "query on (?P<table>\\w+) took (?P<value>[0-9.]+)ms"
+/// min_level = "info"
+/// labels = ["source", "table"]
+/// buckets = [10, 50, 100, 500, 1000]
+///
+/// # Fields extracted by a parser
+/// [[log_metrics]]
+/// name = "http_server_errors_total"
+/// type = "counter"
+/// fields = { status = "^5" }
+/// labels = ["method"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogMetricConfig {
+    /// Metric name, exported as is
+    pub name: String,
+    /// Counter or histogram
+    #[serde(rename = "type")]
+    pub kind: LogMetricKind,
+    /// Regular expression the message must match (any message when absent)
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Conditions on the record fields (field name → regular expression)
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    /// Sources counted (any source when empty)
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Minimum level counted
+    #[serde(default)]
+    pub min_level: Option<TraceLevel>,
+    /// Labels of the metric: `source`, `level`, the name of a capture group
+    /// or else of a record field
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Capture group, or else record field, holding the observed number
+    /// (histograms only)
+    #[serde(default = "default_value_group")]
+    pub value: String,
+    /// Upper bounds of the histogram buckets
+    #[serde(default = "default_buckets")]
+    pub buckets: Vec<f64>,
+}
+
+fn default_value_group() -> String {
+    "value".to_string()
+}
+
+fn default_buckets() -> Vec<f64> {
+    vec![
+        1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+    ]
+}
+
+/// Returns `true` if `name` is a valid Prometheus metric (`colon = true`) or
+/// label name.
+fn valid_name(name: &str, colon: bool) -> bool {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '_' || (colon && c == ':');
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(allowed)
+        && !name.starts_with("__")
+}
+
+/// A metric series fed by a rule.
+#[derive(Clone)]
+enum Series {
+    Counter(Arc<AtomicU64>),
+    Histogram(Arc<Histogram>),
+}
+
+/// A compiled rule.
+struct Rule {
+    config: LogMetricConfig,
+    /// The pattern; an empty one when absent, which matches every message
+    regex: Regex,
+    fields: FieldFilter,
+    /// Series already used, by label values
+    series: Mutex<HashMap<Vec<String>, Series>>,
+}
+
+impl Rule {
+    fn compile(config: &LogMetricConfig) -> Result<Self, Error> {
+        let invalid = |message: String| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid log metric '{}': {}", config.name, message),
+            )
+        };
+
+        if !valid_name(&config.name, true) {
+            return Err(invalid("not a valid metric name".to_string()));
+        }
+        let regex = Regex::new(config.pattern.as_deref().unwrap_or(""))
+            .map_err(|e| invalid(e.to_string()))?;
+        let fields = FieldFilter::new(&config.fields).map_err(|e| invalid(e.to_string()))?;
+
+        for label in &config.labels {
+            if !valid_name(label, false) || label == "le" {
+                return Err(invalid(format!("'{}' is not a valid label name", label)));
+            }
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            regex,
+            fields,
+            series: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the captures of the message if the record matches the rule.
+    fn matches<'r>(&self, record: &'r TraceRecord) -> Option<Captures<'r>> {
+        let selected = (self.config.sources.is_empty()
+            || self.config.sources.contains(&record.source))
+            && self
+                .config
+                .min_level
+                .is_none_or(|level| record.level >= level)
+            && self.fields.matches(record);
+        if !selected {
+            return None;
+        }
+        self.regex.captures(&record.message)
+    }
+
+    /// Returns the value of a capture group, or else of a record field.
+    fn extract<'r>(
+        &self,
+        name: &str,
+        record: &'r TraceRecord,
+        captures: &Captures<'r>,
+    ) -> Option<&'r str> {
+        match captures.name(name) {
+            Some(group) => Some(group.as_str()),
+            None => record.fields.get(name).map(String::as_str),
+        }
+    }
+
+    /// Returns the series for the label values of a matching record.
+    fn series(
+        &self,
+        record: &TraceRecord,
+        captures: &Captures,
+        metrics: &MetricsRegistry,
+    ) -> Series {
+        let values: Vec<String> = self
+            .config
+            .labels
+            .iter()
+            .map(|label| match label.as_str() {
+                "source" => record.source.clone(),
+                "level" => record.level.as_str().to_lowercase(),
+                name => self
+                    .extract(name, record, captures)
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+            .collect();
+
+        let mut series = lock(&self.series);
+        if let Some(found) = series.get(&values) {
+            return found.clone();
+        }
+        let values = if series.len() < MAX_SERIES {
+            values
+        } else {
+            vec![OVERFLOW_LABEL.to_string(); values.len()]
+        };
+
+        let labels: Vec<(&str, &str)> = self
+            .config
+            .labels
+            .iter()
+            .map(String::as_str)
+            .zip(values.iter().map(String::as_str))
+            .collect();
+        let created = match self.config.kind {
+            LogMetricKind::Counter => Series::Counter(metrics.counter(&self.config.name, &labels)),
+            LogMetricKind::Histogram => Series::Histogram(metrics.histogram(
+                &self.config.name,
+                &labels,
+                &self.config.buckets,
+            )),
+        };
+        series.insert(values, created.clone());
+        created
+    }
+}
+
+/// Stage deriving metrics from the records that flow through it.
+///
+/// Records are never modified or dropped. Each `[[log_metrics]]` rule whose
+/// criteria match a record either increments a counter or, for histograms,
+/// observes the number captured by the `value` group or held by the `value`
+/// field (records where it is not a number are ignored). Rules can select
+/// records on the fields extracted by the parsers and take their labels from
+/// them. Metrics land in the [`MetricsRegistry`] and are
+/// exported by `/metrics`, in JSON or in the Prometheus text format.
+///
+/// Each rule keeps at most 1000 label combinations; further ones are counted
+/// with every label set to `_other`.
+pub struct LogMetricsStage {
+    rules: Vec<Rule>,
+    metrics: Arc<MetricsRegistry>,
+}
+
+impl LogMetricsStage {
+    /// Compiles the rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - The `[[log_metrics]]` entries
+    /// * `metrics` - Registry receiving the derived metrics
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` for an invalid metric or label name,
+    /// or an invalid regular expression.
+    pub fn new(rules: &[LogMetricConfig], metrics: Arc<MetricsRegistry>) -> Result<Self, Error> {
+        let rules = rules.iter().map(Rule::compile).collect::<Result<_, _>>()?;
+        Ok(Self { rules, metrics })
+    }
+}
+
+impl TraceStage for LogMetricsStage {
+    fn process(&self, record: TraceRecord, emit: &mut dyn FnMut(TraceRecord)) {
+        for rule in &self.rules {
+            let Some(captures) = rule.matches(&record) else {
+                continue;
+            };
+            match rule.series(&record, &captures, &self.metrics) {
+                Series::Counter(counter) => {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+                Series::Histogram(histogram) => {
+                    let value = rule
+                        .extract(&rule.config.value, &record, &captures)
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .filter(|value| value.is_finite());
+                    if let Some(value) = value {
+                        histogram.observe(value);
+                    }
+                }
+            }
+        }
+        emit(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, kind: LogMetricKind, pattern: &str) -> LogMetricConfig {
+        LogMetricConfig {
+            name: name.to_string(),
+            kind,
+            pattern: Some(pattern.to_string()),
+            fields: BTreeMap::new(),
+            sources: Vec::new(),
+            min_level: None,
+            labels: Vec::new(),
+            value: default_value_group(),
+            buckets: default_buckets(),
+        }
+    }
+
+    fn log(stage: &LogMetricsStage, level: TraceLevel, source: &str, message: &str) {
+        let mut passed = false;
+        stage.process(TraceRecord::new(level, source, message), &mut |_| {
+            passed = true
+        });
+        assert!(passed);
+    }
+
+    #[test]
+    fn test_counter_with_criteria() {
+        let config = LogMetricConfig {
+            sources: vec!["billing".to_string()],
+            min_level: Some(TraceLevel::Warning),
+            labels: vec!["source".to_string(), "level".to_string()],
+            ..rule(
+                "refused_total",
+                LogMetricKind::Counter,
+                "connection refused",
+            )
+        };
+        let metrics = Arc::new(MetricsRegistry::new());
+        let stage = LogMetricsStage::new(&[config], metrics.clone()).unwrap();
+
+        log(
+            &stage,
+            TraceLevel::Error,
+            "billing",
+            "db: connection refused",
+        );
+        log(
+            &stage,
+            TraceLevel::Error,
+            "billing",
+            "db: connection refused",
+        );
+        log(
+            &stage,
+            TraceLevel::Info,
+            "billing",
+            "db: connection refused",
+        );
+        log(&stage, TraceLevel::Error, "web", "db: connection refused");
+        log(&stage, TraceLevel::Error, "billing", "db: timeout");
+
+        let counter = metrics.counter(
+            "refused_total",
+            &[("source", "billing"), ("level", "error")],
+        );
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.snapshot()["counters"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_histogram_with_capture_labels() {
+        let config = LogMetricConfig {
+            labels: vec!["table".to_string()],
+            buckets: vec![10.0, 100.0],
+            ..rule(
+                "query_ms",
+                LogMetricKind::Histogram,
+                r"query on (?P<table>\w+) took (?P<value>\S+)ms",
+            )
+        };
+        let metrics = Arc::new(MetricsRegistry::new());
+        let stage = LogMetricsStage::new(&[config], metrics.clone()).unwrap();
+
+        log(&stage, TraceLevel::Info, "db", "query on users took 4.5ms");
+        log(&stage, TraceLevel::Info, "db", "query on users took 40ms");
+        log(&stage, TraceLevel::Info, "db", "query on users took NaNms");
+        log(&stage, TraceLevel::Info, "db", "query on orders took 400ms");
+
+        let users = metrics.histogram("query_ms", &[("table", "users")], &[]);
+        assert_eq!(users.count(), 2);
+        assert_eq!(users.sum(), 44.5);
+        assert_eq!(
+            users.cumulative_buckets(),
+            vec![(10.0, 1), (100.0, 2), (f64::INFINITY, 2)]
+        );
+        let orders = metrics.histogram("query_ms", &[("table", "orders")], &[]);
+        assert_eq!(orders.count(), 1);
+    }
+
+    #[test]
+    fn test_rules_on_fields() {
+        let errors = LogMetricConfig {
+            pattern: None,
+            fields: BTreeMap::from([("status".to_string(), "^5".to_string())]),
+            labels: vec!["method".to_string(), "user".to_string()],
+            ..rule("http_errors_total", LogMetricKind::Counter, "")
+        };
+        let latency = LogMetricConfig {
+            pattern: None,
+            buckets: vec![100.0],
+            ..rule("http_ms", LogMetricKind::Histogram, "")
+        };
+        let metrics = Arc::new(MetricsRegistry::new());
+        let stage = LogMetricsStage::new(&[errors, latency], metrics.clone()).unwrap();
+
+        for (status, value) in [("500", "120"), ("503", "80"), ("200", "5")] {
+            let mut record = TraceRecord::new(TraceLevel::Info, "web", "request");
+            record
+                .fields
+                .insert("status".to_string(), status.to_string());
+            record
+                .fields
+                .insert("method".to_string(), "GET".to_string());
+            record.fields.insert("value".to_string(), value.to_string());
+            stage.process(record, &mut |_| {});
+        }
+        log(&stage, TraceLevel::Info, "web", "no fields");
+
+        // Missing fields give empty label values
+        let counter = metrics.counter("http_errors_total", &[("method", "GET"), ("user", "")]);
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+        let histogram = metrics.histogram("http_ms", &[], &[]);
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.sum(), 205.0);
+    }
+
+    #[test]
+    fn test_series_limit() {
+        let config = LogMetricConfig {
+            labels: vec!["id".to_string()],
+            ..rule("ids_total", LogMetricKind::Counter, r"id=(?P<id>\d+)")
+        };
+        let metrics = Arc::new(MetricsRegistry::new());
+        let stage = LogMetricsStage::new(&[config], metrics.clone()).unwrap();
+
+        for id in 0..MAX_SERIES + 5 {
+            log(&stage, TraceLevel::Info, "app", &format!("id={}", id));
+        }
+
+        let other = metrics.counter("ids_total", &[("id", OVERFLOW_LABEL)]);
+        assert_eq!(other.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let metrics = Arc::new(MetricsRegistry::new());
+        let invalid = [
+            rule("bad name", LogMetricKind::Counter, "x"),
+            rule("broken_total", LogMetricKind::Counter, "("),
+            LogMetricConfig {
+                fields: BTreeMap::from([("status".to_string(), "(".to_string())]),
+                ..rule("errors_total", LogMetricKind::Counter, "error")
+            },
+            LogMetricConfig {
+                labels: vec!["le".to_string()],
+                ..rule("errors_total", LogMetricKind::Counter, "(?P<le>x)")
+            },
+        ];
+
+        for config in invalid {
+            let error = LogMetricsStage::new(&[config], metrics.clone())
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
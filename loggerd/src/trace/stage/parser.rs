@@ -0,0 +1,387 @@
+use super::TraceStage;
+use crate::metrics::MetricsRegistry;
+use crate::trace::{Fields, TraceLevel, TraceRecord, fields_from_json, parse_logfmt};
+use chrono::{DateTime, Local, NaiveDateTime};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Format of the messages handled by a parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParserKind {
+    /// Named captures of a regular expression become fields
+    Regex,
+    /// `key=value key2="quoted value"` pairs
+    Logfmt,
+    /// JSON objects (other messages are left alone); nested keys are dotted
+    Json,
+}
+
+/// A message parser (`[[parsers]]` entry).
+///
+/// ```toml
+/// [[parsers]]
+/// name = "nginx"
+/// type = "regex"
+/// sources = ["nginx"]
+/// pattern = '^(?P<client>\S+) .* "(?P<method>\w+) (?P<path>\S+) [^"]*" (?P<status>\d{3})'
+///
+/// [[parsers]]
+/// name = "json"
+/// type = "json"
+/// message_field = "msg"
+/// timestamp_format = "%Y-%m-%d %H:%M:%S"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParserConfig {
+    /// Parser name, used as the `parser` label of the metric
+    pub name: String,
+    /// Regex, logfmt or JSON
+    #[serde(rename = "type")]
+    pub kind: ParserKind,
+    /// Sources parsed (any source when empty)
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Regular expression with named captures (regex parsers only)
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Field replacing the level of the record
+    #[serde(default = "default_level_field")]
+    pub level_field: String,
+    /// Field replacing the timestamp of the record
+    #[serde(default = "default_timestamp_field")]
+    pub timestamp_field: String,
+    /// `strftime` format of the timestamp field (RFC 3339 when absent)
+    #[serde(default)]
+    pub timestamp_format: Option<String>,
+    /// Field replacing the message of the record
+    #[serde(default = "default_message_field")]
+    pub message_field: String,
+}
+
+fn default_level_field() -> String {
+    "level".to_string()
+}
+
+fn default_timestamp_field() -> String {
+    "timestamp".to_string()
+}
+
+fn default_message_field() -> String {
+    "message".to_string()
+}
+
+/// Parses a timestamp field, with `format` or as RFC 3339.
+///
+/// A format without time zone is read as local time.
+fn parse_timestamp(value: &str, format: Option<&str>) -> Option<DateTime<Local>> {
+    let parsed = match format {
+        None => DateTime::parse_from_rfc3339(value).ok(),
+        Some(format) => DateTime::parse_from_str(value, format).ok(),
+    };
+    if let Some(timestamp) = parsed {
+        return Some(timestamp.with_timezone(&Local));
+    }
+    NaiveDateTime::parse_from_str(value, format?)
+        .ok()?
+        .and_local_timezone(Local)
+        .earliest()
+}
+
+/// A configured parser and its counter.
+struct Parser {
+    config: ParserConfig,
+    regex: Option<Regex>,
+    /// `parsed_records_total{parser=...}`
+    parsed: Arc<AtomicU64>,
+}
+
+impl Parser {
+    /// Extracts the fields of `message`, or None if it is not in this format.
+    fn parse(&self, message: &str) -> Option<Fields> {
+        match self.config.kind {
+            ParserKind::Regex => {
+                let regex = self.regex.as_ref()?;
+                let captures = regex.captures(message)?;
+                Some(
+                    regex
+                        .capture_names()
+                        .flatten()
+                        .filter_map(|name| {
+                            let value = captures.name(name)?;
+                            Some((name.to_string(), value.as_str().to_string()))
+                        })
+                        .collect(),
+                )
+            }
+            ParserKind::Logfmt => parse_logfmt(message),
+            ParserKind::Json => {
+                let trimmed = message.trim();
+                if !trimmed.starts_with('{') {
+                    return None;
+                }
+                let object: Map<String, Value> = serde_json::from_str(trimmed).ok()?;
+                Some(fields_from_json(&object))
+            }
+        }
+    }
+
+    /// Moves the extracted fields into `record`, taking its level, timestamp
+    /// and message from the dedicated fields when they are valid.
+    fn apply(&self, record: &mut TraceRecord, mut fields: Fields) {
+        if let Some(level) = fields
+            .get(&self.config.level_field)
+            .and_then(|value| value.parse::<TraceLevel>().ok())
+        {
+            record.level = level;
+            fields.remove(&self.config.level_field);
+        }
+        if let Some(timestamp) = fields
+            .get(&self.config.timestamp_field)
+            .and_then(|value| parse_timestamp(value, self.config.timestamp_format.as_deref()))
+        {
+            record.timestamp = timestamp;
+            fields.remove(&self.config.timestamp_field);
+        }
+        if let Some(message) = fields.remove(&self.config.message_field) {
+            record.message = message;
+        }
+        record.fields.extend(fields);
+    }
+}
+
+/// Stage turning raw messages into structured fields.
+///
+/// Parsers are tried in configuration order on the records of their
+/// sources; the first one that recognizes the message fills the record's
+/// `fields` and stops the search. Messages no parser recognizes go through
+/// unchanged.
+///
+/// When the extracted fields include a valid level, timestamp or message
+/// (see `level_field`, `timestamp_field` and `message_field`), they replace
+/// those of the record. The fields can then be used by routing routes, log
+/// metric rules and `/logs/recent` queries.
+///
+/// Each parsed record is counted in the `parsed_records_total{parser="..."}`
+/// metric.
+pub struct ParserStage {
+    parsers: Vec<Parser>,
+}
+
+impl ParserStage {
+    /// Compiles the configured parsers.
+    ///
+    /// # Arguments
+    ///
+    /// * `parsers` - The `[[parsers]]` entries
+    /// * `metrics` - Registry receiving the parser counters
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if a regex parser has no valid
+    /// pattern, or another parser has one.
+    pub fn new(parsers: &[ParserConfig], metrics: &MetricsRegistry) -> Result<Self, Error> {
+        let parsers = parsers
+            .iter()
+            .map(|config| {
+                let invalid = |message: String| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid parser '{}': {}", config.name, message),
+                    )
+                };
+                let regex = match (config.kind, &config.pattern) {
+                    (ParserKind::Regex, Some(pattern)) => {
+                        Some(Regex::new(pattern).map_err(|e| invalid(e.to_string()))?)
+                    }
+                    (ParserKind::Regex, None) => {
+                        return Err(invalid("regex parsers need a pattern".to_string()));
+                    }
+                    (_, Some(_)) => {
+                        return Err(invalid("only regex parsers take a pattern".to_string()));
+                    }
+                    (_, None) => None,
+                };
+
+                Ok(Parser {
+                    config: config.clone(),
+                    regex,
+                    parsed: metrics.counter("parsed_records_total", &[("parser", &config.name)]),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self { parsers })
+    }
+}
+
+impl TraceStage for ParserStage {
+    fn process(&self, mut record: TraceRecord, emit: &mut dyn FnMut(TraceRecord)) {
+        let found = self
+            .parsers
+            .iter()
+            .filter(|parser| {
+                parser.config.sources.is_empty() || parser.config.sources.contains(&record.source)
+            })
+            .find_map(|parser| Some((parser, parser.parse(&record.message)?)));
+
+        if let Some((parser, fields)) = found {
+            parser.apply(&mut record, fields);
+            parser.parsed.fetch_add(1, Ordering::Relaxed);
+        }
+        emit(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Timelike};
+
+    fn parser(name: &str, kind: ParserKind, pattern: Option<&str>) -> ParserConfig {
+        ParserConfig {
+            name: name.to_string(),
+            kind,
+            sources: Vec::new(),
+            pattern: pattern.map(str::to_string),
+            level_field: default_level_field(),
+            timestamp_field: default_timestamp_field(),
+            timestamp_format: None,
+            message_field: default_message_field(),
+        }
+    }
+
+    fn parse(stage: &ParserStage, source: &str, message: &str) -> TraceRecord {
+        let mut out = None;
+        stage.process(
+            TraceRecord::new(TraceLevel::Info, source, message),
+            &mut |r| out = Some(r),
+        );
+        out.unwrap()
+    }
+
+    fn field<'r>(record: &'r TraceRecord, name: &str) -> Option<&'r str> {
+        record.fields.get(name).map(String::as_str)
+    }
+
+    #[test]
+    fn test_regex_parser() {
+        let config = ParserConfig {
+            sources: vec!["nginx".to_string()],
+            ..parser(
+                "nginx",
+                ParserKind::Regex,
+                Some(r#""(?P<method>\w+) (?P<path>\S+)" (?P<status>\d{3})(?: (?P<user>\S+))?"#),
+            )
+        };
+        let metrics = MetricsRegistry::new();
+        let stage = ParserStage::new(&[config], &metrics).unwrap();
+
+        let record = parse(&stage, "nginx", r#"10.0.0.1 "GET /index" 404"#);
+        assert_eq!(field(&record, "method"), Some("GET"));
+        assert_eq!(field(&record, "status"), Some("404"));
+        // Groups that didn't take part in the match are not fields
+        assert_eq!(field(&record, "user"), None);
+        assert_eq!(record.message, r#"10.0.0.1 "GET /index" 404"#);
+
+        // Other sources and non-matching messages are left alone
+        assert!(
+            parse(&stage, "app", r#""GET /index" 404"#)
+                .fields
+                .is_empty()
+        );
+        assert!(parse(&stage, "nginx", "started").fields.is_empty());
+        let parsed = metrics.counter("parsed_records_total", &[("parser", "nginx")]);
+        assert_eq!(parsed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_logfmt_parser_overrides_level_and_message() {
+        let stage = ParserStage::new(
+            &[parser("logfmt", ParserKind::Logfmt, None)],
+            &MetricsRegistry::new(),
+        )
+        .unwrap();
+
+        let record = parse(
+            &stage,
+            "app",
+            r#"level=warn message="disk \"almost\" full" free_mb=120 empty="#,
+        );
+        assert_eq!(record.level, TraceLevel::Warning);
+        assert_eq!(record.message, r#"disk "almost" full"#);
+        assert_eq!(field(&record, "free_mb"), Some("120"));
+        assert_eq!(field(&record, "empty"), Some(""));
+        assert_eq!(field(&record, "level"), None);
+
+        // Plain sentences are not logfmt
+        let record = parse(&stage, "app", "user=bob logged in");
+        assert!(record.fields.is_empty());
+        assert_eq!(record.message, "user=bob logged in");
+        // An invalid level stays a field
+        let record = parse(&stage, "app", "level=loud");
+        assert_eq!(record.level, TraceLevel::Info);
+        assert_eq!(field(&record, "level"), Some("loud"));
+    }
+
+    #[test]
+    fn test_json_parser_with_timestamp() {
+        let config = ParserConfig {
+            message_field: "msg".to_string(),
+            timestamp_field: "time".to_string(),
+            timestamp_format: Some("%Y-%m-%d %H:%M:%S".to_string()),
+            ..parser("json", ParserKind::Json, None)
+        };
+        let stage = ParserStage::new(&[config], &MetricsRegistry::new()).unwrap();
+
+        let record = parse(
+            &stage,
+            "app",
+            r#"{"time":"2025-03-04 05:06:07","level":"ERROR","msg":"boom","http":{"status":500},"ok":false,"none":null}"#,
+        );
+        assert_eq!(record.level, TraceLevel::Error);
+        assert_eq!(record.message, "boom");
+        assert_eq!(
+            (record.timestamp.year(), record.timestamp.hour()),
+            (2025, 5)
+        );
+        assert_eq!(field(&record, "http.status"), Some("500"));
+        assert_eq!(field(&record, "ok"), Some("false"));
+        assert_eq!(field(&record, "none"), None);
+
+        assert!(parse(&stage, "app", "not json").fields.is_empty());
+    }
+
+    #[test]
+    fn test_first_matching_parser_wins() {
+        let stage = ParserStage::new(
+            &[
+                parser("json", ParserKind::Json, None),
+                parser("logfmt", ParserKind::Logfmt, None),
+            ],
+            &MetricsRegistry::new(),
+        )
+        .unwrap();
+
+        assert_eq!(field(&parse(&stage, "app", r#"{"a":"1"}"#), "a"), Some("1"));
+        assert_eq!(field(&parse(&stage, "app", "a=2"), "a"), Some("2"));
+    }
+
+    #[test]
+    fn test_invalid_parsers() {
+        let metrics = MetricsRegistry::new();
+        for config in [
+            parser("missing", ParserKind::Regex, None),
+            parser("broken", ParserKind::Regex, Some("(")),
+            parser("extra", ParserKind::Json, Some(".*")),
+        ] {
+            let error = ParserStage::new(&[config], &metrics).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
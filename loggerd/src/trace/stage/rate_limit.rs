@@ -0,0 +1,358 @@
+use super::TraceStage;
+use super::log_metrics::{MAX_SERIES, OVERFLOW_LABEL};
+use crate::metrics::MetricsRegistry;
+use crate::trace::record::DEFAULT_SOURCE;
+use crate::trace::{TraceLevel, TraceRecord, lock};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Configuration of per-source rate limiting (`[rate_limit]` section).
+///
+/// ```toml
+/// [rate_limit]
+/// records_per_second = 100.0
+/// burst = 200
+/// summary_interval_secs = 60
+/// exempt_sources = ["loggerd"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Sustained rate allowed for each source
+    pub records_per_second: f64,
+    /// Number of records a source may emit at once before being limited
+    pub burst: u32,
+    /// Interval between "suppressed N messages" summary records
+    pub summary_interval_secs: u64,
+    /// Sources never limited (the daemon's own records by default)
+    pub exempt_sources: Vec<String>,
+}
+
+impl RateLimitConfig {
+    /// Checks that the rate and the burst let records through.
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.records_per_second.is_finite() && self.records_per_second > 0.0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "rate limit records_per_second must be greater than 0",
+            ));
+        }
+        if self.burst == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "rate limit burst must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            records_per_second: 100.0,
+            burst: 200,
+            summary_interval_secs: 60,
+            exempt_sources: vec![DEFAULT_SOURCE.to_string()],
+        }
+    }
+}
+
+/// Token bucket of one source.
+struct Bucket {
+    /// Available tokens (one token = one record)
+    tokens: f64,
+    /// Last time tokens were added
+    last_refill: Instant,
+    /// Records suppressed since the last summary
+    suppressed: u64,
+    /// `rate_limit_suppressed_total{source=...}`, created on the first suppression
+    suppressed_total: Option<Arc<AtomicU64>>,
+}
+
+impl Bucket {
+    /// Refills the bucket and takes one token if available.
+    fn try_acquire(&mut self, now: Instant, rate: f64, burst: f64) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Mutable state shared by `process` and `tick`.
+struct RateLimitState {
+    buckets: HashMap<String, Bucket>,
+    last_summary: Instant,
+    /// Suppressed counter of each source, by source
+    counters: HashMap<String, Arc<AtomicU64>>,
+}
+
+/// Stage limiting how fast each source may log.
+///
+/// Every source gets its own token bucket holding up to `burst` tokens and
+/// refilled at `records_per_second`. A record consumes one token; when the
+/// bucket is empty the record is suppressed and counted. Every
+/// `summary_interval_secs`, one Warning record per affected source reports
+/// what was dropped:
+///
+/// ```text
+/// [WARNING] loggerd - suppressed 4210 messages from billing (rate limit 100/s, burst 200)
+/// ```
+///
+/// Suppressed records are also counted in the
+/// `rate_limit_suppressed_total{source="..."}` metric. Only the sources that
+/// had something suppressed get a series, and past `MAX_SERIES` sources the
+/// counts go to the `source="_other"` series.
+pub struct RateLimitStage {
+    config: RateLimitConfig,
+    metrics: Arc<MetricsRegistry>,
+    state: Mutex<RateLimitState>,
+}
+
+impl RateLimitStage {
+    /// Creates the stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Rate and burst applied to each source
+    /// * `metrics` - Registry receiving the suppression counters
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if `records_per_second` is not
+    /// positive or `burst` is 0, which would suppress every record.
+    pub fn new(config: RateLimitConfig, metrics: Arc<MetricsRegistry>) -> Result<Self, Error> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            metrics,
+            state: Mutex::new(RateLimitState {
+                buckets: HashMap::new(),
+                last_summary: Instant::now(),
+                counters: HashMap::new(),
+            }),
+        })
+    }
+
+    /// Returns the suppressed counter of `source`, registering it on first use.
+    fn suppressed_total(
+        &self,
+        counters: &mut HashMap<String, Arc<AtomicU64>>,
+        source: &str,
+    ) -> Arc<AtomicU64> {
+        if let Some(found) = counters.get(source) {
+            return found.clone();
+        }
+        let label = if counters.len() < MAX_SERIES {
+            source
+        } else {
+            OVERFLOW_LABEL
+        };
+        let created = self
+            .metrics
+            .counter("rate_limit_suppressed_total", &[("source", label)]);
+        counters.insert(source.to_string(), created.clone());
+        created
+    }
+
+    /// Emits the summaries and forgets idle sources.
+    fn summarize(&self, state: &mut RateLimitState, emit: &mut dyn FnMut(TraceRecord)) {
+        let burst = f64::from(self.config.burst);
+        for (source, bucket) in state.buckets.iter_mut() {
+            if bucket.suppressed == 0 {
+                continue;
+            }
+            let message = format!(
+                "suppressed {} messages from {} (rate limit {}/s, burst {})",
+                bucket.suppressed, source, self.config.records_per_second, self.config.burst
+            );
+            emit(TraceRecord::new(
+                TraceLevel::Warning,
+                DEFAULT_SOURCE,
+                &message,
+            ));
+            bucket.suppressed = 0;
+        }
+
+        // A full bucket behaves exactly like a new one
+        let now = Instant::now();
+        let rate = self.config.records_per_second;
+        state.buckets.retain(|_, bucket| {
+            let elapsed = now
+                .saturating_duration_since(bucket.last_refill)
+                .as_secs_f64();
+            bucket.tokens + elapsed * rate < burst
+        });
+    }
+}
+
+impl TraceStage for RateLimitStage {
+    fn process(&self, record: TraceRecord, emit: &mut dyn FnMut(TraceRecord)) {
+        if self.config.exempt_sources.contains(&record.source) {
+            emit(record);
+            return;
+        }
+
+        let allowed = {
+            let mut state = lock(&self.state);
+            let RateLimitState {
+                buckets, counters, ..
+            } = &mut *state;
+            let burst = f64::from(self.config.burst);
+            let bucket = buckets
+                .entry(record.source.clone())
+                .or_insert_with(|| Bucket {
+                    tokens: burst,
+                    last_refill: Instant::now(),
+                    suppressed: 0,
+                    suppressed_total: None,
+                });
+
+            let allowed = bucket.try_acquire(Instant::now(), self.config.records_per_second, burst);
+            if !allowed {
+                bucket.suppressed += 1;
+                bucket
+                    .suppressed_total
+                    .get_or_insert_with(|| self.suppressed_total(counters, &record.source))
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            allowed
+        };
+
+        if allowed {
+            emit(record);
+        }
+    }
+
+    fn tick(&self, now: Instant, emit: &mut dyn FnMut(TraceRecord)) {
+        let interval = Duration::from_secs(self.config.summary_interval_secs);
+        let mut summaries = Vec::new();
+        {
+            let mut state = lock(&self.state);
+            if now.saturating_duration_since(state.last_summary) < interval {
+                return;
+            }
+            state.last_summary = now;
+            self.summarize(&mut state, &mut |record| summaries.push(record));
+        }
+        // Emit outside the lock: the next stages may take their own locks
+        summaries.into_iter().for_each(emit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(rate: f64, burst: u32) -> (RateLimitStage, Arc<MetricsRegistry>) {
+        let metrics = Arc::new(MetricsRegistry::new());
+        let config = RateLimitConfig {
+            records_per_second: rate,
+            burst,
+            summary_interval_secs: 0,
+            ..RateLimitConfig::default()
+        };
+        (
+            RateLimitStage::new(config, metrics.clone()).unwrap(),
+            metrics,
+        )
+    }
+
+    fn send(stage: &RateLimitStage, source: &str, count: usize) -> usize {
+        let mut passed = 0;
+        for _ in 0..count {
+            stage.process(
+                TraceRecord::new(TraceLevel::Info, source, "spam"),
+                &mut |_| passed += 1,
+            );
+        }
+        passed
+    }
+
+    #[test]
+    fn test_burst_then_suppression_per_source() {
+        let (stage, metrics) = stage(0.001, 5);
+
+        assert_eq!(send(&stage, "noisy", 50), 5);
+        // Other sources have their own bucket
+        assert_eq!(send(&stage, "quiet", 3), 3);
+        // The daemon's own records are exempt
+        assert_eq!(send(&stage, DEFAULT_SOURCE, 50), 50);
+
+        let suppressed = metrics.counter("rate_limit_suppressed_total", &[("source", "noisy")]);
+        assert_eq!(suppressed.load(Ordering::Relaxed), 45);
+        // Sources that were never limited get no series
+        assert!(!metrics.render_prometheus().contains("source=\"quiet\""));
+    }
+
+    #[test]
+    fn test_suppressed_series_are_capped() {
+        let (stage, metrics) = stage(0.001, 1);
+        for id in 0..MAX_SERIES + 5 {
+            send(&stage, &format!("job-{}", id), 2);
+        }
+        let other = metrics.counter("rate_limit_suppressed_total", &[("source", OVERFLOW_LABEL)]);
+        assert_eq!(other.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let metrics = Arc::new(MetricsRegistry::new());
+        for (records_per_second, burst) in [(100.0, 0), (0.0, 10), (-1.0, 10), (f64::NAN, 10)] {
+            let config = RateLimitConfig {
+                records_per_second,
+                burst,
+                ..RateLimitConfig::default()
+            };
+            let err = RateLimitStage::new(config, metrics.clone()).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_tokens_refill_over_time() {
+        let mut bucket = Bucket {
+            tokens: 0.0,
+            last_refill: Instant::now(),
+            suppressed: 0,
+            suppressed_total: None,
+        };
+        let later = bucket.last_refill + Duration::from_millis(500);
+        assert!(bucket.try_acquire(later, 10.0, 20.0)); // 5 tokens refilled
+        assert!((bucket.tokens - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_periodic_summary() {
+        let (stage, _) = stage(0.001, 1);
+        send(&stage, "noisy", 11);
+
+        let mut summaries = Vec::new();
+        stage.tick(Instant::now(), &mut |r| summaries.push(r));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].level, TraceLevel::Warning);
+        assert!(
+            summaries[0]
+                .message
+                .starts_with("suppressed 10 messages from noisy")
+        );
+
+        // Counts are reset after each summary
+        summaries.clear();
+        stage.tick(Instant::now(), &mut |r| summaries.push(r));
+        assert!(summaries.is_empty());
+    }
+}
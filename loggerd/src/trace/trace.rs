@@ -1,14 +1,100 @@
+use super::drain::DrainReport;
 use super::handlers::TraceHandler;
 use super::level::TraceLevel;
+use super::record::TraceRecord;
+use std::fmt;
+use std::time::Instant;
 
 /// Trait for logging traces with different levels and handlers.
 pub trait Trace {
     /// Logs a message with the specified trace level.
     fn log(&self, level: TraceLevel, message: &str);
+
+    /// Logs a complete record (timestamp, source, message).
+    ///
+    /// The default implementation only keeps the level and message, which is
+    /// what handlers that don't care about the other fields need. Handlers that
+    /// persist or forward records override it.
+    fn log_record(&self, record: &TraceRecord) {
+        self.log(record.level, &record.message);
+    }
+
+    /// Returns `false` if records of `level` would be discarded, so that
+    /// callers can skip building them (the logging macros such as
+    /// [`trace_info!`](crate::trace_info) don't format their message then).
+    ///
+    /// The default implementation keeps every level.
+    fn enabled(&self, _level: TraceLevel) -> bool {
+        true
+    }
+
+    /// Writes out the records queued so far and syncs them to storage,
+    /// waiting until `deadline` at most; called once at shutdown.
+    ///
+    /// The default implementation, for handlers that don't queue records,
+    /// has nothing to drain.
+    fn drain(&self, _deadline: Instant) -> DrainReport {
+        DrainReport::default()
+    }
+}
+
+/// Identifier of a registered handler, returned by
+/// [`HandlerRegister::register`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HandlerId(pub(crate) u64);
+
+impl fmt::Display for HandlerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A registered handler, as listed by [`HandlerRegister::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerInfo {
+    /// Identifier of the registration
+    pub id: HandlerId,
+    /// Description of the handler, see [`TraceHandler::describe`]
+    pub description: String,
 }
 
 /// Trait for registering trace handlers.
+///
+/// Handlers can be removed or replaced while records are logged, e.g. to
+/// apply a new configuration. The removed handler is first drained (see
+/// [`Trace::drain`]): the records queued so far are written and synced.
+/// It is then dropped, which flushes the records dispatched meanwhile and
+/// joins its threads, once the records being dispatched to it on other
+/// threads are done. `unregister` and `replace` wait for that until
+/// `deadline` at most, so async code should call them from a blocking task.
+/// Past the deadline, or when called from a handler (whose own record keeps
+/// the removed one alive), the last record dispatched to the handler drops
+/// it instead.
 pub trait HandlerRegister {
-    /// Registers a new trace handler.
-    fn register<T: TraceHandler + 'static>(&self, handler: T);
+    /// Registers a new trace handler, after the existing ones.
+    fn register<T: TraceHandler + 'static>(&self, handler: T) -> HandlerId;
+
+    /// Removes a handler.
+    ///
+    /// Returns `None` if `id` is not registered, and the drain report of the
+    /// removed handler otherwise. The report is not clean if the drain
+    /// failed, or if records were still being dispatched to the handler at
+    /// `deadline` (it is then dropped later, by the last of them).
+    fn unregister(&self, id: HandlerId, deadline: Instant) -> Option<DrainReport>;
+
+    /// Replaces a handler by `handler`, which keeps its identifier and its
+    /// place.
+    ///
+    /// Returns the drain report of the old handler, as
+    /// [`unregister`](Self::unregister) does, or `None` (dropping `handler`)
+    /// if `id` is not registered.
+    fn replace<T: TraceHandler + 'static>(
+        &self,
+        id: HandlerId,
+        handler: T,
+        deadline: Instant,
+    ) -> Option<DrainReport>;
+
+    /// Lists the registered handlers, in dispatch order.
+    fn list(&self) -> Vec<HandlerInfo>;
 }
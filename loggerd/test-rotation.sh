@@ -1,32 +1,48 @@
 #!/bin/bash
 # Test de rotation des logs
+#
+# Lance loggerd dans un répertoire temporaire, sur son propre port
+# (LOGGERD_PORT, 18080 par défaut), avec une limite de 500 octets par fichier.
+# Fonctionne depuis n'importe quel répertoire. Les tests automatisés
+# équivalents sont dans tests/server.rs (cargo test --package loggerd).
 
 set -e
 
+PORT="${LOGGERD_PORT:-18080}"
+WORKSPACE="$(cd "$(dirname "$0")/.." && pwd)"
+WORKDIR="$(mktemp -d)"
+trap 'kill $LOGGERD_PID 2>/dev/null || true; rm -rf "$WORKDIR"' EXIT
+
 echo "🧪 Test de rotation des logs loggerd"
 echo "======================================"
 echo ""
 
-# Arrêter les instances existantes
-pkill -9 -f loggerd 2>/dev/null || true
-rm -f loggerd.log* 2>/dev/null || true
-
 echo "1️⃣ Démarrage de loggerd avec limite de 500 bytes..."
 
-# Modifier temporairement le fichier pour utiliser une petite taille
-cd /workspaces/cma-rust
+cat > "$WORKDIR/loggerd.toml" <<EOF
+[server]
+bind = "127.0.0.1:$PORT"
+
+[routing.default]
+path = "$WORKDIR/loggerd.log"
+max_size_bytes = 500
+EOF
 
-# Lancer loggerd en arrière-plan
-nohup cargo run --package loggerd > /tmp/loggerd-test.log 2>&1 &
+cargo build --quiet --package loggerd --manifest-path "$WORKSPACE/Cargo.toml"
+"$WORKSPACE/target/debug/loggerd" --config "$WORKDIR/loggerd.toml" > "$WORKDIR/stdout.log" 2>&1 &
 LOGGERD_PID=$!
-echo "   PID: $LOGGERD_PID"
+echo "   PID: $LOGGERD_PID, répertoire: $WORKDIR"
 
-sleep 3
+# Attendre que le serveur réponde
+for _ in {1..50}; do
+    curl -s "http://127.0.0.1:$PORT/health" > /dev/null && break
+    sleep 0.1
+done
 
 echo ""
 echo "2️⃣ Génération de logs via requêtes HTTP..."
 for i in {1..50}; do
-    curl -s http://localhost:8080/metrics > /dev/null
+    curl -s "http://127.0.0.1:$PORT/metrics" > /dev/null
     echo -n "."
     sleep 0.1
 done
@@ -34,35 +50,41 @@ echo " ✓"
 
 echo ""
 echo "3️⃣ Vérification des fichiers de log..."
-ls -lh loggerd.log* 2>/dev/null || echo "   Aucun fichier de backup créé (taille non atteinte)"
+ls -lh "$WORKDIR"/loggerd.log*
+BACKUPS=$(find "$WORKDIR" -name 'loggerd.log.*' | wc -l)
+if [ "$BACKUPS" -eq 0 ]; then
+    echo "   ✗ Aucun fichier de backup créé"
+    exit 1
+fi
+echo "   ✓ $BACKUPS fichier(s) de backup"
 
 echo ""
 echo "4️⃣ Contenu du fichier principal (dernières 5 lignes):"
 echo "─────────────────────────────────────────────────────────"
-tail -5 loggerd.log 2>/dev/null || echo "Fichier vide"
+tail -5 "$WORKDIR/loggerd.log" 2>/dev/null || echo "Fichier vide"
 echo "─────────────────────────────────────────────────────────"
 
 echo ""
 echo "5️⃣ Statistiques:"
-LOG_SIZE=$(stat -c%s loggerd.log 2>/dev/null || echo "0")
-LOG_LINES=$(wc -l < loggerd.log 2>/dev/null || echo "0")
+LOG_SIZE=$(stat -c%s "$WORKDIR/loggerd.log" 2>/dev/null || echo "0")
+LOG_LINES=$(wc -l < "$WORKDIR/loggerd.log" 2>/dev/null || echo "0")
 echo "   Taille du log: $LOG_SIZE bytes"
 echo "   Nombre de lignes: $LOG_LINES"
 
 echo ""
 echo "6️⃣ Métriques finales du daemon:"
-curl -s http://localhost:8080/metrics | python3 -m json.tool 2>/dev/null || curl -s http://localhost:8080/metrics
+curl -s "http://127.0.0.1:$PORT/metrics" | python3 -m json.tool 2>/dev/null || curl -s "http://127.0.0.1:$PORT/metrics"
 
 echo ""
 echo ""
 echo "7️⃣ Arrêt gracieux (SIGTERM)..."
 kill -TERM $LOGGERD_PID
-sleep 2
+wait $LOGGERD_PID
 
 echo ""
 echo "8️⃣ Logs du daemon:"
 echo "─────────────────────────────────────────────────────────"
-tail -20 loggerd.log
+tail -20 "$WORKDIR/loggerd.log"
 echo "─────────────────────────────────────────────────────────"
 
 echo ""
@@ -0,0 +1,126 @@
+//! Harness of the integration tests: a complete daemon in a temporary
+//! directory, listening on an ephemeral port.
+
+use loggerd::config::LoggerdConfig;
+use loggerd::http_client::{HttpEndpoint, HttpResponse};
+use loggerd::server::{self, RunningServer};
+use loggerd::trace::{DrainReport, Trace};
+use std::io::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+/// Timeout of the requests sent to the daemon.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Routing of the default configuration: one file in the temporary directory.
+pub const DEFAULT_ROUTING: &str = "[routing.default]\npath = \"{dir}/loggerd.log\"\n";
+
+/// A daemon started by a test, stopped when dropped.
+///
+/// The daemon runs on its own multi-threaded runtime, so the test thread can
+/// use the blocking HTTP client of the crate against it.
+pub struct TestDaemon {
+    /// Temporary directory holding the log files
+    pub dir: TempDir,
+    runtime: Runtime,
+    server: Option<RunningServer>,
+    addr: SocketAddr,
+}
+
+impl TestDaemon {
+    /// Starts a daemon with the default routing.
+    pub fn start() -> Self {
+        Self::with_config(DEFAULT_ROUTING)
+    }
+
+    /// Starts a daemon with `config`, where `{dir}` stands for the temporary
+    /// directory. The `[server]` section is provided.
+    pub fn with_config(config: &str) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let config = format!(
+            "[server]\nbind = \"127.0.0.1:0\"\n\n{}",
+            config.replace("{dir}", dir.path().to_str().unwrap())
+        );
+        let config = LoggerdConfig::parse(&config).unwrap();
+
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(server::start(config)).unwrap();
+        Self {
+            dir,
+            runtime,
+            addr: server.local_addr(),
+            server: Some(server),
+        }
+    }
+
+    /// Returns the address the daemon listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the trace system of the daemon.
+    pub fn trace(&self) -> Arc<dyn Trace + Send + Sync> {
+        self.server.as_ref().expect("daemon stopped").trace()
+    }
+
+    /// Returns the path of a file of the temporary directory.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Sends a request to the daemon.
+    pub fn request(&self, method: &str, path: &str, body: &str) -> HttpResponse {
+        let endpoint = HttpEndpoint::parse(&format!("http://{}{}", self.addr, path)).unwrap();
+        endpoint
+            .request(
+                method,
+                &[("Content-Type", "application/json")],
+                body.as_bytes(),
+                REQUEST_TIMEOUT,
+            )
+            .unwrap()
+    }
+
+    /// Sends a GET request to the daemon.
+    pub fn get(&self, path: &str) -> HttpResponse {
+        self.request("GET", path, "")
+    }
+
+    /// Sends a POST request to the daemon.
+    pub fn post(&self, path: &str, body: &str) -> HttpResponse {
+        self.request("POST", path, body)
+    }
+
+    /// Shuts the daemon down gracefully and waits until it has stopped and
+    /// drained its handlers (an empty report once stopped).
+    pub fn stop(&mut self) -> Result<DrainReport> {
+        match self.server.take() {
+            Some(server) => self.runtime.block_on(server.stop()),
+            None => Ok(DrainReport::default()),
+        }
+    }
+}
+
+impl Drop for TestDaemon {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Waits up to 5 seconds for `condition` to hold (files are written by
+/// background threads).
+pub fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    condition()
+}
@@ -0,0 +1,181 @@
+//! End-to-end tests of a running daemon: HTTP API, log files, rotation and
+//! graceful shutdown.
+
+mod common;
+
+use common::{TestDaemon, eventually};
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn json(body: &[u8]) -> Value {
+    serde_json::from_slice(body).unwrap()
+}
+
+#[test]
+fn test_health_and_metrics() {
+    let daemon = TestDaemon::start();
+
+    let health = daemon.get("/health");
+    assert_eq!(health.status, 200);
+    assert_eq!(health.text(), "OK");
+
+    let first = json(&daemon.get("/metrics").body);
+    let second = json(&daemon.get("/metrics").body);
+    assert_eq!(first["status"], "running");
+    assert!(second["requests"].as_u64() > first["requests"].as_u64());
+    assert!(second["log_count"].as_u64().unwrap() > 0);
+
+    let prometheus = daemon.get("/metrics?format=prometheus").text();
+    assert!(prometheus.contains("loggerd_requests_total "));
+}
+
+#[test]
+fn test_ingested_records_are_written_and_searchable() {
+    let mut daemon = TestDaemon::start();
+
+    let accepted = daemon.post(
+        "/logs",
+        r#"{"level": "error", "source": "billing", "message": "payment declined", "fields": {"order": "A-42"}}"#,
+    );
+    assert_eq!(accepted.status, 202);
+
+    let log = daemon.path("loggerd.log");
+    assert!(eventually(|| fs::read_to_string(&log)
+        .unwrap()
+        .contains("[ERROR] billing - payment declined {order=A-42}")));
+
+    let found = json(&daemon.get("/logs?source=billing&field.order=A-42").body);
+    assert_eq!(found["searched"], "files");
+    assert_eq!(found["records"][0]["message"], "payment declined");
+    let recent = json(&daemon.get("/logs/recent?level=error").body);
+    assert_eq!(recent["records"][0]["source"], "billing");
+
+    assert_eq!(daemon.post("/logs", "not json").status, 400);
+    daemon.stop().unwrap();
+}
+
+#[test]
+fn test_rotation() {
+    let daemon = TestDaemon::with_config(
+        "[routing.default]\npath = \"{dir}/loggerd.log\"\nmax_size_bytes = 2000\n",
+    );
+    let backups = || {
+        fs::read_dir(daemon.dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("loggerd.log.")
+            })
+            .count()
+    };
+
+    // Size-based rotation
+    for i in 0..40 {
+        let record = format!(r#"{{"source": "load", "message": "record number {}"}}"#, i);
+        assert_eq!(daemon.post("/logs", &record).status, 202);
+    }
+    assert!(eventually(|| backups() >= 1));
+    assert!(fs::metadata(daemon.path("loggerd.log")).unwrap().len() <= 2000);
+
+    // Forced rotation, seen by the file listing
+    let before = backups();
+    let rotated = json(&daemon.post("/admin/rotate", "").body);
+    assert!(rotated["errors"].as_array().unwrap().is_empty());
+    assert_eq!(backups(), before + 1);
+    let files = json(&daemon.get("/admin/files").body);
+    let files = files["files"].as_array().unwrap();
+    assert_eq!(files.len(), before + 2);
+    assert_eq!(files[0]["name"], "loggerd.log");
+    assert_eq!(files[1]["name"], rotated["rotated"][0]["backup"]);
+
+    // Records are searched across the backups
+    let found = json(&daemon.get("/logs?source=load&limit=1000").body);
+    assert_eq!(found["records"].as_array().unwrap().len(), 40);
+    assert_eq!(found["records"][0]["message"], "record number 0");
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let mut daemon = TestDaemon::start();
+    let addr = daemon.addr();
+    assert_eq!(daemon.get("/health").status, 200);
+
+    for i in 0..200 {
+        let record = format!(r#"{{"source": "load", "message": "queued {}"}}"#, i);
+        assert_eq!(daemon.post("/logs", &record).status, 202);
+    }
+    let report = daemon.stop().unwrap();
+
+    assert!(TcpStream::connect(addr).is_err());
+    assert!(report.is_clean(), "{:?}", report);
+    // Every handler is drained: the records and the last message are in the
+    // file before stop() returns
+    let log = fs::read_to_string(daemon.path("loggerd.log")).unwrap();
+    assert!(log.contains("[INFO] load - queued 199"));
+    assert!(log.trim_end().ends_with("loggerd shutdown complete"));
+    assert_eq!(daemon.stop().unwrap(), Default::default());
+}
+
+#[test]
+fn test_shutdown_does_not_wait_for_stalled_clients() {
+    let mut daemon = TestDaemon::start();
+    // A request that never completes keeps its connection busy
+    let mut stalled = TcpStream::connect(daemon.addr()).unwrap();
+    stalled.write_all(b"GET /health HTTP/1.1\r\n").unwrap();
+    assert_eq!(daemon.get("/health").status, 200);
+
+    let started = Instant::now();
+    daemon.stop().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(20));
+    let log = fs::read_to_string(daemon.path("loggerd.log")).unwrap();
+    assert!(log.contains("shutdown grace period"));
+}
+
+#[test]
+fn test_panics_are_logged() {
+    let daemon = TestDaemon::start();
+    loggerd::trace::install_panic_hook(daemon.trace());
+
+    let crashed = thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(|| panic!("worker gave up"))
+        .unwrap()
+        .join();
+    assert!(crashed.is_err());
+
+    let found = json(
+        &daemon
+            .get("/logs/recent?source=panic&field.thread=worker")
+            .body,
+    );
+    assert_eq!(found["records"][0]["level"], "CRITICAL");
+    // The backtrace, when enabled, follows on the next lines
+    let message = found["records"][0]["message"].as_str().unwrap();
+    assert!(
+        message
+            .lines()
+            .next()
+            .unwrap()
+            .ends_with(": worker gave up")
+    );
+}
+
+#[test]
+fn test_daemons_run_side_by_side() {
+    let first = TestDaemon::start();
+    let second = TestDaemon::start();
+    assert_ne!(first.addr(), second.addr());
+
+    first.post("/logs", r#"{"source": "first", "message": "only here"}"#);
+    let found = json(&second.get("/logs/recent?source=first").body);
+    assert!(found["records"].as_array().unwrap().is_empty());
+    let found = json(&first.get("/logs/recent?source=first").body);
+    assert_eq!(found["records"][0]["message"], "only here");
+}